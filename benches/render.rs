@@ -0,0 +1,122 @@
+//! Times every projection, the samplers and whole renders, comparing the
+//! scalar projections with the row paths. Only the stereographic one maps
+//! eight pixels at a time; the others loop over the scalar projection.
+//!
+//! Run with `cargo bench`; any arguments only run the benchmarks whose name
+//! contains one of them.
+
+use std::{
+    hint::black_box,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use image::{Rgba, Rgba32FImage};
+use nalgebra::{vector, SVector};
+use shuodedaoli::{
+    preset::Params,
+    projection::ProjectionKind,
+    render::{CancelToken, RenderRequest},
+    sampler::{EdgeMode, Sampler},
+};
+
+type Vec2f = SVector<f32, 2>;
+
+const WIDTH: u32 = 2048;
+
+/// Runs `f` for about a second and prints the mean time per run, and per
+/// pixel for runs over `pixels` pixels.
+fn bench(filter: &[String], name: &str, pixels: u64, mut f: impl FnMut()) {
+    if !filter.is_empty() && !filter.iter().any(|f| name.contains(f.as_str())) {
+        return;
+    }
+    f();
+    let start = Instant::now();
+    let mut runs = 0;
+    while start.elapsed() < Duration::from_secs(1) {
+        f();
+        runs += 1;
+    }
+    let run = start.elapsed() / runs;
+    let pixel = run.as_nanos() as f64 / pixels as f64;
+    println!("{name:<32} {run:>12.3?} {pixel:>8.2} ns/pixel");
+}
+
+fn panorama() -> Arc<Rgba32FImage> {
+    let img = Rgba32FImage::from_fn(WIDTH, WIDTH / 2, |x, y| {
+        let (u, v) = (x as f32 / WIDTH as f32, y as f32 / WIDTH as f32 * 2.0);
+        Rgba([u, v, (u * 40.0).sin() * 0.5 + 0.5, 1.0])
+    });
+    Arc::new(img)
+}
+
+fn main() {
+    let filter: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|a| a != "--bench")
+        .collect();
+    let image = panorama();
+    let params = Params {
+        size: (WIDTH, WIDTH),
+        ..Default::default()
+    };
+    let request = RenderRequest::new(Arc::clone(&image), &params, Sampler::Bilinear, 1);
+    let rows = (0..WIDTH).step_by(16);
+    let pixels = rows.len() as u64 * WIDTH as u64;
+
+    let kinds = ProjectionKind::ALL.map(|kind| (kind, false));
+    for (kind, inverse) in kinds
+        .into_iter()
+        .chain([(ProjectionKind::Stereographic, true)])
+    {
+        let request = RenderRequest {
+            kind,
+            inverse,
+            ..request.clone()
+        };
+        let proj = request.projection(request.size);
+        let name = if inverse { "Inverse" } else { kind.name() };
+        bench(&filter, &format!("proj/{name}/scalar"), pixels, || {
+            for y in rows.clone() {
+                for x in 0..WIDTH {
+                    black_box(proj.proj(vector![x as f32, y as f32]));
+                }
+            }
+        });
+        let mut row = vec![Vec2f::zeros(); WIDTH as usize];
+        bench(&filter, &format!("proj/{name}/row"), pixels, || {
+            for y in rows.clone() {
+                proj.proj_row(y, &mut row);
+                black_box(&row);
+            }
+        });
+    }
+
+    let proj = request.projection(request.size);
+    let mut coords = vec![Vec2f::zeros(); WIDTH as usize];
+    proj.proj_row(WIDTH / 3, &mut coords);
+    for sampler in Sampler::ALL {
+        let name = sampler.name();
+        bench(&filter, &format!("sample/{name}"), WIDTH as u64, || {
+            for p in &coords {
+                black_box(sampler.sample(&image, p.x, p.y, EdgeMode::Wrap));
+            }
+        });
+    }
+
+    let cancel = CancelToken::next(&Default::default());
+    let size = request.size;
+    let pixels = size.0 as u64 * size.1 as u64;
+    let renders = Sampler::ALL.map(|sampler| (sampler, 1));
+    for (sampler, samples) in renders.into_iter().chain([(Sampler::Bilinear, 4)]) {
+        let request = RenderRequest {
+            sampler,
+            samples,
+            ..request.clone()
+        };
+        let name = format!("render/{}/{samples}x", sampler.name());
+        bench(&filter, &name, pixels, || {
+            black_box(request.render(size, &cancel, None).unwrap());
+        });
+    }
+}
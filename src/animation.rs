@@ -0,0 +1,345 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{mpsc::Sender, Arc},
+};
+
+use image::{
+    buffer::ConvertBuffer,
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, Rgba32FImage, RgbaImage,
+};
+use nalgebra::UnitQuaternion;
+
+use crate::{
+    i18n::tr,
+    preset::{self, Params},
+    remap::RemapCache,
+    render::{CancelToken, FrameSequence, RenderRequest},
+    scene::Scene,
+};
+
+/// The parameters at one point of the timeline. Only the offset, rotation and
+/// scale are animated; everything else is taken from the first keyframe.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    /// Time in seconds.
+    pub time: f32,
+    pub params: Params,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeline {
+    /// Sorted by time.
+    keyframes: Vec<Keyframe>,
+    pub duration: f32,
+    pub fps: u32,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            keyframes: vec![],
+            duration: 4.0,
+            fps: 25,
+        }
+    }
+}
+
+impl Timeline {
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    /// Sets the keyframe at `time`, replacing one that is already there.
+    pub fn insert(&mut self, time: f32, params: Params) {
+        let key = Keyframe { time, params };
+        match self.keyframes.binary_search_by(|k| k.time.total_cmp(&time)) {
+            Ok(i) => self.keyframes[i] = key,
+            Err(i) => self.keyframes.insert(i, key),
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        self.keyframes.remove(index);
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        ((self.duration * self.fps as f32).round() as u32).max(1)
+    }
+
+    pub fn frame_time(&self, frame: u32) -> f32 {
+        frame as f32 / self.fps as f32
+    }
+
+    /// Interpolates the keyframes at `time`: linearly for the offset and the
+    /// scale, and along the shortest arc for the rotation.
+    pub fn sample(&self, time: f32) -> Option<Params> {
+        let first = self.keyframes.first()?;
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+        let (a, b) = match next {
+            0 => (first, first),
+            n if n == self.keyframes.len() => (&self.keyframes[n - 1], &self.keyframes[n - 1]),
+            n => (&self.keyframes[n - 1], &self.keyframes[n]),
+        };
+        let span = b.time - a.time;
+        let t = if span > 0.0 {
+            (time - a.time) / span
+        } else {
+            0.0
+        };
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+
+        let qa =
+            UnitQuaternion::from_rotation_matrix(&preset::rotation_from_degrees(a.params.rotation));
+        let qb =
+            UnitQuaternion::from_rotation_matrix(&preset::rotation_from_degrees(b.params.rotation));
+        let q = qa.try_slerp(&qb, t, 1e-6).unwrap_or(qa);
+        Some(Params {
+            offset: (
+                lerp(a.params.offset.0, b.params.offset.0),
+                lerp(a.params.offset.1, b.params.offset.1),
+            ),
+            rotation: preset::rotation_to_degrees(q.to_rotation_matrix()),
+            scale: lerp(a.params.scale, b.params.scale),
+            ..first.params
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+    Mp4,
+}
+
+impl AnimationFormat {
+    pub const ALL: [AnimationFormat; 3] = [
+        AnimationFormat::Gif,
+        AnimationFormat::Apng,
+        AnimationFormat::Mp4,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "GIF",
+            AnimationFormat::Apng => "APNG",
+            AnimationFormat::Mp4 => "MP4 (ffmpeg)",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "gif",
+            AnimationFormat::Apng => "png",
+            AnimationFormat::Mp4 => "mp4",
+        }
+    }
+}
+
+/// Writes frames one at a time, so that a whole animation never has to be
+/// kept in memory.
+pub enum AnimationEncoder {
+    Gif(GifEncoder<BufWriter<File>>),
+    Apng(png::Writer<BufWriter<File>>),
+    /// MP4 is encoded by piping raw frames to an `ffmpeg` process, at the
+    /// constant frame rate given on creation.
+    Mp4(Child, ChildStdin),
+}
+
+impl AnimationEncoder {
+    pub fn new(
+        path: &Path,
+        format: AnimationFormat,
+        size: (u32, u32),
+        fps: u32,
+        frames: u32,
+    ) -> io::Result<Self> {
+        match format {
+            AnimationFormat::Gif => {
+                let mut encoder =
+                    GifEncoder::new_with_speed(BufWriter::new(File::create(path)?), 10);
+                encoder
+                    .set_repeat(Repeat::Infinite)
+                    .map_err(io::Error::other)?;
+                Ok(AnimationEncoder::Gif(encoder))
+            }
+            AnimationFormat::Apng => {
+                let w = BufWriter::new(File::create(path)?);
+                let mut encoder = png::Encoder::new(w, size.0, size.1);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(frames, 0).map_err(io::Error::other)?;
+                Ok(AnimationEncoder::Apng(
+                    encoder.write_header().map_err(io::Error::other)?,
+                ))
+            }
+            AnimationFormat::Mp4 => {
+                let mut child = Command::new("ffmpeg")
+                    .args([
+                        "-y",
+                        "-loglevel",
+                        "error",
+                        "-f",
+                        "rawvideo",
+                        "-pix_fmt",
+                        "rgba",
+                    ])
+                    .args(["-s", &format!("{}x{}", size.0, size.1)])
+                    .args(["-r", &fps.to_string(), "-i", "-"])
+                    // yuv420p needs even dimensions.
+                    .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"])
+                    .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+                    .arg(path)
+                    .stdin(Stdio::piped())
+                    .spawn()
+                    .map_err(|e| io::Error::new(e.kind(), format!("failed to run ffmpeg: {e}")))?;
+                let stdin = child.stdin.take().unwrap();
+                Ok(AnimationEncoder::Mp4(child, stdin))
+            }
+        }
+    }
+
+    /// Appends `frame`, shown for `delay`.
+    pub fn push(&mut self, frame: &Rgba32FImage, delay: Delay) -> io::Result<()> {
+        let frame: RgbaImage = frame.convert();
+        match self {
+            AnimationEncoder::Gif(encoder) => encoder
+                .encode_frame(Frame::from_parts(frame, 0, 0, delay))
+                .map_err(io::Error::other),
+            AnimationEncoder::Apng(writer) => {
+                let (numer, denom) = delay.numer_denom_ms();
+                let ms = (numer as f32 / denom as f32).round();
+                writer
+                    .set_frame_delay(ms.min(u16::MAX as f32) as u16, 1000)
+                    .map_err(io::Error::other)?;
+                writer
+                    .write_image_data(frame.as_raw())
+                    .map_err(io::Error::other)
+            }
+            AnimationEncoder::Mp4(_, stdin) => stdin.write_all(frame.as_raw()),
+        }
+    }
+
+    /// Stops encoding without finishing the file.
+    pub fn abort(self) {
+        if let AnimationEncoder::Mp4(mut child, stdin) = self {
+            drop(stdin);
+            child.kill().ok();
+            child.wait().ok();
+        }
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            AnimationEncoder::Gif(encoder) => {
+                drop(encoder);
+                Ok(())
+            }
+            AnimationEncoder::Apng(writer) => writer.finish().map_err(io::Error::other),
+            AnimationEncoder::Mp4(mut child, stdin) => {
+                drop(stdin);
+                let status = child.wait()?;
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(io::Error::other(format!("ffmpeg exited with {status}")))
+                }
+            }
+        }
+    }
+}
+
+/// Renders `frames` and encodes them into `path`, sending the number of
+/// finished frames to `progress`. A canceled export leaves no file behind.
+fn encode(
+    frames: impl ExactSizeIterator<Item = (RenderRequest, Delay)>,
+    fps: u32,
+    path: &Path,
+    format: AnimationFormat,
+    cancel: &CancelToken,
+    progress: &Sender<u32>,
+) -> io::Result<()> {
+    let mut frames = frames.peekable();
+    let Some((first, _)) = frames.peek() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, tr("no frames")));
+    };
+    let size = first.size;
+    let count = frames.len() as u32;
+    let mut encoder = AnimationEncoder::new(path, format, size, fps, count)?;
+    for (i, (request, delay)) in frames.enumerate() {
+        let Ok(out) = request.render(size, cancel, None) else {
+            encoder.abort();
+            fs::remove_file(path).ok();
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                tr("export canceled"),
+            ));
+        };
+        encoder.push(&out, delay)?;
+        progress.send(i as u32 + 1).ok();
+    }
+    encoder.finish()
+}
+
+/// Renders every frame of `timeline` from a still `image`.
+pub fn export(
+    timeline: &Timeline,
+    image: Arc<Rgba32FImage>,
+    mut scene: Scene,
+    path: &Path,
+    format: AnimationFormat,
+    cancel: &CancelToken,
+    progress: &Sender<u32>,
+) -> io::Result<()> {
+    let Some(first) = timeline.sample(0.0) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            tr("no keyframes"),
+        ));
+    };
+    let delay = Delay::from_numer_denom_ms(1000, timeline.fps);
+    let frames = (0..timeline.frame_count()).map(|frame| {
+        let params = timeline.sample(timeline.frame_time(frame)).unwrap_or(first);
+        (scene.request(&image, &params), delay)
+    });
+    encode(frames, timeline.fps, path, format, cancel, progress)
+}
+
+/// Projects every frame of an animated source with the same `params`,
+/// keeping the original frame delays. MP4 gets the average frame rate.
+pub fn export_sequence(
+    sequence: &FrameSequence,
+    params: &Params,
+    mut scene: Scene,
+    path: &Path,
+    format: AnimationFormat,
+    cancel: &CancelToken,
+    progress: &Sender<u32>,
+) -> io::Result<()> {
+    let total_ms: f32 = sequence
+        .frames
+        .iter()
+        .map(|(_, delay)| {
+            let (numer, denom) = delay.numer_denom_ms();
+            numer as f32 / denom as f32
+        })
+        .sum();
+    let mean_ms = total_ms / sequence.frames.len() as f32;
+    let fps = if mean_ms > 0.0 {
+        (1000.0 / mean_ms).round().max(1.0) as u32
+    } else {
+        25
+    };
+    scene.linear = sequence.linear;
+    let remap = Arc::new(RemapCache::default());
+    let frames = sequence.frames.iter().map(|(image, delay)| {
+        let mut request = scene.request(image, params);
+        request.remap = Some(Arc::clone(&remap));
+        (request, *delay)
+    });
+    encode(frames, fps, path, format, cancel, progress)
+}
@@ -0,0 +1,103 @@
+//! Standard base64 with padding, for embedding binary data in text files.
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The value of each byte in [`ALPHABET`], or `INVALID`.
+const DIGITS: [u8; 256] = {
+    let mut digits = [INVALID; 256];
+    let mut i = 0;
+    while i < ALPHABET.len() {
+        digits[ALPHABET[i] as usize] = i as u8;
+        i += 1;
+    }
+    digits
+};
+
+const INVALID: u8 = 0xff;
+
+pub fn encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes `s`, ignoring whitespace. Returns `None` on invalid input.
+// `usize::is_multiple_of` is too recent for the toolchains the app builds on.
+#[allow(clippy::manual_is_multiple_of)]
+pub fn decode(s: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = s.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if digits.len() % 4 != 0 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(digits.len() / 4 * 3);
+    for (i, chunk) in digits.chunks(4).enumerate() {
+        let last = i == digits.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&b| b == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut n = 0;
+        for &b in &chunk[..4 - padding] {
+            let v = DIGITS[b as usize];
+            if v == INVALID {
+                return None;
+            }
+            n = n << 6 | v as u32;
+        }
+        n <<= 6 * padding as u32;
+        let bytes = [(n >> 16) as u8, (n >> 8) as u8, n as u8];
+        out.extend_from_slice(&bytes[..3 - padding]);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_known_values() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"f"), "Zg==");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(encode(b"foo"), "Zm9v");
+        assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(encode(&[0xfb, 0xff]), "+/8=");
+    }
+
+    #[test]
+    fn round_trip() {
+        let data: Vec<u8> = (0..=255).chain((0..=255).rev()).collect();
+        for len in 0..data.len() {
+            assert_eq!(decode(&encode(&data[..len])).as_deref(), Some(&data[..len]));
+        }
+    }
+
+    #[test]
+    fn ignores_whitespace() {
+        assert_eq!(decode(" Zm9v\nYmFy\r\n").as_deref(), Some(&b"foobar"[..]));
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        assert_eq!(decode("Zm9"), None);
+        assert_eq!(decode("Zm9*"), None);
+        assert_eq!(decode("Z==="), None);
+        assert_eq!(decode("Zg==Zm9v"), None);
+        assert_eq!(decode("Zm9vYmFy=="), None);
+    }
+}
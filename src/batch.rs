@@ -0,0 +1,195 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::AtomicU64,
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use rayon::prelude::*;
+
+use crate::{
+    error::AppError,
+    export::{self, ExportOptions},
+    i18n::tr,
+    metadata::Metadata,
+    preset::Params,
+    remap::RemapCache,
+    render::{CancelToken, FrameSequence},
+    scene::Scene,
+};
+
+/// Extensions of the files picked up from the input folder.
+const EXTENSIONS: [&str; 10] = [
+    "jpg", "jpeg", "png", "bmp", "gif", "webp", "tif", "tiff", "hdr", "exr",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Pending,
+    Running,
+    Done,
+    Failed(String),
+}
+
+/// A new status for the file at some index.
+type Update = (usize, Status);
+
+/// Applies the same parameters to every image of a folder on a background
+/// thread.
+pub struct Batch {
+    pub input: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    /// Output file name without extension, where `{name}` is replaced by the
+    /// input file stem and `{index}` by its position in the folder.
+    pub pattern: String,
+    pub parallel: bool,
+    files: Vec<(PathBuf, Status)>,
+    generation: Arc<AtomicU64>,
+    job: Option<(JoinHandle<()>, Receiver<Update>)>,
+}
+
+impl Default for Batch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self {
+            input: None,
+            output: None,
+            pattern: "{name}_planet".to_owned(),
+            parallel: true,
+            files: vec![],
+            generation: Arc::new(AtomicU64::new(0)),
+            job: None,
+        }
+    }
+
+    pub fn files(&self) -> &[(PathBuf, Status)] {
+        &self.files
+    }
+
+    pub fn running(&self) -> bool {
+        self.job
+            .as_ref()
+            .is_some_and(|(handle, _)| !handle.is_finished())
+    }
+
+    /// Applies the status updates sent since the last call.
+    pub fn poll(&mut self) {
+        let Some((_, updates)) = &self.job else {
+            return;
+        };
+        for (i, status) in updates.try_iter() {
+            self.files[i].1 = status;
+        }
+    }
+
+    pub fn cancel(&mut self) {
+        CancelToken::next(&self.generation);
+    }
+
+    /// Renders every image of the input folder like Save Image renders the
+    /// open one, with the stickers, eye and overlay of `scene`.
+    pub fn start(
+        &mut self,
+        params: Params,
+        scene: Scene,
+        options: ExportOptions,
+    ) -> io::Result<()> {
+        let (Some(input), Some(output)) = (&self.input, &self.output) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                tr("input and output folders must be selected"),
+            ));
+        };
+        let mut files: Vec<_> = fs::read_dir(input)?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| {
+                let ext = path.extension().and_then(|e| e.to_str());
+                ext.is_some_and(|e| EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+            })
+            .collect();
+        files.sort();
+        fs::create_dir_all(output)?;
+
+        self.files = files.iter().map(|f| (f.clone(), Status::Pending)).collect();
+        let targets: Vec<_> = files
+            .iter()
+            .enumerate()
+            .map(|(i, file)| output.join(self.file_name(file, i, &options)))
+            .collect();
+        let cancel = CancelToken::next(&self.generation);
+        let parallel = self.parallel;
+        let (sender, updates) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let jobs = files.into_iter().zip(targets).enumerate();
+            let remap = Arc::new(RemapCache::default());
+            let run = |(i, (file, target)): (usize, (PathBuf, PathBuf)), sender: &Sender<_>| {
+                if cancel.is_canceled() {
+                    return;
+                }
+                sender.send((i, Status::Running)).ok();
+                let scene = scene.clone();
+                let result = process(&file, &target, &params, scene, &options, &remap, &cancel);
+                let status = match result {
+                    Ok(()) => Status::Done,
+                    Err(_) if cancel.is_canceled() => Status::Failed(tr("canceled").to_owned()),
+                    Err(e) => Status::Failed(e.to_string()),
+                };
+                sender.send((i, status)).ok();
+            };
+            if parallel {
+                jobs.collect::<Vec<_>>()
+                    .into_par_iter()
+                    .for_each_with(sender, |sender, job| run(job, sender));
+            } else {
+                jobs.for_each(|job| run(job, &sender));
+            }
+        });
+        self.job = Some((handle, updates));
+        Ok(())
+    }
+
+    fn file_name(&self, file: &Path, index: usize, options: &ExportOptions) -> String {
+        let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+        let name = self
+            .pattern
+            .replace("{name}", &stem)
+            .replace("{index}", &(index + 1).to_string());
+        format!("{name}.{}", options.format.extensions()[0])
+    }
+}
+
+fn process(
+    file: &Path,
+    target: &Path,
+    params: &Params,
+    mut scene: Scene,
+    options: &ExportOptions,
+    remap: &Arc<RemapCache>,
+    cancel: &CancelToken,
+) -> Result<(), AppError> {
+    // Loaded and saved like the image of the app, with its orientation, color
+    // profile and metadata.
+    let seq = FrameSequence::open(file)?;
+    scene.linear = seq.linear;
+    let mut request = scene.request(seq.first(), params);
+    // Files of the same size are all projected the same way.
+    request.remap = Some(Arc::clone(remap));
+    let out = request.render(params.size, cancel, None)?;
+    export::save(&out, target, options)?;
+    let source = if options.metadata {
+        seq.metadata
+    } else {
+        Metadata::default()
+    };
+    let panorama = params.inverse.then_some(params.size);
+    Ok(source.for_output(panorama, options.gpano).embed(target)?)
+}
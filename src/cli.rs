@@ -0,0 +1,72 @@
+//! Rendering one image without opening the window, as
+//! `shuodedaoli [--preset NAME | --settings TEXT] INPUT OUTPUT`, with the
+//! parameters saved on the last exit unless a preset or the text of
+//! "Copy Settings" is given.
+
+use std::{
+    ffi::OsString,
+    path::PathBuf,
+    sync::{atomic::AtomicU64, Arc},
+};
+
+use crate::{
+    config::Settings,
+    error::AppError,
+    export,
+    metadata::Metadata,
+    preset,
+    render::{CancelToken, FrameSequence},
+    scene::Scene,
+    share,
+};
+
+const USAGE: &str = "usage: shuodedaoli [--preset NAME | --settings TEXT] INPUT OUTPUT";
+
+pub fn run(args: &[OsString], settings: &Settings) -> Result<(), AppError> {
+    let mut params = settings.params;
+    let mut shared = None;
+    let mut paths = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--preset" {
+            let name = args
+                .next()
+                .ok_or_else(|| AppError::Usage(USAGE.to_owned()))?;
+            params = preset::load(&name.to_string_lossy())?;
+        } else if arg == "--settings" {
+            let text = args
+                .next()
+                .ok_or_else(|| AppError::Usage(USAGE.to_owned()))?;
+            shared = Some(share::decode(&text.to_string_lossy())?);
+        } else {
+            paths.push(PathBuf::from(arg));
+        }
+    }
+    let [input, output] = &paths[..] else {
+        return Err(AppError::Usage(USAGE.to_owned()));
+    };
+
+    let seq = FrameSequence::open(input)?;
+    let mut scene = Scene {
+        linear: seq.linear,
+        sampler: settings.sampler,
+        samples: settings.samples,
+        downscale: settings.downscale,
+        ..Scene::default()
+    };
+    if let Some(shared) = shared {
+        params = shared.apply(&mut scene);
+    }
+    let request = scene.request(seq.first(), &params);
+    let cancel = CancelToken::next(&Arc::new(AtomicU64::new(0)));
+    export::save_tiled(&request, output, &settings.export, &cancel, |_| {})?;
+    let source = if settings.export.metadata {
+        seq.metadata
+    } else {
+        Metadata::default()
+    };
+    let panorama = params.inverse.then_some(params.size);
+    let metadata = source.for_output(panorama, settings.export.gpano);
+    metadata.embed(output)?;
+    Ok(())
+}
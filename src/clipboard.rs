@@ -0,0 +1,26 @@
+use std::borrow::Cow;
+
+use arboard::{Clipboard, ImageData};
+use image::{buffer::ConvertBuffer, DynamicImage, Rgba32FImage, RgbaImage};
+
+/// Reads an image from the system clipboard.
+pub fn paste(clipboard: &mut Clipboard) -> Result<Rgba32FImage, arboard::Error> {
+    let data = clipboard.get_image()?;
+    let img = RgbaImage::from_raw(
+        data.width as u32,
+        data.height as u32,
+        data.bytes.into_owned(),
+    )
+    .ok_or(arboard::Error::ConversionFailure)?;
+    Ok(DynamicImage::ImageRgba8(img).into_rgba32f())
+}
+
+/// Puts `img` on the system clipboard as 8-bit RGBA.
+pub fn copy(clipboard: &mut Clipboard, img: &Rgba32FImage) -> Result<(), arboard::Error> {
+    let img: RgbaImage = img.convert();
+    clipboard.set_image(ImageData {
+        width: img.width() as usize,
+        height: img.height() as usize,
+        bytes: Cow::Owned(img.into_raw()),
+    })
+}
@@ -0,0 +1,115 @@
+use image::Rgba32FImage;
+use rayon::prelude::*;
+
+use crate::toml::{Table, Value};
+
+/// Whether colors are adjusted on the source or on the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    BeforeProjection,
+    AfterProjection,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 2] = [Stage::BeforeProjection, Stage::AfterProjection];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::BeforeProjection => "Before Projection",
+            Stage::AfterProjection => "After Projection",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Stage::ALL.into_iter().find(|s| s.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorAdjust {
+    /// In stops.
+    pub exposure: f32,
+    /// From -1 (flat gray) to 1.
+    pub contrast: f32,
+    /// From -1 (grayscale) to 1.
+    pub saturation: f32,
+    /// From -1 (cool) to 1 (warm).
+    pub temperature: f32,
+    /// From -1 (magenta) to 1 (green).
+    pub tint: f32,
+    pub stage: Stage,
+}
+
+impl Default for ColorAdjust {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            contrast: 0.0,
+            saturation: 0.0,
+            temperature: 0.0,
+            tint: 0.0,
+            stage: Stage::AfterProjection,
+        }
+    }
+}
+
+impl ColorAdjust {
+    pub fn is_identity(&self) -> bool {
+        Self {
+            stage: self.stage,
+            ..Default::default()
+        } == *self
+    }
+
+    /// Adjusts a straight-alpha pixel.
+    pub fn apply(&self, [r, g, b, a]: [f32; 4]) -> [f32; 4] {
+        let gain = self.exposure.exp2();
+        let balance = [
+            1.0 + 0.25 * self.temperature,
+            1.0 + 0.25 * self.tint,
+            1.0 - 0.25 * self.temperature,
+        ];
+        let rgb = [r, g, b];
+        let [r, g, b] = [0, 1, 2]
+            .map(|i| ((rgb[i] * gain * balance[i] - 0.5) * (1.0 + self.contrast) + 0.5).max(0.0));
+        let luma = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        let [r, g, b] = [r, g, b].map(|c| (luma + (c - luma) * (1.0 + self.saturation)).max(0.0));
+        [r, g, b, a]
+    }
+
+    pub fn apply_image(&self, img: &mut Rgba32FImage) {
+        img.par_chunks_mut(4).for_each(|pixel| {
+            let adjusted = self.apply(pixel.try_into().unwrap());
+            pixel.copy_from_slice(&adjusted);
+        });
+    }
+
+    pub fn write(&self, table: &mut Table, prefix: &str) {
+        table.insert(format!("{prefix}exposure"), self.exposure);
+        table.insert(format!("{prefix}contrast"), self.contrast);
+        table.insert(format!("{prefix}saturation"), self.saturation);
+        table.insert(format!("{prefix}temperature"), self.temperature);
+        table.insert(format!("{prefix}tint"), self.tint);
+        table.insert(format!("{prefix}color_stage"), self.stage.name());
+    }
+
+    pub fn read(table: &Table, prefix: &str) -> Self {
+        let get = |key: &str| table.get(&format!("{prefix}{key}"));
+        let mut adjust = ColorAdjust::default();
+        for (key, value) in [
+            ("exposure", &mut adjust.exposure),
+            ("contrast", &mut adjust.contrast),
+            ("saturation", &mut adjust.saturation),
+            ("temperature", &mut adjust.temperature),
+            ("tint", &mut adjust.tint),
+        ] {
+            if let Some(v) = get(key).and_then(Value::as_f32) {
+                *value = v;
+            }
+        }
+        if let Some(stage) = get("color_stage").and_then(Value::as_str) {
+            adjust.stage = Stage::from_name(stage).unwrap_or(adjust.stage);
+        }
+        adjust
+    }
+}
@@ -0,0 +1,148 @@
+use egui::{
+    load::SizedTexture, pos2, vec2, Align2, Color32, ColorImage, Context, FontId, Image,
+    ImageSource, Pos2, Rect, Response, Sense, Stroke, TextureHandle, Ui, Vec2,
+};
+use image::{buffer::ConvertBuffer, Rgba32FImage, RgbaImage};
+
+use crate::preset::Params;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareMode {
+    Off,
+    SideBySide,
+    Split,
+}
+
+impl CompareMode {
+    pub const ALL: [CompareMode; 3] = [
+        CompareMode::Off,
+        CompareMode::SideBySide,
+        CompareMode::Split,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CompareMode::Off => "Off",
+            CompareMode::SideBySide => "Side by Side",
+            CompareMode::Split => "Split",
+        }
+    }
+}
+
+/// A pinned render, "A", with the parameters it was rendered with.
+pub struct Snapshot {
+    texture: TextureHandle,
+    pub params: Params,
+}
+
+/// Shows the current render, "B", next to or split with a pinned one.
+pub struct Compare {
+    pub snapshot: Option<Snapshot>,
+    pub mode: CompareMode,
+    /// Fraction of the preview's width showing "A" in split mode.
+    pub split: f32,
+    dragging_split: bool,
+}
+
+impl Default for Compare {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compare {
+    pub fn new() -> Self {
+        Self {
+            snapshot: None,
+            mode: CompareMode::Off,
+            split: 0.5,
+            dragging_split: false,
+        }
+    }
+
+    pub fn pin(&mut self, ctx: &Context, image: &Rgba32FImage, params: Params) {
+        let image: RgbaImage = image.convert();
+        let size = [image.width() as usize, image.height() as usize];
+        let image = ColorImage::from_rgba_unmultiplied(size, image.as_raw());
+        self.snapshot = Some(Snapshot {
+            texture: ctx.load_texture("snapshot", image, Default::default()),
+            params,
+        });
+        if self.mode == CompareMode::Off {
+            self.mode = CompareMode::Split;
+        }
+    }
+
+    /// Whether a drag on the preview moves the split line rather than the
+    /// planet.
+    pub fn is_dragging(&self) -> bool {
+        self.dragging_split
+    }
+
+    fn active(&self) -> Option<&Snapshot> {
+        self.snapshot
+            .as_ref()
+            .filter(|_| self.mode != CompareMode::Off)
+    }
+
+    /// How many images side by side are shown.
+    pub fn columns(&self) -> f32 {
+        match self.active() {
+            Some(_) if self.mode == CompareMode::SideBySide => 2.0,
+            _ => 1.0,
+        }
+    }
+
+    /// Adds the preview of `current` with each image drawn at `size`, and
+    /// returns the response of the current render for its own interactions.
+    pub fn show(&mut self, ui: &mut Ui, current: SizedTexture, size: Vec2) -> Response {
+        let image = Image::new(ImageSource::Texture(current))
+            .fit_to_exact_size(size)
+            .sense(Sense::click_and_drag());
+        let Some(texture) = self.active().map(|s| s.texture.id()) else {
+            return ui.add(image);
+        };
+        let pinned = SizedTexture::new(texture, current.size);
+        if self.mode == CompareMode::SideBySide {
+            return ui
+                .horizontal_top(|ui| {
+                    ui.add(Image::new(ImageSource::Texture(pinned)).fit_to_exact_size(size));
+                    ui.add(image)
+                })
+                .inner;
+        }
+
+        let response = ui.add(image);
+        let rect = response.rect;
+        let x = rect.left() + rect.width() * self.split;
+        if response.drag_started() {
+            let near = |p: Pos2| (p.x - x).abs() < 8.0;
+            self.dragging_split = response.interact_pointer_pos().is_some_and(near);
+        }
+        if self.dragging_split {
+            if let Some(p) = response.interact_pointer_pos() {
+                self.split = ((p.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+            }
+            if response.drag_released() || !response.dragged() {
+                self.dragging_split = false;
+            }
+        }
+        let x = rect.left() + rect.width() * self.split;
+        let left = Rect::from_min_max(rect.min, pos2(x, rect.bottom()));
+        let uv = Rect::from_min_max(pos2(0.0, 0.0), pos2(self.split, 1.0));
+        let painter = ui.painter_at(rect);
+        painter.image(texture, left, uv, Color32::WHITE);
+        painter.line_segment(
+            [pos2(x, rect.top()), pos2(x, rect.bottom())],
+            Stroke::new(2.0, Color32::WHITE),
+        );
+        painter.circle_filled(pos2(x, rect.center().y), 6.0, Color32::WHITE);
+        for (pos, text, align) in [
+            (rect.left_top() + vec2(6.0, 4.0), "A", Align2::LEFT_TOP),
+            (rect.right_top() + vec2(-6.0, 4.0), "B", Align2::RIGHT_TOP),
+        ] {
+            painter.text(pos, align, text, FontId::default(), Color32::WHITE);
+        }
+        response
+    }
+}
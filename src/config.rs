@@ -0,0 +1,154 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    export::ExportOptions,
+    gpu::Backend,
+    i18n::Language,
+    mipmap::Downscale,
+    poster::PosterOptions,
+    preset::Params,
+    sampler::Sampler,
+    toml::{Table, Value},
+};
+
+/// Per-user directory for settings and presets, following the platform's
+/// conventions.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        PathBuf::from(env::var_os("APPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(env::var_os("HOME")?).join("Library/Application Support")
+    } else if let Some(dir) = env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        PathBuf::from(dir)
+    } else {
+        PathBuf::from(env::var_os("HOME")?).join(".config")
+    };
+    Some(base.join("shuodedaoli"))
+}
+
+/// How many recently opened images are kept.
+const MAX_RECENT: usize = 10;
+
+/// Moves `path` to the front of the recently opened images.
+pub fn add_recent(recent: &mut Vec<PathBuf>, path: &Path) {
+    recent.retain(|p| p != path);
+    recent.insert(0, path.to_owned());
+    recent.truncate(MAX_RECENT);
+}
+
+/// Everything restored on the next start.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub params: Params,
+    pub sampler: Sampler,
+    pub samples: u32,
+    pub downscale: Downscale,
+    pub backend: Backend,
+    pub export: ExportOptions,
+    pub poster: PosterOptions,
+    pub last_dir: Option<PathBuf>,
+    /// Recently opened images, the latest first.
+    pub recent: Vec<PathBuf>,
+    /// Source of the custom projection, kept while it is turned off.
+    pub script: String,
+    pub language: Language,
+    pub window_size: (f32, f32),
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            params: Params::default(),
+            sampler: Sampler::Bilinear,
+            samples: 1,
+            downscale: Downscale::Auto,
+            backend: Backend::Auto,
+            export: ExportOptions::default(),
+            poster: PosterOptions::default(),
+            last_dir: None,
+            recent: Vec::new(),
+            script: String::new(),
+            language: Language::detect(),
+            window_size: (900.0, 600.0),
+        }
+    }
+}
+
+impl Settings {
+    fn path() -> Option<PathBuf> {
+        Some(config_dir()?.join("settings.toml"))
+    }
+
+    /// Loads the saved settings, falling back to the defaults for anything
+    /// missing or unreadable.
+    pub fn load() -> Self {
+        let table = Self::path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|s| s.parse::<Table>().ok())
+            .unwrap_or_default();
+        let get = |key| table.get(key);
+        let mut settings = Settings {
+            params: Params::read(&table, ""),
+            export: ExportOptions::read(&table, "export."),
+            poster: PosterOptions::read(&table, "poster."),
+            ..Default::default()
+        };
+        if let Some(sampler) = get("sampler").and_then(Value::as_str) {
+            settings.sampler = Sampler::from_name(sampler).unwrap_or(settings.sampler);
+        }
+        if let Some(samples) = get("samples").and_then(Value::as_u32) {
+            settings.samples = samples.max(1);
+        }
+        if let Some(downscale) = get("downscale").and_then(Value::as_str) {
+            settings.downscale = Downscale::from_name(downscale).unwrap_or(settings.downscale);
+        }
+        if let Some(backend) = get("backend").and_then(Value::as_str) {
+            settings.backend = Backend::from_name(backend).unwrap_or(settings.backend);
+        }
+        settings.last_dir = get("last_dir").and_then(Value::as_str).map(PathBuf::from);
+        if let Some(recent) = get("recent").and_then(Value::as_array) {
+            let paths = recent.iter().filter_map(Value::as_str).map(PathBuf::from);
+            settings.recent = paths.take(MAX_RECENT).collect();
+        }
+        if let Some(language) = get("language").and_then(Value::as_str) {
+            settings.language = Language::from_name(language).unwrap_or(settings.language);
+        }
+        if let Some(script) = get("script").and_then(Value::as_str) {
+            settings.script = script.to_owned();
+        }
+        if let Some([w, h]) = get("window.size").and_then(Value::as_array) {
+            if let (Some(w), Some(h)) = (w.as_f32(), h.as_f32()) {
+                settings.window_size = (w.max(100.0), h.max(100.0));
+            }
+        }
+        settings
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+        let mut table = Table::new();
+        self.params.write(&mut table, "");
+        table.insert("sampler", self.sampler.name());
+        table.insert("samples", self.samples);
+        table.insert("downscale", self.downscale.name());
+        table.insert("backend", self.backend.name());
+        self.export.write(&mut table, "export.");
+        self.poster.write(&mut table, "poster.");
+        if let Some(dir) = self.last_dir.as_ref().and_then(|dir| dir.to_str()) {
+            table.insert("last_dir", dir);
+        }
+        let recent = self.recent.iter().filter_map(|path| path.to_str());
+        table.insert("recent", Value::Array(recent.map(Into::into).collect()));
+        table.insert("script", self.script.as_str());
+        table.insert("language", self.language.name());
+        table.insert("window.size", [self.window_size.0, self.window_size.1]);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, table.to_string())
+    }
+}
@@ -0,0 +1,299 @@
+use std::path::{Path, PathBuf};
+
+use image::{ImageResult, Rgba32FImage};
+use nalgebra::{vector, SVector, Unit};
+use rayon::prelude::*;
+
+use crate::{
+    error::AppError,
+    export::{self, ExportOptions},
+    i18n::tr,
+    projection,
+    sampler::{self, EdgeMode, Sampler},
+};
+
+type Vec3f = SVector<f32, 3>;
+
+/// Faces of a cube map, as seen from inside of the cube.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Face {
+    Front,
+    Right,
+    Back,
+    Left,
+    Up,
+    Down,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::Front,
+        Face::Right,
+        Face::Back,
+        Face::Left,
+        Face::Up,
+        Face::Down,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Face::Front => "front",
+            Face::Right => "right",
+            Face::Back => "back",
+            Face::Left => "left",
+            Face::Up => "up",
+            Face::Down => "down",
+        }
+    }
+
+    /// Direction of the center of the face, and of the right and up edges of
+    /// the face image. The front faces the center of equirectangular images.
+    pub fn axes(self) -> [Vec3f; 3] {
+        let (x, y, z) = (Vec3f::x(), Vec3f::y(), Vec3f::z());
+        match self {
+            Face::Front => [y, x, z],
+            Face::Right => [x, -y, z],
+            Face::Back => [-y, -x, z],
+            Face::Left => [-x, y, z],
+            Face::Up => [z, x, -y],
+            Face::Down => [-z, x, y],
+        }
+    }
+
+    /// Cell of the face in a horizontal cross, four faces wide and three
+    /// high.
+    pub fn cross_cell(self) -> (u32, u32) {
+        match self {
+            Face::Left => (0, 1),
+            Face::Front => (1, 1),
+            Face::Right => (2, 1),
+            Face::Back => (3, 1),
+            Face::Up => (1, 0),
+            Face::Down => (1, 2),
+        }
+    }
+
+    /// Guesses the face from common file naming schemes, such as
+    /// `sky_front.png`, `posz.jpg` or `pz.jpg`.
+    fn from_file_name(path: &Path) -> Option<Face> {
+        let stem = path.file_stem()?.to_str()?.to_ascii_lowercase();
+        let hints: [(Face, &[&str]); 6] = [
+            (Face::Front, &["front", "posz", "pz", "ft"]),
+            (Face::Back, &["back", "negz", "nz", "bk"]),
+            (Face::Right, &["right", "posx", "px", "rt"]),
+            (Face::Left, &["left", "negx", "nx", "lf"]),
+            (Face::Up, &["up", "top", "posy", "py"]),
+            (Face::Down, &["down", "bottom", "negy", "ny", "dn"]),
+        ];
+        let mut words = stem.split(|c: char| !c.is_ascii_alphanumeric());
+        words.find_map(|word| {
+            hints
+                .iter()
+                .find(|(_, names)| names.contains(&word))
+                .map(|&(face, _)| face)
+        })
+    }
+}
+
+/// How the faces of an exported cube map are arranged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Cross,
+    /// One file per face, named after the face.
+    Separate,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 2] = [Layout::Cross, Layout::Separate];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Layout::Cross => "Horizontal Cross",
+            Layout::Separate => "Separate Files",
+        }
+    }
+}
+
+/// Six square faces, indexed in the order of [`Face::ALL`].
+pub struct CubeMap {
+    faces: Vec<Rgba32FImage>,
+}
+
+impl CubeMap {
+    /// Cuts the faces out of a horizontal (4×3) or vertical (3×4) cross.
+    pub fn from_cross(img: &Rgba32FImage) -> Result<Self, AppError> {
+        let (width, height) = img.dimensions();
+        let (size, vertical) = if width * 3 == height * 4 {
+            (width / 4, false)
+        } else if width * 4 == height * 3 {
+            (width / 3, true)
+        } else {
+            return Err(AppError::Invalid(format!(
+                "a {width}×{height} image is not a 4×3 or 3×4 cross layout"
+            )));
+        };
+        let faces = Face::ALL
+            .into_iter()
+            .map(|face| {
+                let (col, row) = face.cross_cell();
+                // The vertical cross has the back face upside down below
+                // the down face.
+                let (col, row, flip) = match (vertical, face) {
+                    (true, Face::Back) => (1, 3, true),
+                    _ => (col, row, false),
+                };
+                let mut face =
+                    image::imageops::crop_imm(img, col * size, row * size, size, size).to_image();
+                if flip {
+                    image::imageops::rotate180_in_place(&mut face);
+                }
+                face
+            })
+            .collect();
+        Ok(Self { faces })
+    }
+
+    /// Loads six face images, identified by their file names.
+    pub fn from_files(paths: &[PathBuf]) -> Result<Self, AppError> {
+        if paths.len() != 6 {
+            return Err(AppError::Invalid(format!(
+                "expected 6 face images, got {}",
+                paths.len()
+            )));
+        }
+        let mut faces: [Option<Rgba32FImage>; 6] = Default::default();
+        for path in paths {
+            let face = Face::from_file_name(path).ok_or_else(|| {
+                AppError::Invalid(format!("cannot tell which face {} is", path.display()))
+            })?;
+            let index = Face::ALL.iter().position(|&f| f == face).unwrap();
+            if faces[index].is_some() {
+                return Err(AppError::Invalid(format!(
+                    "more than one {} face",
+                    face.name()
+                )));
+            }
+            let img = image::open(path)
+                .map_err(|e| AppError::Invalid(format!("{}: {e}", path.display())))?;
+            faces[index] = Some(img.into_rgba32f());
+        }
+        let faces: Vec<_> = faces.into_iter().map(Option::unwrap).collect();
+        let size = faces[0].dimensions();
+        if size.0 != size.1 || faces.iter().any(|f| f.dimensions() != size) {
+            return Err(AppError::Invalid(
+                tr("faces must be square and of the same size").to_owned(),
+            ));
+        }
+        Ok(Self { faces })
+    }
+
+    pub fn open(paths: &[PathBuf]) -> Result<Self, AppError> {
+        match paths {
+            [path] => {
+                let img = image::open(path)?;
+                Self::from_cross(&img.into_rgba32f())
+            }
+            _ => Self::from_files(paths),
+        }
+    }
+
+    /// Resamples an equirectangular panorama into faces of `size` pixels.
+    pub fn from_equirect(img: &Rgba32FImage, size: u32, sampler: Sampler) -> Self {
+        let (width, height) = img.dimensions();
+        let faces = Face::ALL
+            .into_iter()
+            .map(|face| {
+                let [center, right, up] = face.axes();
+                let mut out = Rgba32FImage::new(size, size);
+                out.par_chunks_mut(size as usize * 4)
+                    .enumerate()
+                    .for_each(|(y, line)| {
+                        let v = 1.0 - (y as f32 + 0.5) / size as f32 * 2.0;
+                        for (x, pixel) in line.chunks_exact_mut(4).enumerate() {
+                            let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                            let d = Unit::new_normalize(center + right * u + up * v);
+                            let p = projection::sphere_to_equirect(d);
+                            let q = sampler.sample(
+                                img,
+                                p.x * width as f32,
+                                p.y * height as f32,
+                                EdgeMode::Wrap,
+                            );
+                            pixel.copy_from_slice(&sampler::unpremultiply(q).0);
+                        }
+                    });
+                out
+            })
+            .collect();
+        Self { faces }
+    }
+
+    /// Lays the faces out as in [`Face::cross_cell`], leaving the empty cells
+    /// transparent.
+    pub fn to_cross(&self) -> Rgba32FImage {
+        let size = self.face_size();
+        let mut out = Rgba32FImage::new(size * 4, size * 3);
+        for (face, img) in Face::ALL.into_iter().zip(&self.faces) {
+            let (col, row) = face.cross_cell();
+            image::imageops::replace(&mut out, img, (col * size) as i64, (row * size) as i64);
+        }
+        out
+    }
+
+    /// Saves the cube map to `path`, or for separate files, next to it with
+    /// the face name appended to the file stem.
+    pub fn save(&self, path: &Path, layout: Layout, options: &ExportOptions) -> ImageResult<()> {
+        match layout {
+            Layout::Cross => export::save(&self.to_cross(), path, options),
+            Layout::Separate => {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                let ext = path
+                    .extension()
+                    .map(|e| e.to_string_lossy())
+                    .unwrap_or(options.format.extensions()[0].into());
+                for (face, img) in Face::ALL.into_iter().zip(&self.faces) {
+                    let path = path.with_file_name(format!("{stem}_{}.{ext}", face.name()));
+                    export::save(img, &path, options)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn face_size(&self) -> u32 {
+        self.faces[0].width()
+    }
+
+    /// Samples the cube along direction `d`.
+    pub fn sample(&self, sampler: Sampler, d: Unit<Vec3f>) -> SVector<f32, 4> {
+        let (index, face) = Face::ALL
+            .into_iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| d.dot(&a.axes()[0]).total_cmp(&d.dot(&b.axes()[0])))
+            .unwrap();
+        let [center, right, up] = face.axes();
+        let depth = d.dot(&center);
+        let (u, v) = (d.dot(&right) / depth, d.dot(&up) / depth);
+        let size = self.face_size() as f32;
+        let x = (u + 1.0) / 2.0 * size - 0.5;
+        let y = (1.0 - v) / 2.0 * size - 0.5;
+        sampler.sample(&self.faces[index], x, y, EdgeMode::Transparent)
+    }
+
+    /// Resamples the cube map into a 2:1 equirectangular panorama.
+    pub fn to_equirect(&self, sampler: Sampler) -> Rgba32FImage {
+        let size = self.face_size();
+        let (width, height) = (size * 4, size * 2);
+        let mut out = Rgba32FImage::new(width, height);
+        out.par_chunks_mut(width as usize * 4)
+            .enumerate()
+            .for_each(|(y, line)| {
+                for (x, pixel) in line.chunks_exact_mut(4).enumerate() {
+                    let p = vector![x as f32 / width as f32, y as f32 / height as f32];
+                    let q = self.sample(sampler, projection::equirect_to_sphere(p));
+                    pixel.copy_from_slice(&sampler::unpremultiply(q).0);
+                }
+            });
+        out
+    }
+}
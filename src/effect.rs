@@ -0,0 +1,327 @@
+use image::Rgba32FImage;
+use nalgebra::{vector, SVector};
+use rayon::prelude::*;
+
+use crate::{
+    overlay, preset,
+    toml::{Table, Value},
+};
+
+type Vec2f = SVector<f32, 2>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VignetteMode {
+    /// Darkens the colors.
+    Darken,
+    /// Fades the alpha out, for compositing the planet onto something else.
+    Fade,
+}
+
+impl VignetteMode {
+    pub const ALL: [VignetteMode; 2] = [VignetteMode::Darken, VignetteMode::Fade];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            VignetteMode::Darken => "Darken",
+            VignetteMode::Fade => "Fade Out",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        VignetteMode::ALL.into_iter().find(|m| m.name() == name)
+    }
+}
+
+/// A radial falloff around the planet center. Distances are in halves of
+/// the shorter output side, so that the effect is the same at any
+/// resolution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vignette {
+    pub mode: VignetteMode,
+    /// From 0 (off) to 1.
+    pub strength: f32,
+    /// Distance where the falloff starts; it is complete half a unit further.
+    pub radius: f32,
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Self {
+            mode: VignetteMode::Darken,
+            strength: 0.0,
+            radius: 0.8,
+        }
+    }
+}
+
+impl Vignette {
+    /// Applies the falloff to a straight-alpha pixel at `distance` from the
+    /// planet center.
+    pub fn apply(&self, [r, g, b, a]: [f32; 4], distance: f32) -> [f32; 4] {
+        let t = ((distance - self.radius) / 0.5).clamp(0.0, 1.0);
+        let k = 1.0 - self.strength * t * t * (3.0 - 2.0 * t);
+        match self.mode {
+            VignetteMode::Darken => [r * k, g * k, b * k, a],
+            VignetteMode::Fade => [r, g, b, a * k],
+        }
+    }
+
+    pub fn write(&self, table: &mut Table, prefix: &str) {
+        table.insert(format!("{prefix}vignette"), self.mode.name());
+        table.insert(format!("{prefix}vignette_strength"), self.strength);
+        table.insert(format!("{prefix}vignette_radius"), self.radius);
+    }
+
+    pub fn read(table: &Table, prefix: &str) -> Self {
+        let get = |key: &str| table.get(&format!("{prefix}{key}"));
+        let mut vignette = Vignette::default();
+        if let Some(mode) = get("vignette").and_then(Value::as_str) {
+            vignette.mode = VignetteMode::from_name(mode).unwrap_or(vignette.mode);
+        }
+        if let Some(strength) = get("vignette_strength").and_then(Value::as_f32) {
+            vignette.strength = strength.clamp(0.0, 1.0);
+        }
+        if let Some(radius) = get("vignette_radius").and_then(Value::as_f32) {
+            vignette.radius = radius;
+        }
+        vignette
+    }
+}
+
+/// Lines of latitude and longitude of the panorama, drawn through the
+/// projection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Graticule {
+    pub enabled: bool,
+    /// Degrees between lines.
+    pub spacing: f32,
+    /// Straight alpha.
+    pub color: [f32; 4],
+    /// Draws the lines on the preview only, leaving them out of saved images.
+    pub preview_only: bool,
+}
+
+impl Default for Graticule {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            spacing: 15.0,
+            color: [1.0, 1.0, 1.0, 0.75],
+            preview_only: true,
+        }
+    }
+}
+
+impl Graticule {
+    /// Longitude and latitude in degrees of a point of the panorama given in
+    /// coordinates normalized to `[0, 1]`.
+    pub fn degrees(uv: Vec2f) -> Vec2f {
+        vector![(uv.x - 0.5) * 360.0, (0.5 - uv.y) * 180.0]
+    }
+
+    /// How much of an output pixel the lines cover, from the longitude and
+    /// latitude at the pixel and at its right and lower neighbors. `width` is
+    /// the line width in pixels.
+    pub fn coverage(&self, at: Vec2f, right: Vec2f, below: Vec2f, width: f32) -> f32 {
+        let turn = |d: f32| (d + 180.0).rem_euclid(360.0) - 180.0;
+        let dx = vector![turn(right.x - at.x), right.y - at.y];
+        let dy = vector![turn(below.x - at.x), below.y - at.y];
+        let mut coverage = 0.0f32;
+        for axis in 0..2 {
+            // Degrees per pixel across the lines of this axis.
+            let step = dx[axis].hypot(dy[axis]);
+            // Meridians are left out where they crowd together at the poles.
+            if !(step > 0.0 && step < self.spacing / 4.0) {
+                continue;
+            }
+            let f = at[axis] / self.spacing;
+            let pixels = (f - f.round()).abs() * self.spacing / step;
+            coverage = coverage.max((width / 2.0 + 0.5 - pixels).clamp(0.0, 1.0));
+        }
+        coverage
+    }
+
+    /// Draws the lines over a straight-alpha pixel.
+    pub fn apply(&self, color: [f32; 4], coverage: f32) -> [f32; 4] {
+        let [r, g, b, a] = self.color;
+        overlay::over(color, [r, g, b, a * coverage])
+    }
+
+    pub fn write(&self, table: &mut Table, prefix: &str) {
+        table.insert(format!("{prefix}graticule"), self.enabled);
+        table.insert(format!("{prefix}graticule_spacing"), self.spacing);
+        table.insert(format!("{prefix}graticule_color"), self.color);
+        table.insert(format!("{prefix}graticule_preview_only"), self.preview_only);
+    }
+
+    pub fn read(table: &Table, prefix: &str) -> Self {
+        let get = |key: &str| table.get(&format!("{prefix}{key}"));
+        let mut graticule = Graticule::default();
+        if let Some(enabled) = get("graticule").and_then(Value::as_bool) {
+            graticule.enabled = enabled;
+        }
+        if let Some(spacing) = get("graticule_spacing").and_then(Value::as_f32) {
+            graticule.spacing = spacing.clamp(1.0, 90.0);
+        }
+        if let Some(color) = preset::f32s(get("graticule_color")) {
+            graticule.color = color;
+        }
+        if let Some(preview_only) = get("graticule_preview_only").and_then(Value::as_bool) {
+            graticule.preview_only = preview_only;
+        }
+        graticule
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pole {
+    /// The bottom of the panorama, where the tripod usually is.
+    Nadir,
+    Zenith,
+}
+
+impl Pole {
+    pub const ALL: [Pole; 2] = [Pole::Nadir, Pole::Zenith];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pole::Nadir => "Nadir",
+            Pole::Zenith => "Zenith",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Pole::ALL.into_iter().find(|p| p.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoleMode {
+    /// Blurs the cap around the pole, more the closer to the pole.
+    Blur,
+    /// Covers the cap with the ring around it, blurred towards the pole.
+    Fill,
+}
+
+impl PoleMode {
+    pub const ALL: [PoleMode; 2] = [PoleMode::Blur, PoleMode::Fill];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PoleMode::Blur => "Blur",
+            PoleMode::Fill => "Fill",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        PoleMode::ALL.into_iter().find(|m| m.name() == name)
+    }
+}
+
+/// Retouching of a cap around a pole of the source panorama, which the
+/// projections blow up into the middle of a little planet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoleCap {
+    pub enabled: bool,
+    pub pole: Pole,
+    pub mode: PoleMode,
+    /// Angular radius of the cap in degrees.
+    pub radius: f32,
+}
+
+impl Default for PoleCap {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            pole: Pole::Nadir,
+            mode: PoleMode::Blur,
+            radius: 15.0,
+        }
+    }
+}
+
+impl PoleCap {
+    /// Retouches an equirectangular `img` covering `fov` degrees of latitude
+    /// around the equator. Each row of the cap is averaged over a span of
+    /// longitudes that grows from nothing at the rim to all of them at the
+    /// pole, so the pole becomes a single color.
+    pub fn apply_image(&self, img: &mut Rgba32FImage, fov: f32) {
+        let (width, height) = img.dimensions();
+        let sign = match self.pole {
+            Pole::Nadir => -1.0,
+            Pole::Zenith => 1.0,
+        };
+        let latitude = |y: f32| (0.5 - (y + 0.5) / height as f32) * fov;
+        // The row at the rim of the cap, which is copied inwards to fill it.
+        let rim = (0.5 - sign * (90.0 - self.radius) / fov) * height as f32 - 0.5;
+        let rim = (rim.round().max(0.0) as u32).min(height - 1);
+        let row_len = width as usize * 4;
+        let rim_row = img.as_raw()[rim as usize * row_len..][..row_len].to_vec();
+        img.par_chunks_mut(row_len)
+            .enumerate()
+            .for_each(|(y, line)| {
+                let distance = 90.0 - sign * latitude(y as f32);
+                if distance >= self.radius {
+                    return;
+                }
+                let t = (distance / self.radius).max(0.0);
+                let half = ((1.0 - t) * width as f32 / 2.0) as usize;
+                let source = match self.mode {
+                    PoleMode::Blur => line.to_vec(),
+                    PoleMode::Fill => rim_row.clone(),
+                };
+                blur_row(&source, line, half);
+            });
+    }
+
+    pub fn write(&self, table: &mut Table, prefix: &str) {
+        table.insert(format!("{prefix}pole"), self.enabled);
+        table.insert(format!("{prefix}pole_side"), self.pole.name());
+        table.insert(format!("{prefix}pole_mode"), self.mode.name());
+        table.insert(format!("{prefix}pole_radius"), self.radius);
+    }
+
+    pub fn read(table: &Table, prefix: &str) -> Self {
+        let get = |key: &str| table.get(&format!("{prefix}{key}"));
+        let mut cap = PoleCap::default();
+        if let Some(enabled) = get("pole").and_then(Value::as_bool) {
+            cap.enabled = enabled;
+        }
+        if let Some(pole) = get("pole_side").and_then(Value::as_str) {
+            cap.pole = Pole::from_name(pole).unwrap_or(cap.pole);
+        }
+        if let Some(mode) = get("pole_mode").and_then(Value::as_str) {
+            cap.mode = PoleMode::from_name(mode).unwrap_or(cap.mode);
+        }
+        if let Some(radius) = get("pole_radius").and_then(Value::as_f32) {
+            cap.radius = radius.clamp(1.0, 45.0);
+        }
+        cap
+    }
+}
+
+/// Averages each straight-alpha pixel of the row `source` with the `half`
+/// pixels on either side, wrapping around, into `out`.
+fn blur_row(source: &[f32], out: &mut [f32], half: usize) {
+    let width = source.len() / 4;
+    // Running sums of the premultiplied pixels, over the row twice.
+    let mut sums = vec![[0.0f64; 4]; 2 * width + 1];
+    for i in 0..2 * width {
+        let p = &source[i % width * 4..][..4];
+        let a = p[3] as f64;
+        let pixel = [p[0] as f64 * a, p[1] as f64 * a, p[2] as f64 * a, a];
+        sums[i + 1] = [0, 1, 2, 3].map(|c| sums[i][c] + pixel[c]);
+    }
+    let span = (2 * half + 1).min(width);
+    for (x, pixel) in out.chunks_exact_mut(4).enumerate() {
+        let start = (x + width - half.min(width / 2)) % width;
+        let sum = [0, 1, 2, 3].map(|c| sums[start + span][c] - sums[start][c]);
+        let alpha = sum[3];
+        if alpha > 0.0 {
+            for (p, c) in pixel.iter_mut().zip(&sum[..3]) {
+                *p = (c / alpha) as f32;
+            }
+        }
+        pixel[3] = (alpha / span as f64) as f32;
+    }
+}
@@ -0,0 +1,61 @@
+//! Why loading, rendering or saving failed, shown to the user as a toast or
+//! printed on the command line.
+
+use std::io;
+
+use image::ImageError;
+use thiserror::Error;
+
+use crate::{
+    i18n::tr,
+    render::{Canceled, RenderError},
+};
+
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Image(#[from] ImageError),
+    #[error(transparent)]
+    Clipboard(#[from] arboard::Error),
+    #[error("{}", tr("canceled"))]
+    Canceled,
+    #[error("{}", tr("no horizon was found in the image"))]
+    NoHorizon,
+    /// Arguments the command line does not understand.
+    #[error("{0}")]
+    Usage(String),
+    /// Input that can be read but not used, such as a cube map of the wrong
+    /// shape.
+    #[error("{0}")]
+    Invalid(String),
+}
+
+impl From<Canceled> for AppError {
+    fn from(_: Canceled) -> Self {
+        AppError::Canceled
+    }
+}
+
+impl From<RenderError> for AppError {
+    fn from(e: RenderError) -> Self {
+        match e {
+            RenderError::Canceled => AppError::Canceled,
+            RenderError::Empty => AppError::Invalid(tr("the output has no pixels").to_owned()),
+        }
+    }
+}
+
+impl AppError {
+    /// The exit code of the command line, following the BSD `sysexits.h`.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AppError::Usage(_) => 64,
+            AppError::Io(_) | AppError::Image(ImageError::IoError(_)) => 74,
+            AppError::Image(_) | AppError::Invalid(_) => 65,
+            AppError::Canceled => 130,
+            AppError::Clipboard(_) | AppError::NoHorizon => 1,
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Random variations of the current composition, rendered as thumbnails to
+//! pick from.
+
+use std::{
+    sync::{
+        atomic::AtomicU64,
+        mpsc::{self, Receiver},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use egui::{ColorImage, Context, ImageButton, TextureHandle, Ui, Vec2};
+use image::{buffer::ConvertBuffer, RgbaImage};
+use nalgebra::vector;
+
+use crate::{
+    i18n::trf,
+    preset::Params,
+    render::{CancelToken, RenderRequest},
+};
+
+/// Variations shown at once, in a square grid.
+pub const COUNT: usize = 9;
+
+/// Longer side of a thumbnail in pixels.
+const THUMBNAIL_SIZE: u32 = 128;
+
+/// A rendered thumbnail for the variation at some index.
+type Thumbnail = (usize, ColorImage);
+
+pub struct Explore {
+    /// How far the variations stray from the current parameters, from 0 to 1.
+    pub spread: f32,
+    seed: u32,
+    variations: Vec<(Params, Option<TextureHandle>)>,
+    generation: Arc<AtomicU64>,
+    job: Option<(JoinHandle<()>, Receiver<Thumbnail>)>,
+}
+
+impl Default for Explore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A xorshift step, returning a number in `[-1, 1]`.
+fn random(state: &mut u32) -> f32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    *state as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+/// Wraps an angle in degrees into `[-180, 180)`.
+fn wrap_degrees(angle: f32) -> f32 {
+    (angle + 180.0).rem_euclid(360.0) - 180.0
+}
+
+impl Explore {
+    pub fn new() -> Self {
+        Self {
+            spread: 0.5,
+            seed: 0x9e37_79b9,
+            variations: vec![],
+            generation: Arc::new(AtomicU64::new(0)),
+            job: None,
+        }
+    }
+
+    pub fn running(&self) -> bool {
+        self.job
+            .as_ref()
+            .is_some_and(|(handle, _)| !handle.is_finished())
+    }
+
+    /// Rotation, scale and offset of `params`, each moved by a random amount
+    /// up to the spread. The first variation is `params` itself.
+    fn vary(&mut self, params: &Params) -> Vec<Params> {
+        let spread = self.spread;
+        let state = &mut self.seed;
+        let mut variations = vec![*params];
+        for _ in 1..COUNT {
+            let mut p = *params;
+            p.rotation = (
+                wrap_degrees(p.rotation.0 + random(state) * 180.0 * spread),
+                wrap_degrees(p.rotation.1 + random(state) * 90.0 * spread),
+                wrap_degrees(p.rotation.2 + random(state) * 180.0 * spread),
+            );
+            p.scale = (p.scale * 2f32.powf(random(state) * spread)).clamp(0.1, 10.0);
+            p.offset = (
+                (p.offset.0 + random(state) * 0.5 * spread).clamp(-1.0, 1.0),
+                (p.offset.1 + random(state) * 0.5 * spread).clamp(-1.0, 1.0),
+            );
+            variations.push(p);
+        }
+        variations
+    }
+
+    /// Renders new variations of `params` on a background thread, each as
+    /// `base` with its own rotation, scale and offset.
+    pub fn generate(&mut self, ctx: &Context, base: RenderRequest, params: &Params) {
+        let cancel = CancelToken::next(&self.generation);
+        let variations = self.vary(params);
+        let (width, height) = params.size;
+        let k = THUMBNAIL_SIZE as f32 / width.max(height) as f32;
+        let size = (
+            (width as f32 * k).round().max(1.0) as u32,
+            (height as f32 * k).round().max(1.0) as u32,
+        );
+        let requests: Vec<_> = variations
+            .iter()
+            .map(|p| RenderRequest {
+                offset: vector![p.offset.0, p.offset.1],
+                rotation: p.view_rotation(),
+                scale: p.scale,
+                remap: None,
+                ..base.clone()
+            })
+            .collect();
+        self.variations = variations.into_iter().map(|p| (p, None)).collect();
+
+        let (sender, receiver) = mpsc::channel();
+        let ctx = ctx.clone();
+        let handle = thread::spawn(move || {
+            for (i, request) in requests.iter().enumerate() {
+                let Ok(out) = request.render(size, &cancel, None) else {
+                    return;
+                };
+                let out: RgbaImage = out.convert();
+                let dims = [out.width() as usize, out.height() as usize];
+                let image = ColorImage::from_rgba_unmultiplied(dims, out.as_raw());
+                if sender.send((i, image)).is_err() {
+                    return;
+                }
+                ctx.request_repaint();
+            }
+        });
+        self.job = Some((handle, receiver));
+    }
+
+    /// Stops rendering and forgets the variations.
+    pub fn clear(&mut self) {
+        CancelToken::next(&self.generation);
+        self.variations.clear();
+        self.job = None;
+    }
+
+    /// Shows the grid of thumbnails, returning the parameters of the one
+    /// clicked.
+    pub fn show(&mut self, ui: &mut Ui) -> Option<Params> {
+        if let Some((_, thumbnails)) = &self.job {
+            for (i, image) in thumbnails.try_iter() {
+                let name = format!("explore {i}");
+                let texture = ui.ctx().load_texture(name, image, Default::default());
+                self.variations[i].1 = Some(texture);
+            }
+        }
+        let columns = (COUNT as f32).sqrt().ceil() as usize;
+        let mut picked = None;
+        egui::Grid::new("explore").show(ui, |ui| {
+            for (i, (params, texture)) in self.variations.iter().enumerate() {
+                let (width, height) = params.size;
+                let k = THUMBNAIL_SIZE as f32 / width.max(height) as f32;
+                let size = Vec2::new(width as f32 * k, height as f32 * k);
+                match texture {
+                    Some(texture) => {
+                        let button = ImageButton::new((texture.id(), size));
+                        let (x, y, z) = params.rotation;
+                        let response = ui.add(button).on_hover_text(trf(
+                            "Rotation {}°, {}°, {}°\nScale {}",
+                            &[
+                                &format!("{x:.0}"),
+                                &format!("{y:.0}"),
+                                &format!("{z:.0}"),
+                                &format!("{:.2}", params.scale),
+                            ],
+                        ));
+                        if response.clicked() {
+                            picked = Some(*params);
+                        }
+                    }
+                    None => {
+                        ui.add_sized(size, egui::Spinner::new());
+                    }
+                }
+                if (i + 1) % columns == 0 {
+                    ui.end_row();
+                }
+            }
+        });
+        picked
+    }
+}
@@ -0,0 +1,478 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Seek, Write},
+    ops::Range,
+    path::Path,
+};
+
+use exr::{
+    block::{writer::ChunksWriter, UncompressedBlock},
+    meta::{
+        attribute::{ChannelDescription, LineOrder, SampleType, Text},
+        header::Header,
+        BlockDescription,
+    },
+    prelude::Compression,
+};
+use image::{
+    buffer::ConvertBuffer,
+    codecs::{jpeg::JpegEncoder, webp::WebPEncoder},
+    imageops, ImageBuffer, ImageResult, RgbImage, Rgba, Rgba32FImage, RgbaImage,
+};
+use tiff::encoder::{colortype, TiffEncoder, TiffKind};
+
+use crate::{
+    i18n::{tr, trf},
+    icc,
+    render::{CancelToken, RenderError, RenderRequest, MAX_PREVIEW_SIZE},
+    stereo::StereoLayout,
+    toml::{Table, Value},
+};
+
+/// Output rows rendered at a time by [`save_tiled`] and posters.
+pub const TILE_ROWS: u32 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Jpeg,
+    /// Always lossless: the encoder of `image` has no lossy mode, so there
+    /// is no quality to set.
+    WebP,
+    Tiff,
+    Exr,
+}
+
+impl Format {
+    pub const ALL: [Format; 5] = [
+        Format::Png,
+        Format::Jpeg,
+        Format::WebP,
+        Format::Tiff,
+        Format::Exr,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Png => "PNG",
+            Format::Jpeg => "JPEG",
+            Format::WebP => "WebP",
+            Format::Tiff => "TIFF",
+            Format::Exr => "OpenEXR",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Format::ALL.into_iter().find(|x| x.name() == name)
+    }
+
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Format::Png => &["png"],
+            Format::Jpeg => &["jpg", "jpeg"],
+            Format::WebP => &["webp"],
+            Format::Tiff => &["tif", "tiff"],
+            Format::Exr => &["exr"],
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        Format::ALL
+            .into_iter()
+            .find(|format| format.extensions().contains(&ext.as_str()))
+    }
+
+    /// Whether the format can store 16 bits per channel; EXR always stores
+    /// floating point.
+    pub fn has_sixteen_bit(self) -> bool {
+        matches!(self, Format::Png | Format::Tiff)
+    }
+
+    pub fn has_quality(self) -> bool {
+        matches!(self, Format::Jpeg)
+    }
+
+    /// Whether [`save_tiled`] can write the image a band at a time. The JPEG
+    /// and WebP encoders need the whole image, so they are limited to sizes
+    /// that aren't tiled.
+    pub fn streams(self) -> bool {
+        matches!(self, Format::Png | Format::Tiff | Format::Exr)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExportOptions {
+    pub format: Format,
+    pub sixteen_bit: bool,
+    /// JPEG quality, from 1 to 100.
+    pub quality: u8,
+    /// Whether the metadata of the source is copied into JPEG and PNG files.
+    pub metadata: bool,
+    /// Whether panoramas are tagged as such for panorama viewers.
+    pub gpano: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: Format::Png,
+            sixteen_bit: false,
+            quality: 90,
+            metadata: true,
+            gpano: true,
+        }
+    }
+}
+
+impl ExportOptions {
+    pub fn write(&self, table: &mut Table, prefix: &str) {
+        table.insert(format!("{prefix}format"), self.format.name());
+        table.insert(format!("{prefix}sixteen_bit"), self.sixteen_bit);
+        table.insert(format!("{prefix}quality"), self.quality as u32);
+        table.insert(format!("{prefix}metadata"), self.metadata);
+        table.insert(format!("{prefix}gpano"), self.gpano);
+    }
+
+    /// Reads the options written by [`ExportOptions::write`], keeping the
+    /// defaults for anything that is missing or malformed.
+    pub fn read(table: &Table, prefix: &str) -> Self {
+        let get = |key: &str| table.get(&format!("{prefix}{key}"));
+        let mut options = ExportOptions::default();
+        if let Some(format) = get("format").and_then(Value::as_str) {
+            options.format = Format::from_name(format).unwrap_or(options.format);
+        }
+        if let Some(sixteen_bit) = get("sixteen_bit").and_then(Value::as_bool) {
+            options.sixteen_bit = sixteen_bit;
+        }
+        if let Some(quality) = get("quality").and_then(Value::as_u32) {
+            options.quality = quality.clamp(1, 100) as u8;
+        }
+        if let Some(metadata) = get("metadata").and_then(Value::as_bool) {
+            options.metadata = metadata;
+        }
+        if let Some(gpano) = get("gpano").and_then(Value::as_bool) {
+            options.gpano = gpano;
+        }
+        options
+    }
+}
+
+/// Saves `img` in the format of `options`, or in the format implied by the
+/// extension of `path` when it has a known one.
+pub fn save(img: &Rgba32FImage, path: &Path, options: &ExportOptions) -> ImageResult<()> {
+    let format = Format::from_path(path).unwrap_or(options.format);
+    let image_format = match format {
+        Format::Png => image::ImageFormat::Png,
+        Format::Jpeg => image::ImageFormat::Jpeg,
+        Format::WebP => image::ImageFormat::WebP,
+        Format::Tiff => image::ImageFormat::Tiff,
+        Format::Exr => image::ImageFormat::OpenExr,
+    };
+    match format {
+        Format::Exr => {
+            let mut img = img.clone();
+            for p in img.pixels_mut() {
+                *p = image::Rgba(linear(p.0));
+            }
+            img.save_with_format(path, image_format)
+        }
+        Format::Jpeg => {
+            let img: RgbImage = img.convert();
+            let mut w = BufWriter::new(File::create(path)?);
+            JpegEncoder::new_with_quality(&mut w, options.quality).encode_image(&img)
+        }
+        Format::WebP => {
+            let img: RgbaImage = img.convert();
+            let w = BufWriter::new(File::create(path)?);
+            WebPEncoder::new_lossless(w).encode(
+                img.as_raw(),
+                img.width(),
+                img.height(),
+                image::ColorType::Rgba8,
+            )
+        }
+        _ if options.sixteen_bit && format.has_sixteen_bit() => {
+            let img: ImageBuffer<Rgba<u16>, Vec<u16>> = img.convert();
+            img.save_with_format(path, image_format)
+        }
+        _ => {
+            let img: RgbaImage = img.convert();
+            img.save_with_format(path, image_format)
+        }
+    }
+}
+
+/// Renders are encoded into sRGB, for the screen and the integer formats;
+/// OpenEXR holds linear light, so their colors are decoded again.
+fn linear([r, g, b, a]: [f32; 4]) -> [f32; 4] {
+    let [r, g, b] = [r, g, b].map(icc::srgb_to_linear);
+    [r, g, b, a]
+}
+
+pub fn tile_count(size: (u32, u32)) -> u32 {
+    size.1.div_ceil(TILE_ROWS)
+}
+
+/// Renders and saves `request` in bands of rows, passing the number of
+/// finished bands to `progress`. PNG, TIFF and EXR bands are streamed into
+/// the encoder as they are done, so only one band is ever kept in memory;
+/// JPEG and WebP images are assembled before being saved, and refused when
+/// they are too large to be rendered at once. A canceled export leaves no
+/// file behind.
+pub fn save_tiled(
+    request: &RenderRequest,
+    path: &Path,
+    options: &ExportOptions,
+    cancel: &CancelToken,
+    progress: impl Fn(u32),
+) -> io::Result<()> {
+    let size = request.size;
+    let render = |rows| request.render_rows(size, rows, cancel, None);
+    save_bands(size, render, path, options, progress)
+}
+
+/// [`save_tiled`] for the requests of both eyes, saved side by side or one
+/// over the other as `layout` puts them, like [`StereoLayout::combine`].
+/// Mono keeps the left eye.
+pub fn save_stereo(
+    layout: StereoLayout,
+    requests: &[RenderRequest; 2],
+    path: &Path,
+    options: &ExportOptions,
+    cancel: &CancelToken,
+    progress: impl Fn(u32),
+) -> io::Result<()> {
+    let eye = requests[0].size;
+    let size = layout.size(eye);
+    let offsets = match layout {
+        StereoLayout::Mono => [Some((0, 0)), None],
+        StereoLayout::OverUnder => [Some((0, 0)), Some((0, eye.1))],
+        StereoLayout::SideBySide => [Some((0, 0)), Some((eye.0, 0))],
+    };
+    let render = |rows: Range<u32>| {
+        let mut band = Rgba32FImage::new(size.0, rows.len() as u32);
+        for (request, offset) in requests.iter().zip(offsets) {
+            let Some((x, y)) = offset else {
+                continue;
+            };
+            // The rows of the band that this eye covers, in its own rows.
+            let start = rows.start.max(y).min(y + eye.1);
+            let end = rows.end.max(y).min(y + eye.1);
+            if start < end {
+                let part = request.render_rows(eye, start - y..end - y, cancel, None)?;
+                imageops::replace(&mut band, &part, x as i64, (start - rows.start) as i64);
+            }
+        }
+        Ok(band)
+    };
+    save_bands(size, render, path, options, progress)
+}
+
+/// Saves an image of `size` made of the bands of rows that `render` returns.
+fn save_bands(
+    size: (u32, u32),
+    render: impl Fn(Range<u32>) -> Result<Rgba32FImage, RenderError>,
+    path: &Path,
+    options: &ExportOptions,
+    progress: impl Fn(u32),
+) -> io::Result<()> {
+    let format = Format::from_path(path).unwrap_or(options.format);
+    if !format.streams() && size.0.max(size.1) > MAX_PREVIEW_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            trf(
+                "{} images can't be larger than {} pixels; save them as PNG, TIFF or \
+                 OpenEXR",
+                &[&format.name(), &MAX_PREVIEW_SIZE],
+            ),
+        ));
+    }
+    if size.0 == 0 || size.1 == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            tr("the output has no pixels"),
+        ));
+    }
+    let bands = (0..tile_count(size)).map(|i| {
+        let rows = i * TILE_ROWS..((i + 1) * TILE_ROWS).min(size.1);
+        let band = render(rows)
+            .map_err(|_| io::Error::new(io::ErrorKind::Interrupted, tr("export canceled")))?;
+        progress(i + 1);
+        Ok(band)
+    });
+
+    let file = BufWriter::new(File::create(path)?);
+    // The encoders own the file, so it is closed by the time this returns and
+    // a canceled export can be removed, which Windows refuses while it is open.
+    let result = match format {
+        Format::Png => save_png(file, size, options.sixteen_bit, bands),
+        Format::Tiff => {
+            // Classic TIFF offsets are 32 bits, too few for the largest outputs.
+            let bytes = size.0 as u64 * size.1 as u64 * if options.sixteen_bit { 8 } else { 4 };
+            if bytes < u32::MAX as u64 {
+                let encoder = TiffEncoder::new(file).map_err(io::Error::other)?;
+                save_tiff(encoder, size, options.sixteen_bit, bands)
+            } else {
+                let encoder = TiffEncoder::new_big(file).map_err(io::Error::other)?;
+                save_tiff(encoder, size, options.sixteen_bit, bands)
+            }
+        }
+        Format::Exr => save_exr(file, size, bands),
+        Format::Jpeg | Format::WebP => {
+            drop(file);
+            let mut out = Rgba32FImage::new(size.0, size.1);
+            bands
+                .enumerate()
+                .try_for_each(|(i, band)| {
+                    imageops::replace(&mut out, &band?, 0, i as i64 * TILE_ROWS as i64);
+                    Ok(())
+                })
+                .and_then(|()| save(&out, path, options).map_err(io::Error::other))
+        }
+    };
+    if result
+        .as_ref()
+        .is_err_and(|e| e.kind() == io::ErrorKind::Interrupted)
+    {
+        fs::remove_file(path).ok();
+    }
+    result
+}
+
+fn save_png(
+    file: impl Write,
+    size: (u32, u32),
+    sixteen_bit: bool,
+    bands: impl Iterator<Item = io::Result<Rgba32FImage>>,
+) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(file, size.0, size.1);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(if sixteen_bit {
+        png::BitDepth::Sixteen
+    } else {
+        png::BitDepth::Eight
+    });
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    let mut stream = writer.stream_writer().map_err(io::Error::other)?;
+    for band in bands {
+        let band = band?;
+        if sixteen_bit {
+            let band: ImageBuffer<Rgba<u16>, Vec<u16>> = band.convert();
+            let bytes: Vec<u8> = band.as_raw().iter().flat_map(|c| c.to_be_bytes()).collect();
+            stream.write_all(&bytes)?;
+        } else {
+            let band: RgbaImage = band.convert();
+            stream.write_all(band.as_raw())?;
+        }
+    }
+    stream.finish().map_err(io::Error::other)
+}
+
+/// Writes each band as one strip.
+fn save_tiff<W: Write + Seek, K: TiffKind>(
+    mut encoder: TiffEncoder<W, K>,
+    size: (u32, u32),
+    sixteen_bit: bool,
+    bands: impl Iterator<Item = io::Result<Rgba32FImage>>,
+) -> io::Result<()> {
+    if sixteen_bit {
+        let mut image = encoder
+            .new_image::<colortype::RGBA16>(size.0, size.1)
+            .map_err(io::Error::other)?;
+        image.rows_per_strip(TILE_ROWS).map_err(io::Error::other)?;
+        for band in bands {
+            let band: ImageBuffer<Rgba<u16>, Vec<u16>> = band?.convert();
+            image.write_strip(band.as_raw()).map_err(io::Error::other)?;
+        }
+        image.finish().map_err(io::Error::other)
+    } else {
+        let mut image = encoder
+            .new_image::<colortype::RGBA8>(size.0, size.1)
+            .map_err(io::Error::other)?;
+        image.rows_per_strip(TILE_ROWS).map_err(io::Error::other)?;
+        for band in bands {
+            let band: RgbaImage = band?.convert();
+            image.write_strip(band.as_raw()).map_err(io::Error::other)?;
+        }
+        image.finish().map_err(io::Error::other)
+    }
+}
+
+/// Writes 32-bit float scan lines, compressed in blocks of 16 lines, which
+/// divide [`TILE_ROWS`] so that no block straddles two bands. Colors are
+/// decoded into linear light like those of [`save`].
+fn save_exr(
+    file: impl Write + Seek,
+    size: (u32, u32),
+    mut bands: impl Iterator<Item = io::Result<Rgba32FImage>>,
+) -> io::Result<()> {
+    // Channels are stored in alphabetical order, and only alpha is linear.
+    let names = ["A", "B", "G", "R"];
+    let channels = names.map(|name| ChannelDescription::new(name, SampleType::F32, name == "A"));
+    let header = Header::new(
+        Text::from("planet"),
+        (size.0 as usize, size.1 as usize),
+        channels.into_iter().collect(),
+    )
+    .with_encoding(
+        Compression::ZIP16,
+        BlockDescription::ScanLines,
+        LineOrder::Increasing,
+    );
+    let mut error = None;
+    let written = exr::block::write(
+        file,
+        [header].into_iter().collect(),
+        true,
+        |meta, writer| {
+            let mut band: Option<(usize, Rgba32FImage)> = None;
+            let mut blocks =
+                meta.enumerate_ordered_header_block_indices()
+                    .map_while(|(i, index)| {
+                        let y = index.pixel_position.y();
+                        let inside =
+                            |(start, b): &(usize, Rgba32FImage)| y < start + b.height() as usize;
+                        if !band.as_ref().is_some_and(inside) {
+                            match bands.next()? {
+                                Ok(next) => band = Some((y, next)),
+                                Err(e) => {
+                                    error = Some(e);
+                                    return None;
+                                }
+                            }
+                        }
+                        let (start, band) = band.as_ref()?;
+                        let width = band.width() as usize;
+                        let block = UncompressedBlock::from_lines(
+                            &meta.headers[0].channels,
+                            index,
+                            |line| {
+                                let row = line.location.position.y() - start;
+                                let channel = 3 - line.location.channel;
+                                line.write_samples(|x| {
+                                    let i = (row * width + x) * 4;
+                                    let pixel = band.as_raw()[i..i + 4].try_into().unwrap();
+                                    linear(pixel)[channel]
+                                })
+                                .expect("lines are sized for their samples");
+                            },
+                        );
+                        Some((i, block))
+                    });
+            // Compressed on a pool of its own, or on this thread where there
+            // are no threads.
+            if let Some(mut compressor) = writer.parallel_blocks_compressor(&meta) {
+                return blocks.try_for_each(|(i, block)| {
+                    compressor.add_block_to_compression_queue(i, block)
+                });
+            }
+            let mut compressor = writer.sequential_blocks_compressor(&meta);
+            blocks.try_for_each(|(i, block)| compressor.compress_block(i, block))
+        },
+    );
+    match error {
+        Some(e) => Err(e),
+        None => written.map_err(io::Error::other),
+    }
+}
@@ -0,0 +1,102 @@
+use image::Rgba32FImage;
+use nalgebra::{vector, SVector, Unit};
+use rayon::prelude::*;
+
+use crate::{
+    projection,
+    sampler::{self, EdgeMode, Sampler},
+};
+
+type Vec3f = SVector<f32, 3>;
+
+/// Raw frames of 360 cameras: two equidistant fisheye circles side by side,
+/// the left one looking to the front and the right one to the back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DualFisheye {
+    /// Field of view of each lens, in degrees.
+    pub fov: f32,
+    /// Width of the band around the seam where both lenses are blended, in
+    /// degrees.
+    pub overlap: f32,
+}
+
+impl Default for DualFisheye {
+    fn default() -> Self {
+        Self {
+            fov: 190.0,
+            overlap: 6.0,
+        }
+    }
+}
+
+impl DualFisheye {
+    /// Samples one lens along direction `d`, given the lens axis and the
+    /// directions of the right and up edges of its circle.
+    fn sample_lens(
+        &self,
+        img: &Rgba32FImage,
+        sampler: Sampler,
+        center: (f32, f32),
+        axes: [Vec3f; 3],
+        d: &Vec3f,
+    ) -> SVector<f32, 4> {
+        let [axis, right, up] = axes;
+        let radius = img.height().min(img.width() / 2) as f32 / 2.0;
+        let theta = d.dot(&axis).clamp(-1.0, 1.0).acos();
+        let (x, y) = (d.dot(&right), d.dot(&up));
+        let rho = x.hypot(y);
+        let r = if rho > 0.0 {
+            theta / (self.fov.to_radians() / 2.0) * radius / rho
+        } else {
+            0.0
+        };
+        sampler.sample(
+            img,
+            center.0 + x * r,
+            center.1 - y * r,
+            EdgeMode::Transparent,
+        )
+    }
+
+    /// Stitches both lenses into a 2:1 equirectangular panorama as tall as
+    /// the fisheye circles.
+    pub fn stitch(&self, img: &Rgba32FImage, sampler: Sampler) -> Rgba32FImage {
+        let (w, h) = (img.width() as f32, img.height() as f32);
+        let (x, y, z) = (Vec3f::x(), Vec3f::y(), Vec3f::z());
+        let front = ((w / 4.0, h / 2.0), [y, x, z]);
+        let back = ((w * 3.0 / 4.0, h / 2.0), [-y, -x, z]);
+        let half_fov = self.fov.to_radians() / 2.0;
+        // The blend can't extend beyond what the lenses actually see.
+        let band = (self.overlap.to_radians() / 2.0).min(half_fov - std::f32::consts::FRAC_PI_2);
+
+        let height = img.height().min(img.width() / 2);
+        let width = height * 2;
+        let mut out = Rgba32FImage::new(width, height);
+        out.par_chunks_mut(width as usize * 4)
+            .enumerate()
+            .for_each(|(row, line)| {
+                for (col, pixel) in line.chunks_exact_mut(4).enumerate() {
+                    let p = vector![col as f32 / width as f32, row as f32 / height as f32];
+                    let d: Unit<Vec3f> = projection::equirect_to_sphere(p);
+                    // Angle from the seam towards the front lens.
+                    let angle = d.y.clamp(-1.0, 1.0).asin();
+                    let weight = if band > 0.0 {
+                        ((angle + band) / (2.0 * band)).clamp(0.0, 1.0)
+                    } else if angle >= 0.0 {
+                        1.0
+                    } else {
+                        0.0
+                    };
+                    let mut q = SVector::<f32, 4>::zeros();
+                    if weight > 0.0 {
+                        q += self.sample_lens(img, sampler, front.0, front.1, &d) * weight;
+                    }
+                    if weight < 1.0 {
+                        q += self.sample_lens(img, sampler, back.0, back.1, &d) * (1.0 - weight);
+                    }
+                    pixel.copy_from_slice(&sampler::unpremultiply(q).0);
+                }
+            });
+        out
+    }
+}
@@ -0,0 +1,119 @@
+//! A font with Chinese characters, found among the fonts of the system since
+//! the ones built into egui have none.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use egui::{Context, FontData, FontDefinitions, FontFamily};
+
+/// Known CJK fonts of Windows and macOS, in order of preference.
+const SYSTEM_FONTS: [&str; 7] = [
+    "C:\\Windows\\Fonts\\msyh.ttc",
+    "C:\\Windows\\Fonts\\simhei.ttf",
+    "C:\\Windows\\Fonts\\simsun.ttc",
+    "/System/Library/Fonts/PingFang.ttc",
+    "/System/Library/Fonts/Hiragino Sans GB.ttc",
+    "/System/Library/Fonts/STHeiti Light.ttc",
+    "/Library/Fonts/Arial Unicode.ttf",
+];
+
+/// Parts of the file names of CJK fonts common on Linux, in order of
+/// preference, matched against lowercase names.
+const FONT_NAMES: [&str; 7] = [
+    "notosanscjk",
+    "notosanssc",
+    "sourcehansans",
+    "wqy-microhei",
+    "wqy-zenhei",
+    "droidsansfallback",
+    "uming",
+];
+
+/// Directories searched for fonts named like [`FONT_NAMES`].
+fn font_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![
+        PathBuf::from("/usr/share/fonts"),
+        PathBuf::from("/usr/local/share/fonts"),
+    ];
+    if let Some(home) = env::var_os("HOME") {
+        let home = PathBuf::from(home);
+        dirs.push(home.join(".local/share/fonts"));
+        dirs.push(home.join(".fonts"));
+    }
+    dirs
+}
+
+/// Font files below `dir`.
+fn walk(dir: &Path, out: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+        if path.is_dir() {
+            walk(&path, out);
+        } else if path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| ["ttf", "otf", "ttc"].contains(&e.to_ascii_lowercase().as_str()))
+        {
+            out.push(path);
+        }
+    }
+}
+
+/// The path of the preferred CJK font of the system. Among the files of one
+/// font, the regular weight is taken when there is one.
+pub fn find() -> Option<PathBuf> {
+    if let Some(path) = SYSTEM_FONTS
+        .into_iter()
+        .map(PathBuf::from)
+        .find(|p| p.is_file())
+    {
+        return Some(path);
+    }
+    let mut files = vec![];
+    for dir in font_dirs() {
+        walk(&dir, &mut files);
+    }
+    let rank = |path: &PathBuf| {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        let font = FONT_NAMES.iter().position(|n| name.contains(n))?;
+        let regular = name.contains("regular") || !name.contains('-');
+        Some((font, !regular, name.len()))
+    };
+    files
+        .into_iter()
+        .filter_map(|path| Some((rank(&path)?, path)))
+        .min()
+        .map(|(_, path)| path)
+}
+
+/// The bytes of the font of [`find`], read once.
+pub fn cjk() -> Option<&'static [u8]> {
+    static FONT: OnceLock<Option<Vec<u8>>> = OnceLock::new();
+    let font = FONT.get_or_init(|| fs::read(find()?).ok());
+    font.as_deref()
+}
+
+/// Adds the CJK font to the fonts of `ctx`, after egui's own so that it is
+/// only used for the characters they lack.
+pub fn install(ctx: &Context) {
+    let Some(font) = cjk() else {
+        return;
+    };
+    let mut fonts = FontDefinitions::default();
+    fonts
+        .font_data
+        .insert("cjk".to_owned(), FontData::from_static(font));
+    for family in [FontFamily::Proportional, FontFamily::Monospace] {
+        fonts
+            .families
+            .entry(family)
+            .or_default()
+            .push("cjk".to_owned());
+    }
+    ctx.set_fonts(fonts);
+}
@@ -0,0 +1,83 @@
+use std::f32::consts::PI;
+
+use egui::{Color32, Response, Sense, Stroke, Ui, Vec2, Widget};
+use nalgebra::{vector, Rotation3, Unit};
+
+/// Rotation that makes the sphere follow a drag of `delta` on an area whose
+/// shorter side is `extent`; dragging across the whole area turns it by π.
+pub fn drag_rotation(delta: Vec2, extent: f32) -> Rotation3<f32> {
+    let axis = vector![delta.y, -delta.x, 0.0];
+    let angle = delta.length() / extent * PI;
+    Unit::try_new(axis, f32::EPSILON).map_or_else(Rotation3::identity, |axis| {
+        Rotation3::from_axis_angle(&axis, angle)
+    })
+}
+
+/// A sphere showing the panorama's axes as seen from the output, which can be
+/// dragged like a trackball to rotate it.
+pub struct Gizmo<'a> {
+    rotation: &'a mut Rotation3<f32>,
+    size: f32,
+}
+
+impl<'a> Gizmo<'a> {
+    pub fn new(rotation: &'a mut Rotation3<f32>) -> Self {
+        Self {
+            rotation,
+            size: 96.0,
+        }
+    }
+}
+
+impl Widget for Gizmo<'_> {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let (rect, mut response) = ui.allocate_exact_size(Vec2::splat(self.size), Sense::drag());
+        let delta = response.drag_delta();
+        if delta != Vec2::ZERO {
+            *self.rotation *= drag_rotation(delta, self.size);
+            response.mark_changed();
+        }
+
+        if ui.is_rect_visible(rect) {
+            let visuals = ui.style().interact(&response);
+            let painter = ui.painter_at(rect);
+            let center = rect.center();
+            let radius = self.size / 2.0 - 8.0;
+            painter.circle(center, radius, visuals.bg_fill, visuals.fg_stroke);
+
+            let axes = [
+                ("X", Color32::from_rgb(220, 70, 70)),
+                ("Y", Color32::from_rgb(70, 180, 70)),
+                ("Z", Color32::from_rgb(70, 110, 230)),
+            ];
+            let mut ends: Vec<_> = axes
+                .into_iter()
+                .enumerate()
+                .map(|(i, axis)| {
+                    let d = self.rotation.inverse() * nalgebra::Vector3::ith(i, 1.0);
+                    (d, axis)
+                })
+                .collect();
+            // Draw the axes pointing away from the viewer first.
+            ends.sort_by(|a, b| b.0.z.total_cmp(&a.0.z));
+            for (d, (name, color)) in ends {
+                let end = center + Vec2::new(d.x, -d.y) * radius;
+                let color = if d.z > 0.0 {
+                    color.gamma_multiply(0.4)
+                } else {
+                    color
+                };
+                painter.line_segment([center, end], Stroke::new(2.0, color));
+                painter.circle_filled(end, 3.0, color);
+                painter.text(
+                    center + Vec2::new(d.x, -d.y) * (radius + 7.0),
+                    egui::Align2::CENTER_CENTER,
+                    name,
+                    egui::FontId::proportional(10.0),
+                    color,
+                );
+            }
+        }
+        response
+    }
+}
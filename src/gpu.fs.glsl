@@ -0,0 +1,145 @@
+uniform sampler2D u_source;
+uniform int u_kind;
+uniform float u_radius;
+uniform vec2 u_image_size;
+uniform vec2 u_proj_size;
+uniform vec2 u_offset;
+uniform mat3 u_rotation;
+
+out vec4 out_color;
+
+const float PI = 3.14159265358979;
+const float SQRT_2 = 1.41421356237310;
+
+const int STEREOGRAPHIC = 0;
+const int RECTILINEAR = 1;
+const int EQUIDISTANT = 2;
+const int EQUISOLID = 3;
+const int PANNINI = 4;
+const int MERCATOR = 5;
+const int ORTHOGRAPHIC = 6;
+const int INVERSE = 7;
+
+bool valid;
+
+vec2 pixel_to_plane(vec2 p) {
+    return p + (u_offset - 0.5) * u_proj_size;
+}
+
+vec2 plane_to_pixel(vec2 p) {
+    return p - (u_offset - 0.5) * u_proj_size;
+}
+
+vec2 sphere_to_image(vec3 p) {
+    p = normalize(u_rotation * p);
+    float row = acos(clamp(p.z, -1.0, 1.0)) / PI;
+    float col = atan(p.x, p.y) / (2.0 * PI) + 0.5;
+    return vec2(col, row) * u_image_size;
+}
+
+vec3 image_to_sphere(vec2 p) {
+    p /= u_image_size;
+    float theta = p.y * PI;
+    float phi = (p.x - 0.5) * 2.0 * PI;
+    vec3 d = vec3(sin(theta) * sin(phi), sin(theta) * cos(phi), cos(theta));
+    return transpose(u_rotation) * d;
+}
+
+vec3 radial(vec2 p, float theta, float rho) {
+    vec2 dir = rho > 0.0 ? p * (sin(theta) / rho) : vec2(0.0);
+    return vec3(dir, cos(theta));
+}
+
+vec2 proj(vec2 p) {
+    if (u_kind == INVERSE) {
+        vec3 s = image_to_sphere(p);
+        return plane_to_pixel(s.xy * (u_radius / (s.z + 1.0)));
+    }
+
+    p = pixel_to_plane(p);
+    if (u_kind == STEREOGRAPHIC) {
+        float r2 = u_radius * u_radius;
+        float k = 2.0 * r2 / (dot(p, p) + r2);
+        return sphere_to_image(vec3(k * p, (k - 1.0) * u_radius));
+    }
+
+    p /= u_radius;
+    float rho = length(p);
+    if (u_kind == RECTILINEAR) {
+        return sphere_to_image(radial(p, atan(rho), rho));
+    }
+    if (u_kind == EQUIDISTANT) {
+        valid = rho <= 2.0;
+        return sphere_to_image(radial(p, rho * PI / 2.0, rho));
+    }
+    if (u_kind == EQUISOLID) {
+        valid = rho <= SQRT_2;
+        return sphere_to_image(radial(p, 2.0 * asin(min(rho / SQRT_2, 1.0)), rho));
+    }
+    if (u_kind == PANNINI) {
+        float k = p.x * p.x / 4.0;
+        float dscr = k * k - (k + 1.0) * (k - 1.0);
+        valid = dscr >= 0.0;
+        float clon = (-k + sqrt(max(dscr, 0.0))) / (k + 1.0);
+        float s = 2.0 / (1.0 + clon);
+        float lon = atan(p.x, s * clon);
+        return sphere_to_image(vec3(sin(lon), p.y / s, cos(lon)));
+    }
+    if (u_kind == MERCATOR) {
+        float lat = atan(sinh(p.y));
+        return sphere_to_image(vec3(cos(lat) * sin(p.x), sin(lat), cos(lat) * cos(p.x)));
+    }
+    // ORTHOGRAPHIC
+    valid = rho <= 1.0;
+    return sphere_to_image(radial(p, asin(min(rho, 1.0)), rho));
+}
+
+// The source is uploaded premultiplied in linear light and blended texel by
+// texel, like `fetch` and `bilinear_interpolation` of the CPU samplers: across the
+// bottom and top rows is the opposite meridian, and inverse projections,
+// which are transparent off the image, are clamped to its edge.
+vec4 fetch(ivec2 p, ivec2 size) {
+    if (u_kind != INVERSE) {
+        if (p.y < 0) {
+            p = ivec2(p.x + size.x / 2, -1 - p.y);
+        } else if (p.y >= size.y) {
+            p = ivec2(p.x + size.x / 2, 2 * size.y - 1 - p.y);
+        }
+        p.x -= size.x * int(floor(float(p.x) / float(size.x)));
+    }
+    return texelFetch(u_source, clamp(p, ivec2(0), size - 1), 0);
+}
+
+vec4 bilinear(vec2 p, ivec2 size) {
+    vec2 p1 = floor(p);
+    vec2 f = p - p1;
+    ivec2 i = ivec2(p1);
+    vec4 r1 = mix(fetch(i, size), fetch(i + ivec2(1, 0), size), f.x);
+    vec4 r2 = mix(fetch(i + ivec2(0, 1), size), fetch(i + ivec2(1, 1), size), f.x);
+    return mix(r1, r2, f.y);
+}
+
+vec3 linear_to_srgb(vec3 c) {
+    vec3 low = max(c, 0.0) * 12.92;
+    vec3 high = 1.055 * pow(max(c, 0.0), vec3(1.0 / 2.4)) - 0.055;
+    return mix(low, high, greaterThan(c, vec3(0.0031308)));
+}
+
+void main() {
+    valid = true;
+    vec2 p = proj(gl_FragCoord.xy - 0.5);
+    if (!valid) {
+        out_color = vec4(0.0);
+        return;
+    }
+    ivec2 size = textureSize(u_source, 0);
+    vec2 last = vec2(size) - 0.5;
+    bool outside = any(lessThan(p, vec2(-0.5))) || any(greaterThan(p, last));
+    if (u_kind == INVERSE && outside) {
+        out_color = vec4(0.0);
+        return;
+    }
+    vec4 q = bilinear(p, size);
+    vec3 rgb = q.a > 0.0 ? linear_to_srgb(q.rgb / q.a) : vec3(0.0);
+    out_color = vec4(rgb, min(q.a, 1.0));
+}
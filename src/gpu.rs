@@ -0,0 +1,295 @@
+use std::sync::Arc;
+
+use eframe::{
+    egui_glow::ShaderVersion,
+    glow::{self, HasContext},
+};
+use image::Rgba32FImage;
+
+use crate::{
+    icc,
+    render::RenderRequest,
+    sampler::{EdgeMode, Sampler},
+};
+
+const VERTEX_SHADER: &str = r#"
+void main() {
+    vec2 pos = vec2(float((gl_VertexID << 1) & 2), float(gl_VertexID & 2));
+    gl_Position = vec4(pos * 2.0 - 1.0, 0.0, 1.0);
+}
+"#;
+
+const FRAGMENT_SHADER: &str = include_str!("gpu.fs.glsl");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Auto,
+    Cpu,
+    Gpu,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 3] = [Backend::Auto, Backend::Cpu, Backend::Gpu];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Auto => "Auto",
+            Backend::Cpu => "CPU",
+            Backend::Gpu => "GPU",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Backend::ALL.into_iter().find(|x| x.name() == name)
+    }
+}
+
+/// Evaluates the projection per-pixel in a fragment shader on eframe's own
+/// GL context, so it must be driven from the UI thread.
+pub struct GpuRenderer {
+    gl: Arc<glow::Context>,
+    program: glow::Program,
+    vao: glow::VertexArray,
+    max_texture_size: u32,
+    source: Option<(Arc<Rgba32FImage>, bool, glow::Texture)>,
+}
+
+impl GpuRenderer {
+    pub fn new(gl: Arc<glow::Context>) -> Result<Self, String> {
+        let version = ShaderVersion::get(&gl);
+        if !version.is_new_shader_interface() {
+            return Err(format!("unsupported shader version {version:?}"));
+        }
+        let header = if version.is_embedded() {
+            format!("{}precision highp float;\n", version.version_declaration())
+        } else {
+            version.version_declaration().to_owned()
+        };
+
+        unsafe {
+            let program = gl.create_program()?;
+            let mut shaders = vec![];
+            for (ty, source) in [
+                (glow::VERTEX_SHADER, VERTEX_SHADER),
+                (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
+            ] {
+                let shader = gl.create_shader(ty)?;
+                gl.shader_source(shader, &format!("{header}{source}"));
+                gl.compile_shader(shader);
+                if !gl.get_shader_compile_status(shader) {
+                    return Err(gl.get_shader_info_log(shader));
+                }
+                gl.attach_shader(program, shader);
+                shaders.push(shader);
+            }
+            gl.link_program(program);
+            if !gl.get_program_link_status(program) {
+                return Err(gl.get_program_info_log(program));
+            }
+            for shader in shaders {
+                gl.detach_shader(program, shader);
+                gl.delete_shader(shader);
+            }
+
+            let vao = gl.create_vertex_array()?;
+            let max_texture_size = gl.get_parameter_i32(glow::MAX_TEXTURE_SIZE) as u32;
+            Ok(GpuRenderer {
+                gl,
+                program,
+                vao,
+                max_texture_size,
+                source: None,
+            })
+        }
+    }
+
+    fn source_texture(
+        &mut self,
+        image: &Arc<Rgba32FImage>,
+        linear: bool,
+    ) -> Result<glow::Texture, String> {
+        if let Some((source, was_linear, texture)) = &self.source {
+            if Arc::ptr_eq(source, image) && *was_linear == linear {
+                return Ok(*texture);
+            }
+        }
+
+        let gl = &self.gl;
+        // Premultiplied in linear light, as the CPU samplers blend it, and
+        // encoded again by the shader.
+        let decode = |c: f32| if linear { c } else { icc::to_linear(c) };
+        let bytes: Vec<u8> = image
+            .as_raw()
+            .chunks_exact(4)
+            .flat_map(|p| {
+                let a = p[3];
+                [decode(p[0]) * a, decode(p[1]) * a, decode(p[2]) * a, a]
+            })
+            .flat_map(|c| c.to_ne_bytes())
+            .collect();
+        unsafe {
+            if let Some((_, _, texture)) = self.source.take() {
+                gl.delete_texture(texture);
+            }
+            let texture = gl.create_texture()?;
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            // Read with `texelFetch`, and wrapped and blended by the shader.
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MIN_FILTER,
+                glow::NEAREST as i32,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MAG_FILTER,
+                glow::NEAREST as i32,
+            );
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA16F as i32,
+                image.width() as i32,
+                image.height() as i32,
+                0,
+                glow::RGBA,
+                glow::FLOAT,
+                Some(&bytes),
+            );
+            self.source = Some((Arc::clone(image), linear, texture));
+            Ok(texture)
+        }
+    }
+
+    pub fn render(&mut self, request: &RenderRequest) -> Result<Rgba32FImage, String> {
+        let downscaled = request.downscaled(request.size);
+        let request = downscaled.as_ref().unwrap_or(request);
+        let image = &request.image;
+        let size = request.size;
+        let view = request.view(size);
+        if request.is_tiled() {
+            return Err("tiled outputs are not supported".into());
+        }
+        if request.script.is_some() {
+            return Err("custom projections are not supported".into());
+        }
+        if request.overlay.is_some() {
+            return Err("overlays are not supported".into());
+        }
+        if request.graticule.is_some() {
+            return Err("graticules are not supported".into());
+        }
+        if request.vignette.is_some() {
+            return Err("vignettes are not supported".into());
+        }
+        if request.color.is_some() {
+            return Err("color adjustments after projection are not supported".into());
+        }
+        if request.samples > 1 {
+            return Err("supersampling is not supported".into());
+        }
+        if request.sampler != Sampler::Bilinear {
+            return Err(format!(
+                "{} sampling is not supported",
+                request.sampler.name()
+            ));
+        }
+        if !view.is_full() {
+            return Err("partial panoramas are not supported".into());
+        }
+        if request.edge != EdgeMode::Wrap {
+            return Err(format!(
+                "the {} edge mode is not supported",
+                request.edge.name()
+            ));
+        }
+        let limit = self.max_texture_size;
+        if image.width().max(image.height()) > limit || size.0.max(size.1) > limit {
+            return Err(format!("texture size exceeds {limit}"));
+        }
+
+        let source = self.source_texture(image, request.linear)?;
+        let gl = &self.gl;
+        let (width, height) = (size.0 as i32, size.1 as i32);
+        let mut pixels = vec![0; size.0 as usize * size.1 as usize * 4 * 4];
+        unsafe {
+            let target = gl.create_texture()?;
+            gl.bind_texture(glow::TEXTURE_2D, Some(target));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA32F as i32,
+                width,
+                height,
+                0,
+                glow::RGBA,
+                glow::FLOAT,
+                None,
+            );
+            let fbo = gl.create_framebuffer()?;
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(target),
+                0,
+            );
+
+            let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+            if status == glow::FRAMEBUFFER_COMPLETE {
+                let kind = if request.inverse {
+                    7
+                } else {
+                    request.kind as i32
+                };
+                let uniform = |name| gl.get_uniform_location(self.program, name);
+                gl.viewport(0, 0, width, height);
+                gl.disable(glow::BLEND);
+                gl.disable(glow::SCISSOR_TEST);
+                gl.use_program(Some(self.program));
+                gl.uniform_1_i32(uniform("u_source").as_ref(), 0);
+                gl.uniform_1_i32(uniform("u_kind").as_ref(), kind);
+                gl.uniform_1_f32(uniform("u_radius").as_ref(), view.radius);
+                gl.uniform_2_f32_slice(
+                    uniform("u_image_size").as_ref(),
+                    view.image_size.as_slice(),
+                );
+                gl.uniform_2_f32_slice(uniform("u_proj_size").as_ref(), view.proj_size.as_slice());
+                gl.uniform_2_f32_slice(uniform("u_offset").as_ref(), view.offset.as_slice());
+                gl.uniform_matrix_3_f32_slice(
+                    uniform("u_rotation").as_ref(),
+                    false,
+                    view.rotation.matrix().as_slice(),
+                );
+                gl.active_texture(glow::TEXTURE0);
+                gl.bind_texture(glow::TEXTURE_2D, Some(source));
+                gl.bind_vertex_array(Some(self.vao));
+                gl.draw_arrays(glow::TRIANGLES, 0, 3);
+                gl.read_pixels(
+                    0,
+                    0,
+                    width,
+                    height,
+                    glow::RGBA,
+                    glow::FLOAT,
+                    glow::PixelPackData::Slice(&mut pixels),
+                );
+                gl.bind_vertex_array(None);
+                gl.use_program(None);
+            }
+
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            gl.delete_framebuffer(fbo);
+            gl.delete_texture(target);
+            if status != glow::FRAMEBUFFER_COMPLETE {
+                return Err(format!("incomplete framebuffer: {status:#x}"));
+            }
+        }
+
+        let pixels = pixels
+            .chunks_exact(4)
+            .map(|c| f32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+            .collect();
+        Ok(Rgba32FImage::from_raw(size.0, size.1, pixels).unwrap())
+    }
+}
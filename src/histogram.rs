@@ -0,0 +1,186 @@
+//! Histograms of the rendered output and where it clips, worked out on the
+//! render thread after each render, to tell whether colors or export
+//! settings blow out the sky or crush the shadows.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use egui::{
+    epaint::ImageDelta, load::SizedTexture, mutex::RwLock, pos2, vec2, Color32, ColorImage,
+    Context, Sense, Shape, Stroke, Ui,
+};
+use image::Rgba32FImage;
+
+use crate::render::{CancelToken, Canceled};
+
+/// Levels counted, one for each value of an 8-bit channel.
+pub const BINS: usize = 256;
+
+/// How clipped pixels are marked in the overlay.
+const HIGHLIGHT: Color32 = Color32::from_rgba_premultiplied(200, 0, 0, 200);
+const SHADOW: Color32 = Color32::from_rgba_premultiplied(0, 0, 200, 200);
+
+/// Pixels of an image at each level of red, green and blue. Transparent
+/// pixels are left out.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    pub channels: [[u32; BINS]; 3],
+    /// Pixels with some channel at the highest level.
+    pub highlights: u32,
+    /// Pixels with every channel at the lowest level.
+    pub shadows: u32,
+    pub total: u32,
+}
+
+/// The 8-bit level of a channel, as it is shown and saved.
+fn level(value: f32) -> usize {
+    (value.clamp(0.0, 1.0) * 255.0).round() as usize
+}
+
+impl Histogram {
+    pub fn new(img: &Rgba32FImage) -> Self {
+        let mut histogram = Histogram {
+            channels: [[0; BINS]; 3],
+            highlights: 0,
+            shadows: 0,
+            total: 0,
+        };
+        for pixel in img.pixels().filter(|p| p.0[3] > 0.0) {
+            let levels = [0, 1, 2].map(|c| level(pixel.0[c]));
+            for (channel, &l) in histogram.channels.iter_mut().zip(&levels) {
+                channel[l] += 1;
+            }
+            histogram.highlights += levels.contains(&(BINS - 1)) as u32;
+            histogram.shadows += (levels == [0; 3]) as u32;
+            histogram.total += 1;
+        }
+        histogram
+    }
+
+    /// Draws the three channels over each other, scaled to the fullest level
+    /// other than the two ends, which would flatten the rest when much of the
+    /// image clips.
+    pub fn show(&self, ui: &mut Ui) {
+        let size = vec2(ui.available_width(), 80.0);
+        let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+        let peak = self
+            .channels
+            .iter()
+            .flat_map(|c| &c[1..BINS - 1])
+            .max()
+            .copied()
+            .unwrap_or(0)
+            .max(1) as f32;
+        let colors = [
+            Color32::from_rgb(230, 60, 60),
+            Color32::from_rgb(60, 200, 60),
+            Color32::from_rgb(70, 110, 240),
+        ];
+        for (channel, color) in self.channels.iter().zip(colors) {
+            let points = channel
+                .iter()
+                .enumerate()
+                .map(|(i, &count)| {
+                    let x = rect.left() + rect.width() * i as f32 / (BINS - 1) as f32;
+                    let y = rect.bottom() - rect.height() * (count as f32 / peak).min(1.0);
+                    pos2(x, y)
+                })
+                .collect();
+            painter.add(Shape::line(points, Stroke::new(1.0, color)));
+        }
+    }
+
+    /// Fractions of the pixels clipped in the highlights and the shadows.
+    pub fn clipped(&self) -> (f32, f32) {
+        let total = self.total.max(1) as f32;
+        (self.highlights as f32 / total, self.shadows as f32 / total)
+    }
+}
+
+/// Marks clipped highlights red and clipped shadows blue, leaving the rest
+/// of the image clear.
+fn clipping_mask(img: &Rgba32FImage) -> ColorImage {
+    let size = [img.width() as usize, img.height() as usize];
+    let pixels = img
+        .pixels()
+        .map(|pixel| {
+            if pixel.0[3] <= 0.0 {
+                return Color32::TRANSPARENT;
+            }
+            let levels = [0, 1, 2].map(|c| level(pixel.0[c]));
+            if levels.contains(&(BINS - 1)) {
+                HIGHLIGHT
+            } else if levels == [0; 3] {
+                SHADOW
+            } else {
+                Color32::TRANSPARENT
+            }
+        })
+        .collect();
+    ColorImage { size, pixels }
+}
+
+/// What is known of the last render of a [`crate::render::Renderer`].
+#[derive(Default)]
+pub struct Analysis {
+    /// Whether to also make the clipping overlay, which takes a texture as
+    /// large as the output.
+    pub clipping: AtomicBool,
+    histogram: RwLock<Option<Histogram>>,
+    mask: RwLock<Option<SizedTexture>>,
+}
+
+impl Analysis {
+    pub fn histogram(&self) -> Option<Histogram> {
+        self.histogram.read().clone()
+    }
+
+    /// The clipping overlay, unless it was turned off after the last render.
+    pub fn mask(&self) -> Option<SizedTexture> {
+        let mask = *self.mask.read();
+        mask.filter(|_| self.clipping.load(Ordering::Relaxed))
+    }
+
+    /// Works out the histogram of `out`, and the clipping overlay when it is
+    /// asked for, to be shown at `display_size`.
+    pub fn update(
+        &self,
+        ctx: &Context,
+        out: &Rgba32FImage,
+        display_size: (u32, u32),
+        cancel: &CancelToken,
+    ) -> Result<(), Canceled> {
+        let histogram = Histogram::new(out);
+        let mask = self
+            .clipping
+            .load(Ordering::Relaxed)
+            .then(|| clipping_mask(out));
+        let mut tex = self.mask.write();
+        cancel.check()?;
+        if let Some(mask) = mask {
+            // The texture is reused like the one of the output.
+            let manager = ctx.tex_manager();
+            let mut manager = manager.write();
+            let id = match *tex {
+                Some(tex) => {
+                    manager.set(tex.id, ImageDelta::full(mask, Default::default()));
+                    tex.id
+                }
+                None => manager.alloc("clipping".into(), mask.into(), Default::default()),
+            };
+            let size = [display_size.0 as f32, display_size.1 as f32];
+            tex.replace(SizedTexture::new(id, size));
+        }
+        self.histogram.write().replace(histogram);
+        ctx.request_repaint();
+        Ok(())
+    }
+
+    pub fn free(&self, ctx: &Context) {
+        self.histogram.write().take();
+        if let Some(tex) = self.mask.write().take() {
+            ctx.tex_manager().write().free(tex.id);
+        }
+    }
+}
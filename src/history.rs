@@ -0,0 +1,86 @@
+/// Undo and redo stacks of snapshots of some state.
+pub struct History<T> {
+    undo: Vec<T>,
+    redo: Vec<T>,
+    current: T,
+}
+
+/// Number of undo steps kept.
+const LIMIT: usize = 100;
+
+impl<T: Clone + PartialEq> History<T> {
+    pub fn new(current: T) -> Self {
+        Self {
+            undo: vec![],
+            redo: vec![],
+            current,
+        }
+    }
+
+    /// Makes `state` the current snapshot, unless it is unchanged.
+    pub fn record(&mut self, state: &T) {
+        if *state == self.current {
+            return;
+        }
+        let previous = std::mem::replace(&mut self.current, state.clone());
+        self.undo.push(previous);
+        if self.undo.len() > LIMIT {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn undo(&mut self) -> Option<T> {
+        let state = self.undo.pop()?;
+        self.redo.push(std::mem::replace(&mut self.current, state));
+        Some(self.current.clone())
+    }
+
+    pub fn redo(&mut self) -> Option<T> {
+        let state = self.redo.pop()?;
+        self.undo.push(std::mem::replace(&mut self.current, state));
+        Some(self.current.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_and_redo() {
+        let mut history = History::new(0);
+        history.record(&1);
+        history.record(&1);
+        history.record(&2);
+        assert_eq!(history.undo(), Some(1));
+        assert_eq!(history.undo(), Some(0));
+        assert_eq!(history.undo(), None);
+        assert_eq!(history.redo(), Some(1));
+        history.record(&3);
+        assert!(!history.can_redo());
+        assert_eq!(history.undo(), Some(1));
+    }
+
+    #[test]
+    fn keeps_the_last_steps() {
+        let mut history = History::new(0);
+        for i in 1..=LIMIT + 10 {
+            history.record(&i);
+        }
+        let mut steps = 0;
+        while history.undo().is_some() {
+            steps += 1;
+        }
+        assert_eq!(steps, LIMIT);
+        assert_eq!(history.current, 10);
+    }
+}
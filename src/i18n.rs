@@ -0,0 +1,377 @@
+//! Translations of the interface, looked up by their English text so that
+//! untranslated strings still show up in English.
+
+use std::{
+    env, fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    English,
+    Chinese,
+}
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::English, Language::Chinese];
+
+    /// The name of the language in itself.
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::Chinese => "简体中文",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Language::ALL.into_iter().find(|l| l.name() == name)
+    }
+
+    /// The language of the system's locale, for the first start.
+    pub fn detect() -> Self {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|key| env::var(key).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        if locale.starts_with("zh") {
+            Language::Chinese
+        } else {
+            Language::English
+        }
+    }
+}
+
+static LANGUAGE: AtomicU8 = AtomicU8::new(0);
+
+pub fn language() -> Language {
+    Language::ALL[LANGUAGE.load(Ordering::Relaxed) as usize]
+}
+
+pub fn set_language(language: Language) {
+    LANGUAGE.store(language as u8, Ordering::Relaxed);
+}
+
+/// `text` in the current language.
+pub fn tr(text: &'static str) -> &'static str {
+    match language() {
+        Language::English => text,
+        Language::Chinese => chinese(text).unwrap_or(text),
+    }
+}
+
+/// [`tr`] of a template, with each `{}` replaced by the next of `args`.
+pub fn trf(template: &'static str, args: &[&dyn fmt::Display]) -> String {
+    let mut parts = tr(template).split("{}");
+    let mut out = parts.next().unwrap_or_default().to_owned();
+    for (i, part) in parts.enumerate() {
+        if let Some(arg) = args.get(i) {
+            out += &arg.to_string();
+        }
+        out += part;
+    }
+    out
+}
+
+fn chinese(text: &str) -> Option<&'static str> {
+    Some(match text {
+        // Main panel
+        "Preset" => "预设",
+        "None" => "无",
+        "Save Preset" => "保存预设",
+        "Save Preset…" => "保存预设…",
+        "Name" => "名称",
+        "Save" => "保存",
+        "Undo" => "撤销",
+        "Redo" => "重做",
+        "360 Viewer" => "360° 查看器",
+        "FOV" => "视野",
+        "Center Planet Here" => "以此为星球中心",
+        "Puts the middle of the view at the planet's center" => "把视图中央放到星球的中心",
+        "Projection" => "投影",
+        "Custom Script" => "自定义脚本",
+        "Replaces the projection. Reads {} and assigns either lon and lat in radians, \
+         or u and v in [0, 1]." => {
+            "替换投影。读取 {}，并赋值 lon 和 lat（弧度），或 u 和 v（0 到 1）。"
+        }
+        "Inverse (Planet to Panorama)" => "反向（星球转全景）",
+        "Tunnel" => "隧道",
+        "Puts the other pole at the center" => "把另一极放到中心",
+        "Offset X" => "水平偏移",
+        "Offset Y" => "垂直偏移",
+        "Rotation" => "旋转",
+        "Reset Rotation" => "重置旋转",
+        "Auto Level" => "自动水平",
+        "Click to put this point at the planet's center" => "点击把此处设为星球中心",
+        "Scale" => "缩放",
+        "Color" => "颜色",
+        "Exposure" => "曝光",
+        "Contrast" => "对比度",
+        "Saturation" => "饱和度",
+        "Temperature" => "色温",
+        "Tint" => "色调",
+        "Reset Color" => "重置颜色",
+        "Vignette" => "暗角",
+        "Strength" => "强度",
+        "Radius" => "半径",
+        "Where the falloff starts, from the planet center" => "从星球中心算起，开始衰减的位置",
+        "Grid" => "网格",
+        "Latitude/Longitude" => "经纬线",
+        "Spacing" => "间距",
+        "Preview Only" => "仅预览",
+        "Leave the lines out of saved images" => "保存的图像中不含网格线",
+        "Output Size" => "输出尺寸",
+        "Sampler" => "采样",
+        "Edges" => "边缘",
+        "What fills the areas off the source image" => "源图像以外的区域如何填充",
+        "Source FOV" => "源视野",
+        "How much of the sphere the source covers, around its center. Wrapped edges are \
+         left transparent for partial panoramas." => {
+            "源图像围绕其中心覆盖的球面范围。局部全景的环绕边缘保持透明。"
+        }
+        "Stereo" => "立体",
+        "How the eyes of a stereo panorama are stacked. VR180 eyes also need a source FOV \
+         of 180° × 180°." => "立体全景中双眼图像的排列方式。VR180 还需要 180° × 180° 的源视野。",
+        "Save Both Eyes" => "保存双眼",
+        "Supersampling" => "超采样",
+        "Backend" => "后端",
+        "no GL context" => "没有 GL 上下文",
+        "GPU unavailable" => "GPU 不可用",
+        "GPU render failed" => "GPU 渲染失败",
+        "Debug" => "调试",
+        "Textures: {} ({} MiB)" => "纹理：{}（{} MiB）",
+        "Output image: {} MiB" => "输出图像：{} MiB",
+        "Show Render Stats" => "显示渲染统计",
+        "Histogram" => "直方图",
+        "Clipped: {}% highlights, {}% shadows" => "溢出：高光 {}%，阴影 {}%",
+        "Show Clipping" => "显示溢出",
+        "Marks clipped highlights red and clipped shadows blue" => {
+            "以红色标出溢出的高光，以蓝色标出溢出的阴影"
+        }
+        "{} in {} ms\n{} M samples/s" => "{}，用时 {} ms\n每秒 {} M 次采样",
+        "{}% of {} threads" => "{}% 的时间忙碌，共 {} 个线程",
+        "Language" => "语言",
+
+        // Files
+        "Select Image" => "选择图像",
+        "Image" => "图像",
+        "Recent" => "最近",
+        "Clear" => "清除",
+        "Reload" => "重新载入",
+        "Read the image again from disk" => "从磁盘重新读取图像",
+        "Watch" => "监视",
+        "Reload the image whenever it is written again, keeping the current parameters" => {
+            "图像被重新写入时自动载入，保留当前参数"
+        }
+        "Import Cube Map…" => "导入立方体贴图…",
+        "Select six faces or a single cross" => "选择六个面或一张十字展开图",
+        "Dual Fisheye…" => "双鱼眼…",
+        "Save Image" => "保存图像",
+        "canceled" => "已取消",
+        "the output has no pixels" => "输出没有像素",
+        "Export Options…" => "导出选项…",
+        "Overlay…" => "叠加…",
+        "Stickers…" => "贴纸…",
+        "Cube Map…" => "立方体贴图…",
+        "Poster…" => "海报…",
+        "Poster" => "海报",
+        "Paper" => "纸张",
+        "Letter" => "信纸",
+        "Tabloid" => "小报",
+        "Landscape" => "横向",
+        "Resolution" => "分辨率",
+        "Overlap" => "重叠",
+        "How far pages reach over their neighbors" => "每页与相邻页重叠的宽度",
+        "{} × {} pages, {} × {} cm" => "{} × {} 页，{} × {} 厘米",
+        "Save as PDF" => "保存为 PDF",
+        "Animation…" => "动画…",
+        "Live…" => "实时…",
+        "Batch…" => "批量…",
+        "Explore…" => "探索…",
+        "Add View" => "添加视图",
+        "Shows the same source in another window, with a projection \
+         of its own" => "在另一个窗口中以独立的投影显示同一来源",
+        "View {}" => "视图 {}",
+        "Swap with Main" => "与主视图交换",
+        "Save All Views…" => "保存所有视图…",
+        "Tile {}/{}" => "分块 {}/{}",
+        "Cancel" => "取消",
+        "Paste Image" => "粘贴图像",
+        "Copy Result" => "复制结果",
+        "Open Project" => "打开项目",
+        "Project" => "项目",
+        "Save Project" => "保存项目",
+        "Embed Image" => "嵌入图像",
+        "Pasted images are always embedded" => "粘贴的图像总是嵌入项目",
+
+        // Windows
+        "Animation" => "动画",
+        "Time" => "时间",
+        "Duration: " => "时长：",
+        "FPS: " => "帧率：",
+        "Add Keyframe" => "添加关键帧",
+        "Go" => "跳转",
+        "Remove" => "移除",
+        "Animation Format" => "动画格式",
+        "{}/{} frames" => "{}/{} 帧",
+        "The source has {} frames, which are all exported with the current parameters." => {
+            "源图像有 {} 帧，全部以当前参数导出。"
+        }
+        "Export Animation" => "导出动画",
+        "Live Input" => "实时输入",
+        "Device" => "设备",
+        "Start Webcam" => "打开摄像头",
+        "Open Video…" => "打开视频…",
+        "Video" => "视频",
+        "Stop" => "停止",
+        "Playing" => "播放中",
+        "Stopped" => "已停止",
+        "Off" => "关闭",
+        "Frames are decoded by ffmpeg and scaled to {}×{}" => "由 ffmpeg 解码并缩放到 {}×{}",
+        "Batch" => "批量处理",
+        "Input Folder" => "输入文件夹",
+        "Output Folder" => "输出文件夹",
+        "File Name" => "文件名",
+        "{name} is replaced by the input file name and {index} by its number" => {
+            "{name} 替换为输入文件名，{index} 替换为其序号"
+        }
+        "Process in Parallel" => "并行处理",
+        "Start" => "开始",
+        "Pending" => "等待中",
+        "Done" => "完成",
+        "Failed" => "失败",
+        "Explore" => "探索",
+        "Spread" => "变化幅度",
+        "How far the variations stray from the current one" => "变化偏离当前参数的程度",
+        "Shuffle" => "随机",
+        "Rotation {}°, {}°, {}°\nScale {}" => "旋转 {}°, {}°, {}°\n缩放 {}",
+        "Dual Fisheye" => "双鱼眼",
+        "Open Raw…" => "打开原始图像…",
+        "Lens FOV" => "镜头视野",
+        "Stitch Overlap" => "拼接重叠",
+        "Overlay" => "叠加",
+        "Show Overlay" => "显示叠加",
+        "Font…" => "字体…",
+        "Font" => "字体",
+        "No system font with Chinese characters was found" => "未找到含中文字符的系统字体",
+        "Open Logo…" => "打开标志…",
+        "Horizontal" => "水平",
+        "Vertical" => "垂直",
+        "Size" => "大小",
+        "Opacity" => "不透明度",
+        "Stickers" => "贴纸",
+        "Add Sticker…" => "添加贴纸…",
+        "Longitude" => "经度",
+        "Latitude" => "纬度",
+        "Cube Map Export" => "导出立方体贴图",
+        "Face Size" => "面尺寸",
+        "Layout" => "布局",
+        "Saved as {}" => "保存为 {}",
+        "Export" => "导出",
+        "Export Options" => "导出选项",
+        "Format" => "格式",
+        "16-bit" => "16 位",
+        "Quality" => "质量",
+        "Keep Metadata" => "保留元数据",
+        "Copies the EXIF data of the source into JPEG and PNG files, and its XMP data into \
+         panoramas" => "把源图像的 EXIF 数据写入 JPEG 和 PNG 文件，并把 XMP 数据写入全景图",
+        "Tag Panoramas" => "标记全景图",
+        "Writes GPano tags into unwrapped panoramas, so that panorama viewers show them in \
+         360°" => "在展开的全景图中写入 GPano 标签，让全景查看器以 360° 显示",
+        "WebP is always saved lossless" => "WebP 总是以无损方式保存",
+        "{} images can't be larger than {} pixels; save them as PNG, TIFF or OpenEXR" => {
+            "{} 图像不能大于 {} 像素，请保存为 PNG、TIFF 或 OpenEXR"
+        }
+        "Coordinate Map" => "坐标映射",
+        "Export Map…" => "导出映射…",
+        "Saves the source pixel of every output pixel, to apply the same warp to videos" => {
+            "保存每个输出像素对应的源像素，以便对视频应用同样的变换"
+        }
+        "Fit" => "适应",
+        "Pin as A" => "固定为 A",
+        "Keeps this render to compare the next ones with" => "保留此渲染，与之后的渲染对比",
+        "Restore A" => "恢复 A",
+        "Copy Settings" => "复制设置",
+        "Copies the parameters, sampler and custom projection as text to share" => {
+            "将参数、采样器和自定义投影复制为可分享的文本"
+        }
+        "Paste Settings" => "粘贴设置",
+        "Pole" => "极点",
+        "Retouch Pole" => "修饰极点",
+        "Hides the tripod at the center of the planet" => "遮盖星球中心的三脚架",
+        "Source Size" => "源图尺寸",
+        "Samples large sources from smaller copies. Auto picks the smallest one still as \
+         sharp as the output." => {
+            "从缩小的副本采样大尺寸源图。“自动”选择仍与输出一样清晰的最小副本。"
+        }
+
+        // Errors
+        "Error" => "错误",
+        "Failed to load preset" => "载入预设失败",
+        "Failed to save preset" => "保存预设失败",
+        "Failed to level the image" => "校平图像失败",
+        "Failed to open image" => "打开图像失败",
+        "Failed to import cube map" => "导入立方体贴图失败",
+        "Failed to save image" => "保存图像失败",
+        "Failed to open clipboard" => "打开剪贴板失败",
+        "Failed to paste image" => "粘贴图像失败",
+        "Failed to copy image" => "复制图像失败",
+        "Failed to copy settings" => "复制设置失败",
+        "Failed to paste settings" => "粘贴设置失败",
+        "not a settings string" => "不是设置文本",
+        "Failed to open project" => "打开项目失败",
+        "Failed to save project" => "保存项目失败",
+        "Failed to save settings" => "保存设置失败",
+        "Failed to export animation" => "导出动画失败",
+        "Failed to start live input" => "启动实时输入失败",
+        "Failed to start batch" => "启动批量处理失败",
+        "Failed to load font" => "载入字体失败",
+        "Failed to export cube map" => "导出立方体贴图失败",
+        "Failed to export poster" => "导出海报失败",
+        "Failed to export map" => "导出映射失败",
+        "input and output folders must be selected" => "必须选择输入和输出文件夹",
+        "no frames" => "没有帧",
+        "no keyframes" => "没有关键帧",
+        "export canceled" => "导出已取消",
+        "invalid preset name" => "无效的预设名称",
+        "faces must be square and of the same size" => "各面必须是同样大小的正方形",
+        "no horizon was found in the image" => "未能在图像中找到地平线",
+
+        // Names of options
+        "Stereographic" => "球极投影",
+        "Rectilinear" => "直线投影",
+        "Fisheye (Equidistant)" => "鱼眼（等距）",
+        "Fisheye (Equisolid)" => "鱼眼（等立体角）",
+        "Pannini" => "帕尼尼投影",
+        "Mercator" => "墨卡托投影",
+        "Orthographic" => "正交投影",
+        "Before Projection" => "投影前",
+        "After Projection" => "投影后",
+        "Darken" => "变暗",
+        "Fade Out" => "淡出",
+        "Nearest" => "最近邻",
+        "Bilinear" => "双线性",
+        "Bicubic" => "双三次",
+        "Wrap" => "环绕",
+        "Mirror" => "镜像",
+        "Solid Color" => "纯色",
+        "Transparent" => "透明",
+        "Mono" => "单眼",
+        "Over-Under" => "上下",
+        "Side by Side" => "左右",
+        "Left" => "左眼",
+        "Right" => "右眼",
+        "Auto" => "自动",
+        "Full" => "原始",
+        "Split" => "分割",
+        "Horizontal Cross" => "横向十字",
+        "Separate Files" => "单独文件",
+        "Text" => "文字",
+        "Nadir" => "天底",
+        "Zenith" => "天顶",
+        "Blur" => "模糊",
+        "Fill" => "填充",
+        "Logo" => "标志",
+        _ => return None,
+    })
+}
@@ -0,0 +1,432 @@
+//! Color profiles. Sources with an embedded ICC profile of the common
+//! matrix and curves kind are brought into sRGB when they are loaded, others
+//! are taken to be sRGB already, and saved JPEGs carry a profile of sRGB.
+//! Samples are blended in linear light, through [`to_linear`] and
+//! [`encode_premultiplied`]; HDR and OpenEXR sources are linear already and
+//! skip the decoding, and OpenEXR outputs are decoded back into it.
+
+use std::sync::OnceLock;
+
+use image::Rgba32FImage;
+use nalgebra::{matrix, vector, Matrix3, Vector3};
+use rayon::prelude::*;
+
+/// The sRGB primaries adapted to D50, as the columns of the matrix from
+/// linear sRGB to the XYZ connection space of ICC profiles.
+const SRGB_PRIMARIES: Matrix3<f32> = matrix![
+    0.436_074_7, 0.385_064_9, 0.143_080_4;
+    0.222_504_5, 0.716_878_6, 0.060_616_9;
+    0.013_932_2, 0.097_104_5, 0.714_173_3
+];
+
+/// The D50 white point of the connection space.
+const D50: [f32; 3] = [0.964_2, 1.0, 0.824_9];
+
+/// Entries of the table behind [`to_linear`], which is called for every
+/// pixel a sample reads.
+const LINEAR_STEPS: usize = 4096;
+
+/// Decodes an sRGB channel value. Values past 1, of lines and filters that
+/// overshoot, follow the same curve.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c < 0.0 {
+        -srgb_to_linear(-c)
+    } else if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c.max(0.0) * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// [`srgb_to_linear`] looked up in a table for values in `[0, 1]`.
+pub fn to_linear(c: f32) -> f32 {
+    static TABLE: OnceLock<Vec<f32>> = OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        (0..=LINEAR_STEPS)
+            .map(|i| srgb_to_linear(i as f32 / LINEAR_STEPS as f32))
+            .collect()
+    });
+    if !(0.0..=1.0).contains(&c) {
+        return srgb_to_linear(c);
+    }
+    let x = c * LINEAR_STEPS as f32;
+    let i = (x as usize).min(LINEAR_STEPS - 1);
+    let f = x - i as f32;
+    table[i] + (table[i + 1] - table[i]) * f
+}
+
+/// Encodes the color channels of a linear premultiplied color, leaving
+/// alpha as it is.
+pub fn encode_premultiplied([r, g, b, a]: [f32; 4]) -> [f32; 4] {
+    if a <= 0.0 {
+        return [0.0; 4];
+    }
+    let encode = |c: f32| linear_to_srgb(c / a) * a;
+    [encode(r), encode(g), encode(b), a]
+}
+
+/// How a profile turns a channel value into linear light.
+#[derive(Debug, Clone, PartialEq)]
+enum Curve {
+    Gamma(f32),
+    /// Values at evenly spaced inputs, in `[0, 1]`.
+    Table(Vec<f32>),
+    /// The parametric function of ICC, with parameters g, a, b, c, d, e, f.
+    Parametric([f32; 7]),
+}
+
+impl Curve {
+    fn apply(&self, x: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+        match self {
+            Curve::Gamma(g) => x.powf(*g),
+            Curve::Table(table) => {
+                let x = x * (table.len() - 1) as f32;
+                let i = (x as usize).min(table.len() - 2);
+                table[i] + (table[i + 1] - table[i]) * (x - i as f32)
+            }
+            Curve::Parametric([g, a, b, c, d, e, f]) => {
+                if x >= *d {
+                    (a * x + b).max(0.0).powf(*g) + e
+                } else {
+                    c * x + f
+                }
+            }
+        }
+    }
+}
+
+fn u16_at(bytes: &[u8], i: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(bytes.get(i..i + 2)?.try_into().ok()?))
+}
+
+fn u32_at(bytes: &[u8], i: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(i..i + 4)?.try_into().ok()?))
+}
+
+fn s15_at(bytes: &[u8], i: usize) -> Option<f32> {
+    Some(u32_at(bytes, i)? as i32 as f32 / 65536.0)
+}
+
+/// An RGB profile described by the XYZ of its primaries and a curve for each
+/// channel, like those of cameras and of Display P3 or Adobe RGB images.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    curves: [Curve; 3],
+    /// From linear values to the XYZ connection space.
+    primaries: Matrix3<f32>,
+}
+
+impl Profile {
+    /// Reads `bytes`, or `None` for profiles of other kinds, like CMYK ones
+    /// or those made of lookup tables only.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.get(16..24)? != b"RGB XYZ " || bytes.get(36..40)? != b"acsp" {
+            return None;
+        }
+        let count = u32_at(bytes, 128)? as usize;
+        let tag = |signature: &[u8; 4]| {
+            (0..count.min(256)).find_map(|i| {
+                let entry = 132 + 12 * i;
+                if bytes.get(entry..entry + 4)? != signature {
+                    return None;
+                }
+                let offset = u32_at(bytes, entry + 4)? as usize;
+                let size = u32_at(bytes, entry + 8)? as usize;
+                bytes.get(offset..offset.checked_add(size)?)
+            })
+        };
+        let xyz = |signature| -> Option<Vector3<f32>> {
+            let data = tag(signature).filter(|d| d.starts_with(b"XYZ "))?;
+            Some(vector![
+                s15_at(data, 8)?,
+                s15_at(data, 12)?,
+                s15_at(data, 16)?
+            ])
+        };
+        let primaries = Matrix3::from_columns(&[xyz(b"rXYZ")?, xyz(b"gXYZ")?, xyz(b"bXYZ")?]);
+        let curves = [
+            parse_curve(tag(b"rTRC")?)?,
+            parse_curve(tag(b"gTRC")?)?,
+            parse_curve(tag(b"bTRC")?)?,
+        ];
+        let name = tag(b"desc").and_then(parse_text).unwrap_or_default();
+        Some(Self {
+            name,
+            curves,
+            primaries,
+        })
+    }
+
+    /// Whether the profile is sRGB, or close enough that converting from it
+    /// would change nothing visible.
+    pub fn is_srgb(&self) -> bool {
+        let primaries = (self.primaries - SRGB_PRIMARIES).abs().max() < 0.002;
+        let curves = self.curves.iter().all(|curve| {
+            (1..16).all(|i| {
+                let x = i as f32 / 16.0;
+                (curve.apply(x) - srgb_to_linear(x)).abs() < 0.002
+            })
+        });
+        primaries && curves
+    }
+
+    /// Converts `img` from this profile into sRGB. Colors out of the range of
+    /// sRGB are clipped.
+    pub fn to_srgb(&self, img: &mut Rgba32FImage) {
+        let Some(to_srgb) = SRGB_PRIMARIES.try_inverse() else {
+            return;
+        };
+        let m = to_srgb * self.primaries;
+        let width = img.width() as usize;
+        img.par_chunks_mut(width * 4).for_each(|line| {
+            for pixel in line.chunks_exact_mut(4) {
+                let linear = vector![
+                    self.curves[0].apply(pixel[0]),
+                    self.curves[1].apply(pixel[1]),
+                    self.curves[2].apply(pixel[2])
+                ];
+                let rgb = m * linear;
+                for (p, c) in pixel.iter_mut().zip(rgb.iter()) {
+                    *p = linear_to_srgb(c.clamp(0.0, 1.0));
+                }
+            }
+        });
+    }
+}
+
+fn parse_curve(data: &[u8]) -> Option<Curve> {
+    match data.get(..4)? {
+        b"curv" => {
+            let count = u32_at(data, 8)? as usize;
+            match count {
+                0 => Some(Curve::Gamma(1.0)),
+                1 => Some(Curve::Gamma(u16_at(data, 12)? as f32 / 256.0)),
+                _ => (0..count)
+                    .map(|i| Some(u16_at(data, 12 + 2 * i)? as f32 / 65535.0))
+                    .collect::<Option<_>>()
+                    .map(Curve::Table),
+            }
+        }
+        b"para" => {
+            let kind = u16_at(data, 8)?;
+            let count = [1, 3, 4, 5, 7].get(kind as usize)?;
+            let mut p = [0.0; 7];
+            for (i, p) in p.iter_mut().enumerate().take(*count) {
+                *p = s15_at(data, 12 + 4 * i)?;
+            }
+            let [g, a, b, c, d, e, f] = p;
+            // Each kind is the full function with some of the parameters
+            // fixed.
+            Some(Curve::Parametric(match kind {
+                0 => [g, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+                1 => [g, a, b, 0.0, -b / a, 0.0, 0.0],
+                2 => [g, a, b, 0.0, -b / a, c, c],
+                3 => [g, a, b, c, d, 0.0, 0.0],
+                _ => [g, a, b, c, d, e, f],
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// The text of a description tag, in the ASCII of version 2 profiles or the
+/// first UTF-16 record of version 4 ones.
+fn parse_text(data: &[u8]) -> Option<String> {
+    match data.get(..4)? {
+        b"desc" => {
+            let len = u32_at(data, 8)? as usize;
+            let text = data.get(12..12 + len)?;
+            let text = text.split(|&b| b == 0).next()?;
+            Some(String::from_utf8_lossy(text).into_owned())
+        }
+        b"mluc" => {
+            let len = u32_at(data, 20)? as usize;
+            let offset = u32_at(data, 24)? as usize;
+            let text = data.get(offset..offset + len)?;
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect();
+            Some(String::from_utf16_lossy(&units))
+        }
+        _ => None,
+    }
+}
+
+fn s15(v: f32) -> [u8; 4] {
+    ((v * 65536.0).round() as i32).to_be_bytes()
+}
+
+fn xyz_tag(xyz: [f32; 3]) -> Vec<u8> {
+    let mut data = b"XYZ \0\0\0\0".to_vec();
+    for v in xyz {
+        data.extend(s15(v));
+    }
+    data
+}
+
+/// A version 2 ICC profile of sRGB, written into saved JPEGs. PNGs are
+/// marked with their own `sRGB` chunk instead.
+pub fn srgb_profile() -> Vec<u8> {
+    const STEPS: usize = 1024;
+
+    let name = b"sRGB\0";
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    desc.extend((name.len() as u32).to_be_bytes());
+    desc.extend(name);
+    // No Unicode or ScriptCode versions of the name.
+    desc.extend([0; 4 + 4 + 2 + 1 + 67]);
+    let mut curve = b"curv\0\0\0\0".to_vec();
+    curve.extend((STEPS as u32).to_be_bytes());
+    for i in 0..STEPS {
+        let v = srgb_to_linear(i as f32 / (STEPS - 1) as f32);
+        curve.extend(((v * 65535.0).round() as u16).to_be_bytes());
+    }
+    let column = |i: usize| {
+        let c = SRGB_PRIMARIES.column(i);
+        xyz_tag([c[0], c[1], c[2]])
+    };
+    let tags: [(&[u8; 4], Vec<u8>); 6] = [
+        (b"desc", desc),
+        (b"cprt", b"text\0\0\0\0No copyright, use freely\0".to_vec()),
+        (b"wtpt", xyz_tag(D50)),
+        (b"rXYZ", column(0)),
+        (b"gXYZ", column(1)),
+        (b"bXYZ", column(2)),
+    ];
+    let curves = [b"rTRC", b"gTRC", b"bTRC"];
+
+    let count = tags.len() + curves.len();
+    let mut table = vec![];
+    let mut data = vec![];
+    let start = 128 + 4 + 12 * count;
+    let mut add = |signature: &[u8; 4], offset: usize, size: usize| {
+        table.extend(signature);
+        table.extend((offset as u32).to_be_bytes());
+        table.extend((size as u32).to_be_bytes());
+    };
+    for (signature, tag) in &tags {
+        add(signature, start + data.len(), tag.len());
+        data.extend(tag);
+        data.resize(data.len().next_multiple_of(4), 0);
+    }
+    // The channels share one curve.
+    let offset = start + data.len();
+    for signature in curves {
+        add(signature, offset, curve.len());
+    }
+    data.extend(&curve);
+
+    let size = start + data.len();
+    let mut header = vec![0; 128];
+    header[..4].copy_from_slice(&(size as u32).to_be_bytes());
+    header[8..12].copy_from_slice(&[2, 0x10, 0, 0]);
+    header[12..24].copy_from_slice(b"mntrRGB XYZ ");
+    header[36..40].copy_from_slice(b"acsp");
+    for (i, v) in D50.into_iter().enumerate() {
+        header[68 + 4 * i..72 + 4 * i].copy_from_slice(&s15(v));
+    }
+
+    let mut profile = header;
+    profile.extend((count as u32).to_be_bytes());
+    profile.extend(table);
+    profile.extend(data);
+    profile
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Where the data of tag `signature` starts in `profile`.
+    fn tag_offset(profile: &[u8], signature: &[u8; 4]) -> usize {
+        let count = u32_at(profile, 128).unwrap() as usize;
+        (0..count)
+            .map(|i| 132 + 12 * i)
+            .find(|&entry| &profile[entry..entry + 4] == signature)
+            .and_then(|entry| u32_at(profile, entry + 4))
+            .unwrap() as usize
+    }
+
+    #[test]
+    fn transfer_functions_are_inverses() {
+        for i in 0..=100 {
+            let c = i as f32 / 100.0;
+            assert!((linear_to_srgb(srgb_to_linear(c)) - c).abs() < 1e-5, "{c}");
+            assert!((to_linear(c) - srgb_to_linear(c)).abs() < 1e-4, "{c}");
+        }
+        assert_eq!(to_linear(2.0), srgb_to_linear(2.0));
+    }
+
+    #[test]
+    fn reads_the_built_srgb_profile() {
+        let bytes = srgb_profile();
+        assert_eq!(u32_at(&bytes, 0), Some(bytes.len() as u32));
+        let profile = Profile::parse(&bytes).unwrap();
+        assert_eq!(profile.name, "sRGB");
+        assert!(profile.is_srgb());
+
+        let mut img = Rgba32FImage::from_pixel(2, 1, image::Rgba([0.2, 0.5, 0.9, 1.0]));
+        profile.to_srgb(&mut img);
+        for (c, expected) in img.get_pixel(0, 0).0.iter().zip([0.2, 0.5, 0.9, 1.0]) {
+            assert!((c - expected).abs() < 0.005, "{c} != {expected}");
+        }
+    }
+
+    #[test]
+    fn tells_other_profiles_apart() {
+        let mut bytes = srgb_profile();
+        // Red and green swapped.
+        let (r, g) = (tag_offset(&bytes, b"rXYZ"), tag_offset(&bytes, b"gXYZ"));
+        let red: Vec<u8> = bytes[r..r + 20].to_vec();
+        bytes.copy_within(g..g + 20, r);
+        bytes[g..g + 20].copy_from_slice(&red);
+        let profile = Profile::parse(&bytes).unwrap();
+        assert!(!profile.is_srgb());
+
+        let mut img = Rgba32FImage::from_pixel(1, 1, image::Rgba([1.0, 0.0, 0.0, 1.0]));
+        profile.to_srgb(&mut img);
+        let [r, g, b, _] = img.get_pixel(0, 0).0;
+        assert!(r < 0.01 && g > 0.99 && b < 0.01, "{r} {g} {b}");
+    }
+
+    #[test]
+    fn rejects_other_kinds_of_profiles() {
+        let mut bytes = srgb_profile();
+        bytes[16..20].copy_from_slice(b"CMYK");
+        assert_eq!(Profile::parse(&bytes), None);
+        assert_eq!(Profile::parse(&srgb_profile()[..200]), None);
+        assert_eq!(Profile::parse(b"not a profile"), None);
+    }
+
+    #[test]
+    fn parses_curves() {
+        let gamma = [b"curv".as_slice(), &[0; 4], &1u32.to_be_bytes(), &[2, 0x33]].concat();
+        assert_eq!(
+            parse_curve(&gamma),
+            Some(Curve::Gamma(2.0 + 0x33 as f32 / 256.0))
+        );
+        let linear = [b"curv".as_slice(), &[0; 8]].concat();
+        assert_eq!(parse_curve(&linear), Some(Curve::Gamma(1.0)));
+
+        // The sRGB function as a parametric curve of the fourth kind.
+        let p = [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.040_45];
+        let mut para = [b"para".as_slice(), &[0; 4], &3u16.to_be_bytes(), &[0; 2]].concat();
+        para.extend(p.iter().flat_map(|&v| s15(v)));
+        let curve = parse_curve(&para).unwrap();
+        for x in [0.01, 0.04, 0.2, 0.5, 1.0] {
+            assert!((curve.apply(x) - srgb_to_linear(x)).abs() < 1e-3, "{x}");
+        }
+        assert_eq!(parse_curve(b"sf32\0\0\0\0"), None);
+    }
+}
@@ -0,0 +1,101 @@
+//! Finds the horizon of a panorama, to straighten the planet's ground.
+
+use std::f32::consts::PI;
+
+use image::{imageops, Rgba32FImage};
+use nalgebra::{vector, Matrix3, Rotation3, SVector, Unit};
+
+use crate::projection;
+
+type Vec3f = SVector<f32, 3>;
+
+/// Columns and rows of the grid the panorama is reduced to, one per degree.
+const GRID: (u32, u32) = (360, 180);
+
+/// Only edges this close to the middle of the image are taken for the
+/// horizon, in degrees.
+const BAND: f32 = 45.0;
+
+/// Estimates the normal of the great circle along the horizon of a panorama.
+///
+/// The strongest vertical edge of each column is taken as a point of the
+/// horizon, and a tilted horizon makes these points a sine wave across the
+/// image. Returns `None` when there is no wave to be found.
+pub fn horizon_normal(img: &Rgba32FImage) -> Option<Unit<Vec3f>> {
+    let (w, h) = GRID;
+    let small = imageops::resize(img, w, h, imageops::FilterType::Triangle);
+    let luma = |x: u32, y: u32| {
+        let [r, g, b, _] = small.get_pixel(x, y).0;
+        0.2126 * r + 0.7152 * g + 0.0722 * b
+    };
+    let top = ((90.0 - BAND) / 180.0 * h as f32) as u32;
+    let bottom = ((90.0 + BAND) / 180.0 * h as f32) as u32;
+
+    // Longitude, tangent of the latitude and edge strength of each column.
+    let mut points = Vec::new();
+    for x in 0..w {
+        let gradient = |y: u32| (luma(x, y + 1) - luma(x, y - 1)).abs();
+        let Some(y) = (top..bottom).max_by(|&a, &b| gradient(a).total_cmp(&gradient(b))) else {
+            continue;
+        };
+        // Refines the edge between rows with a parabola through its neighbors.
+        let (l, c, r) = (gradient(y - 1), gradient(y), gradient(y + 1));
+        let denom = l - 2.0 * c + r;
+        let shift = if denom < 0.0 {
+            (0.5 * (l - r) / denom).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+        let uv = vector![
+            (x as f32 + 0.5) / w as f32,
+            (y as f32 + 0.5 + shift) / h as f32
+        ];
+        let lon = (uv.x - 0.5) * 2.0 * PI;
+        let lat = PI / 2.0 - uv.y * PI;
+        points.push((lon, lat.tan(), c));
+    }
+
+    // Weighted least squares of tan(lat) = a sin(lon) + b cos(lon) + c, fitted
+    // again without the points far from the first fit. The offset `c` takes
+    // up a horizon seen from above, and is not part of the tilt.
+    let mut fit = None;
+    let mut tolerance = f32::INFINITY;
+    for _ in 0..3 {
+        let mut normal = Matrix3::zeros();
+        let mut rhs = Vec3f::zeros();
+        let mut count = 0;
+        for &(lon, t, weight) in &points {
+            let basis = vector![lon.sin(), lon.cos(), 1.0];
+            if let Some(fit) = fit {
+                if (basis.dot(&fit) - t).abs() > tolerance {
+                    continue;
+                }
+            }
+            normal += basis * basis.transpose() * weight;
+            rhs += basis * (t * weight);
+            count += 1;
+        }
+        if count < w / 4 {
+            return None;
+        }
+        fit = Some(normal.try_inverse()? * rhs);
+        // About 6 degrees near the horizon.
+        tolerance = 0.1;
+    }
+    let fit = fit?;
+    Some(Unit::new_normalize(vector![-fit.x, -fit.y, 1.0]))
+}
+
+/// Turns `rotation` by the smallest angle that makes the planet's pole
+/// perpendicular to the horizon of `img`.
+pub fn auto_level(img: &Rgba32FImage, rotation: Rotation3<f32>) -> Option<Rotation3<f32>> {
+    let normal = horizon_normal(img)?;
+    // Either pole may be at the center, so the nearer one is kept there.
+    let pole = rotation * Vec3f::z();
+    let normal = if pole.dot(&normal) < 0.0 {
+        -normal
+    } else {
+        normal
+    };
+    Some(projection::center_on(rotation, normal))
+}
@@ -0,0 +1,47 @@
+//! Everything the app is made of, also linked into the benchmarks.
+
+pub mod animation;
+pub mod base64;
+pub mod batch;
+pub mod cli;
+pub mod clipboard;
+pub mod color;
+pub mod compare;
+pub mod config;
+pub mod cubemap;
+pub mod effect;
+pub mod error;
+pub mod explore;
+pub mod export;
+pub mod fisheye;
+pub mod fonts;
+pub mod gizmo;
+pub mod gpu;
+pub mod histogram;
+pub mod history;
+pub mod i18n;
+pub mod icc;
+pub mod level;
+pub mod live;
+pub mod metadata;
+pub mod mipmap;
+pub mod overlay;
+pub mod params;
+pub mod poster;
+pub mod preset;
+pub mod project;
+pub mod projection;
+pub mod remap;
+pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod script;
+pub mod share;
+pub mod stereo;
+pub mod sticker;
+pub mod toast;
+pub mod toml;
+pub mod viewer;
+pub mod views;
+pub mod watch;
+pub mod zoom;
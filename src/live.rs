@@ -0,0 +1,111 @@
+use std::{
+    ffi::OsString,
+    io::{self, Read},
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+use image::{buffer::ConvertBuffer, Rgba32FImage, RgbaImage};
+
+/// Size the live frames are scaled to by `ffmpeg`, 2:1 like the output of
+/// 360 cameras.
+pub const FRAME_SIZE: (u32, u32) = (1440, 720);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LiveSource {
+    /// A capture device, named as `ffmpeg` expects it on this platform.
+    Webcam(String),
+    Video(PathBuf),
+}
+
+impl LiveSource {
+    pub fn default_device() -> &'static str {
+        if cfg!(target_os = "windows") {
+            "video=Integrated Camera"
+        } else if cfg!(target_os = "macos") {
+            "0"
+        } else {
+            "/dev/video0"
+        }
+    }
+
+    fn input_args(&self) -> Vec<OsString> {
+        match self {
+            LiveSource::Webcam(device) => {
+                let format = if cfg!(target_os = "windows") {
+                    "dshow"
+                } else if cfg!(target_os = "macos") {
+                    "avfoundation"
+                } else {
+                    "v4l2"
+                };
+                vec!["-f".into(), format.into(), "-i".into(), device.into()]
+            }
+            // Videos play at their own frame rate, over and over.
+            LiveSource::Video(path) => vec![
+                "-re".into(),
+                "-stream_loop".into(),
+                "-1".into(),
+                "-i".into(),
+                path.into(),
+            ],
+        }
+    }
+}
+
+/// Frames decoded by an `ffmpeg` process on a background thread. Only the
+/// latest frame is kept, so a slow projection skips frames instead of
+/// falling behind.
+pub struct LiveInput {
+    child: Child,
+    latest: Arc<Mutex<Option<Rgba32FImage>>>,
+    reader: JoinHandle<()>,
+}
+
+impl LiveInput {
+    pub fn start(source: &LiveSource) -> io::Result<Self> {
+        let (width, height) = FRAME_SIZE;
+        let mut child = Command::new("ffmpeg")
+            .args(["-loglevel", "error"])
+            .args(source.input_args())
+            .args(["-vf", &format!("scale={width}:{height}")])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("failed to run ffmpeg: {e}")))?;
+        let mut stdout = child.stdout.take().unwrap();
+        let latest = Arc::new(Mutex::new(None));
+        let slot = Arc::clone(&latest);
+        let reader = thread::spawn(move || {
+            let mut buf = vec![0; width as usize * height as usize * 4];
+            while stdout.read_exact(&mut buf).is_ok() {
+                let frame = RgbaImage::from_raw(width, height, buf.clone()).unwrap();
+                *slot.lock().unwrap() = Some(frame.convert());
+            }
+        });
+        Ok(Self {
+            child,
+            latest,
+            reader,
+        })
+    }
+
+    /// Takes the newest frame decoded since the last call.
+    pub fn frame(&self) -> Option<Rgba32FImage> {
+        self.latest.lock().unwrap().take()
+    }
+
+    /// Whether frames are still coming, which stops at the end of the input
+    /// or when `ffmpeg` fails.
+    pub fn running(&self) -> bool {
+        !self.reader.is_finished()
+    }
+
+    pub fn stop(mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
@@ -1,182 +1,2147 @@
 use std::{
-    f32::consts::PI,
+    env, process,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver},
         Arc,
     },
-    thread,
+    thread::{self, JoinHandle},
+    time::Instant,
 };
 
 use eframe::NativeOptions;
-use egui::{load::SizedTexture, mutex::RwLock, ColorImage, ImageSource, Slider, ViewportBuilder};
-use image::{DynamicImage, GenericImageView, Pixel, RgbImage};
-use nalgebra::{vector, Rotation3};
-use rayon::prelude::*;
-
-use crate::projection::Projection;
-
-mod listener;
-mod projection;
+use egui::{
+    pos2, vec2, Checkbox, Color32, ColorImage, ComboBox, DragValue, Event, FontId, Image, Key,
+    KeyboardShortcut, Modifiers, PointerButton, ProgressBar, Rect, Sense, Slider, Ui, Vec2,
+    ViewportBuilder,
+};
+use image::buffer::ConvertBuffer;
 
-type Vec3u8 = nalgebra::SVector<u8, 3>;
-type Vec3f = nalgebra::SVector<f32, 3>;
+use shuodedaoli::{
+    animation::{self, AnimationFormat, Timeline},
+    batch::{self, Batch},
+    cli, clipboard,
+    color::{ColorAdjust, Stage},
+    compare::{Compare, CompareMode},
+    config,
+    cubemap::{CubeMap, Layout},
+    effect::{Pole, PoleMode, VignetteMode},
+    error::AppError,
+    explore::Explore,
+    export::{self, Format},
+    fisheye::DualFisheye,
+    fonts, gizmo,
+    gpu::{Backend, GpuRenderer},
+    history::History,
+    i18n::{self, tr, trf, Language},
+    level,
+    live::{self, LiveInput, LiveSource},
+    metadata::Metadata,
+    mipmap::Downscale,
+    overlay::{self, StampKind},
+    params::{AppParams, Changes},
+    poster::{self, Paper},
+    preset::{self, rotation_from_degrees, rotation_to_degrees},
+    project::{self, Project, Source},
+    projection::{self, ProjectionKind},
+    remap::{self, RemapFormat},
+    render::{self, CancelToken, FrameSequence, RenderRequest, RenderStats, Renderer},
+    sampler::{EdgeMode, Sampler},
+    scene::Scene,
+    script::{self, CustomProjection},
+    share::{self, Shared},
+    stereo::{Eye, StereoLayout},
+    sticker::Sticker,
+    toast::Toasts,
+    viewer::Viewer,
+    views::{self, Views},
+    watch::{self, Watcher},
+    zoom::Zoom,
+};
 
-fn interpolation(q1: image::Rgb<u8>, x1: f32, q2: image::Rgb<u8>, x2: f32) -> image::Rgb<u8> {
-    let q1: Vec3f = Vec3u8::from_iterator(q1.channels().iter().copied()).cast();
-    let q2: Vec3f = Vec3u8::from_iterator(q2.channels().iter().copied()).cast();
-    let q = (q1.scale(x1) + q2.scale(x2)) / (x1 + x2);
-    image::Rgb([q[0] as u8, q[1] as u8, q[2] as u8])
+struct AnimationJob {
+    handle: JoinHandle<()>,
+    progress: Receiver<u32>,
+    done: u32,
+    total: u32,
 }
 
-fn bilinear_interpolation(img: &DynamicImage, x: f32, y: f32) -> image::Rgb<u8> {
-    let (width, height) = img.dimensions();
-    let x1 = (x.max(0.) as u32).min(width - 1);
-    let y1 = (y.max(0.) as u32).min(height - 1);
-    let x2 = (x1 + 1).min(width - 1);
-    let y2 = (y1 + 1).min(height - 1);
-
-    let q11 = img.get_pixel(x1, y1).to_rgb();
-    let q21 = img.get_pixel(x2, y1).to_rgb();
-    let q12 = img.get_pixel(x1, y2).to_rgb();
-    let q22 = img.get_pixel(x2, y2).to_rgb();
-
-    let r1 = interpolation(q11, x2 as f32 - x, q21, x - x1 as f32);
-    let r2 = interpolation(q12, x2 as f32 - x, q22, x - x1 as f32);
-    interpolation(r1, y2 as f32 - y, r2, y - y1 as f32)
+/// What "Save Image" writes: the rendered image, or for outputs too large to
+/// be rendered at once, the request to render tile by tile.
+enum Output {
+    Image(Arc<image::Rgba32FImage>),
+    Tiles(RenderRequest),
+    /// The requests of both eyes, saved in one image in this layout.
+    Stereo(StereoLayout, Box<[RenderRequest; 2]>),
 }
 
-fn stereographic_projection(img: &DynamicImage, out: &mut RgbImage, proj: Projection) {
-    out.enumerate_pixels_mut()
-        .par_bridge()
-        .for_each(|(x, y, pixel)| {
-            let p = proj.proj(vector![x as f32, y as f32]);
-            *pixel = bilinear_interpolation(img, p.x, p.y);
-        });
+/// Paints how the last pass went over the bottom left corner of `rect`.
+fn render_stats(ui: &Ui, rect: Rect, stats: &RenderStats) {
+    let (width, height) = stats.size;
+    let mut text = trf(
+        "{} in {} ms\n{} M samples/s",
+        &[
+            &format!("{width}×{height}"),
+            &format!("{:.0}", stats.time.as_secs_f64() * 1000.0),
+            &format!("{:.1}", stats.samples_per_second() / 1e6),
+        ],
+    );
+    text.push('\n');
+    match stats.utilization() {
+        Some(busy) => {
+            let busy = format!("{:.0}", busy * 100.0);
+            text += &trf("{}% of {} threads", &[&busy, &stats.threads]);
+        }
+        None => text += "GPU",
+    }
+    let painter = ui.painter_at(rect);
+    let galley = painter.layout_no_wrap(text, FontId::monospace(12.0), Color32::WHITE);
+    let pos = rect.left_bottom() + vec2(6.0, -6.0 - galley.size().y);
+    let background = Rect::from_min_size(pos, galley.size()).expand(4.0);
+    painter.rect_filled(background, 4.0, Color32::from_black_alpha(160));
+    painter.galley(pos, galley, Color32::WHITE);
 }
 
 fn main() -> eframe::Result<()> {
-    let mut image = None;
-    let mut offset = (0.0, 0.4);
-    let mut rotation = (0.0, 0.09, 0.0);
-    let mut scale = 1.5;
+    let settings = config::Settings::load();
+    let args: Vec<_> = env::args_os().skip(1).collect();
+    if !args.is_empty() {
+        i18n::set_language(settings.language);
+        if let Err(e) = cli::run(&args, &settings) {
+            eprintln!("{}: {e}", tr("Error"));
+            process::exit(e.exit_code());
+        }
+        return Ok(());
+    }
+    let mut image: Option<Arc<image::Rgba32FImage>> = None;
+    let mut sequence: Option<Arc<FrameSequence>> = None;
+    let mut image_path = None;
+    let mut metadata = Metadata::default();
+    let mut embed_image = false;
+    let mut params = AppParams::new(settings.params);
+    let mut presets = preset::list();
+    let mut preset_name = String::new();
+    let mut show_save_preset = false;
+    let mut timeline = Timeline::default();
+    let mut playhead = 0.0;
+    let mut animation_format = AnimationFormat::Gif;
+    let mut show_animation = false;
+    let mut batch = Batch::new();
+    let mut show_batch = false;
+    let mut explore = Explore::new();
+    let mut views = Views::default();
+    let mut show_explore = false;
+    let mut animation_job: Option<AnimationJob> = None;
+    let animation_generation = Arc::new(AtomicU64::new(0));
+    let mut history = History::new(*params);
+    let mut history_pending = false;
+    let mut ssaa = settings.samples.ilog2().min(3);
+    let mut scene = Scene {
+        sampler: settings.sampler,
+        samples: 1 << ssaa,
+        downscale: settings.downscale,
+        custom: CustomProjection::new(&settings.script),
+        ..Scene::default()
+    };
+    let mut export = settings.export;
+    let mut poster = settings.poster;
+    let mut show_export = false;
+    let mut live: Option<LiveInput> = None;
+    let mut live_device = LiveSource::default_device().to_owned();
+    let mut show_live = false;
+    let mut remap_format = RemapFormat::Pgm16;
+    let mut fisheye = DualFisheye::default();
+    let mut fisheye_raw = None;
+    let mut show_fisheye = false;
+    let mut show_cube_export = false;
+    let mut show_poster = false;
+    let mut show_overlay = false;
+    let mut show_stickers = false;
+    let mut cube_size = 1024;
+    let mut cube_layout = Layout::Cross;
+    let mut saving = None;
+    let mut tile_progress = None;
+    let tile_generation = Arc::new(AtomicU64::new(0));
+    let mut clipboard = None;
+    let mut backend = settings.backend;
+    let mut last_dir = settings.last_dir;
+    let mut recent = settings.recent;
+    let mut language = settings.language;
+    i18n::set_language(language);
+    let mut watching = false;
+    let mut watcher = Watcher::default();
+    let mut gpu: Option<Result<GpuRenderer, String>> = None;
+    let mut fonts_installed = false;
+    let mut toasts = Toasts::new();
+    let mut close_unsaved = false;
 
-    let out_image: Arc<RwLock<Option<RgbImage>>> = Arc::new(RwLock::new(None));
-    let out_tex = Arc::new(RwLock::new(None));
-    let processing = Arc::new(AtomicBool::new(false));
+    let mut renderer = Renderer::new();
+    let mut preview_changed = false;
+    let mut thumbnail = None;
+    let mut viewer = Viewer::default();
+    let mut compare = Compare::new();
+    let mut zoom = Zoom::new();
+    let mut view_mode = false;
+    let mut show_stats = false;
 
     let options = NativeOptions {
-        viewport: ViewportBuilder::default().with_inner_size([900., 600.]),
+        viewport: ViewportBuilder::default()
+            .with_inner_size([settings.window_size.0, settings.window_size.1]),
         ..Default::default()
     };
-    eframe::run_simple_native("说的道理", options, move |ctx, _frame| {
+    eframe::run_simple_native("说的道理", options, move |ctx, frame| {
         egui_extras::install_image_loaders(ctx);
+        if !fonts_installed {
+            fonts::install(ctx);
+            fonts_installed = true;
+        }
+        let notify = toasts.notifier(ctx);
+        if ctx.input(|i| i.viewport().close_requested()) {
+            let window_size = ctx.input(|i| i.viewport().inner_rect.map(|r| r.size()));
+            let settings = config::Settings {
+                params: *params,
+                sampler: scene.sampler,
+                samples: scene.samples,
+                downscale: scene.downscale,
+                backend,
+                export,
+                poster,
+                last_dir: last_dir.clone(),
+                recent: recent.clone(),
+                script: scene.custom.source().to_owned(),
+                language,
+                window_size: window_size.map_or(settings.window_size, |s| (s.x, s.y)),
+            };
+            match settings.save() {
+                // The window stays open once so that the error can be read;
+                // closing it again quits without saving.
+                Err(e) if !close_unsaved => {
+                    notify.error("Failed to save settings", e);
+                    close_unsaved = true;
+                    ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+                }
+                _ => {
+                    if let Some(input) = live.take() {
+                        input.stop();
+                    }
+                }
+            }
+        }
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.horizontal(|ui| {
                 ui.vertical(|ui| {
-                    let mut listener = listener::Listerner::new();
+                    // Renders new variations in the explore window this frame.
+                    let mut shuffle = false;
+                    if std::mem::take(&mut preview_changed) {
+                        params.refresh();
+                    }
+
+                    // Each frame cancels the render of the previous one, so on the CPU only
+                    // the coarse passes keep up with the frame rate.
+                    if let Some(input) = &live {
+                        if let Some(frame) = input.frame() {
+                            image = Some(Arc::new(frame));
+                            scene.linear = false;
+                            sequence = None;
+                            image_path = None;
+                            metadata = Metadata::default();
+                            params.refresh();
+                        }
+                        if input.running() {
+                            ctx.request_repaint();
+                        }
+                    }
+
+                    ui.horizontal(|ui| {
+                        let selected = if preset_name.is_empty() {
+                            tr("None")
+                        } else {
+                            &preset_name
+                        };
+                        let mut load = None;
+                        ComboBox::from_label(tr("Preset"))
+                            .selected_text(selected.to_owned())
+                            .show_ui(ui, |ui| {
+                                for name in &presets {
+                                    if ui.selectable_label(*name == preset_name, name).clicked() {
+                                        load = Some(name.clone());
+                                    }
+                                }
+                            });
+                        if let Some(name) = load {
+                            match preset::load(&name) {
+                                Ok(p) => {
+                                    *params = p;
+                                    preset_name = name;
+                                }
+                                Err(e) => {
+                                    notify.error("Failed to load preset", e);
+                                }
+                            }
+                        }
+                        if ui.button(tr("Save Preset…")).clicked() {
+                            show_save_preset = !show_save_preset;
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        let (undo_key, redo_key) = if ctx.wants_keyboard_input() {
+                            (false, false)
+                        } else {
+                            ctx.input_mut(|i| {
+                                let undo = KeyboardShortcut::new(Modifiers::COMMAND, Key::Z);
+                                let redo = KeyboardShortcut::new(Modifiers::COMMAND, Key::Y);
+                                let redo_shift = KeyboardShortcut::new(
+                                    Modifiers::COMMAND | Modifiers::SHIFT,
+                                    Key::Z,
+                                );
+                                // Check the shifted shortcut first, as consuming Ctrl+Z
+                                // ignores Shift.
+                                let redo =
+                                    i.consume_shortcut(&redo_shift) || i.consume_shortcut(&redo);
+                                (i.consume_shortcut(&undo), redo)
+                            })
+                        };
+                        let undo = ui
+                            .add_enabled(history.can_undo(), egui::Button::new(tr("Undo")))
+                            .on_hover_text("Ctrl+Z")
+                            .clicked();
+                        let redo = ui
+                            .add_enabled(history.can_redo(), egui::Button::new(tr("Redo")))
+                            .on_hover_text("Ctrl+Y")
+                            .clicked();
+                        let state = if undo || undo_key {
+                            history.undo()
+                        } else if redo || redo_key {
+                            history.redo()
+                        } else {
+                            None
+                        };
+                        if let Some(state) = state {
+                            *params = state;
+                        }
+                    });
+                    ui.separator();
+
+                    ui.horizontal(|ui| {
+                        params += ui.checkbox(&mut view_mode, tr("360 Viewer"));
+                        if view_mode {
+                            params += ui.add(
+                                Slider::new(&mut viewer.fov, 10.0..=150.0)
+                                    .text(tr("FOV"))
+                                    .suffix("°"),
+                            );
+                            if ui
+                                .button(tr("Center Planet Here"))
+                                .on_hover_text(tr(
+                                    "Puts the middle of the view at the planet's center",
+                                ))
+                                .clicked()
+                            {
+                                params.set_view_rotation(viewer.rotation());
+                                view_mode = false;
+                                params += true;
+                            }
+                        }
+                    });
+                    ui.separator();
+
+                    ui.add_enabled_ui(!params.inverse, |ui| {
+                        ComboBox::from_label(tr("Projection"))
+                            .selected_text(tr(params.kind.name()))
+                            .show_ui(ui, |ui| {
+                                for k in ProjectionKind::ALL {
+                                    let name = tr(k.name());
+                                    ui.selectable_value(&mut params.kind, k, name);
+                                }
+                            });
+                        params += ui
+                            .checkbox(&mut scene.custom.enabled, tr("Custom Script"))
+                            .on_hover_text(trf(
+                                "Replaces the projection. Reads {} and assigns either \
+                                 lon and lat in radians, or u and v in [0, 1].",
+                                &[&script::INPUTS.join(", ")],
+                            ));
+                        if scene.custom.enabled {
+                            let editor = egui::TextEdit::multiline(scene.custom.source_mut())
+                                .code_editor()
+                                .desired_rows(3);
+                            if ui.add(editor).changed() {
+                                scene.custom.compile();
+                                params += true;
+                            }
+                            if let Some(e) = scene.custom.error() {
+                                ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+                            }
+                        }
+                    });
+                    let inverse = tr("Inverse (Planet to Panorama)");
+                    ui.checkbox(&mut params.inverse, inverse);
+                    ui.checkbox(&mut params.tunnel, tr("Tunnel"))
+                        .on_hover_text(tr("Puts the other pole at the center"));
+                    ui.separator();
 
-                    listener += ui.add(Slider::new(&mut offset.0, -1.0..=1.0).text("Offset X"));
-                    listener += ui.add(Slider::new(&mut offset.1, -1.0..=1.0).text("Offset Y"));
+                    ui.add(Slider::new(&mut params.offset.0, -1.0..=1.0).text(tr("Offset X")));
+                    ui.add(Slider::new(&mut params.offset.1, -1.0..=1.0).text(tr("Offset Y")));
                     ui.shrink_width_to_current();
                     ui.separator();
 
-                    listener += ui.add(Slider::new(&mut rotation.0, 0.0..=PI).text("Rotation X"));
-                    listener += ui.add(Slider::new(&mut rotation.1, 0.0..=PI).text("Rotation Y"));
-                    listener += ui.add(Slider::new(&mut rotation.2, 0.0..=PI).text("Rotation Z"));
+                    ui.horizontal(|ui| {
+                        ui.label(tr("Rotation"));
+                        let rotation = &mut params.rotation;
+                        for (angle, axis) in [
+                            (&mut rotation.0, "X: "),
+                            (&mut rotation.1, "Y: "),
+                            (&mut rotation.2, "Z: "),
+                        ] {
+                            ui.add(
+                                DragValue::new(angle)
+                                    .clamp_range(-180.0..=180.0)
+                                    .prefix(axis)
+                                    .suffix("°"),
+                            );
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        let mut r = rotation_from_degrees(params.rotation);
+                        if ui.add(gizmo::Gizmo::new(&mut r)).changed() {
+                            params.rotation = rotation_to_degrees(r);
+                        }
+                        if ui.button(tr("Reset Rotation")).clicked() {
+                            params.rotation = (0.0, 0.0, 0.0);
+                        }
+                        let level = ui.add_enabled(
+                            image.is_some() && !params.inverse,
+                            egui::Button::new(tr("Auto Level")),
+                        );
+                        if let Some(image) = image.as_ref().filter(|_| level.clicked()) {
+                            match level::auto_level(image, params.view_rotation()) {
+                                Some(r) => params.set_view_rotation(r),
+                                None => {
+                                    notify.error("Failed to level the image", AppError::NoHorizon);
+                                }
+                            }
+                        }
+                    });
+                    if let Some(image) = image.as_ref().filter(|_| !params.inverse) {
+                        // Regenerated whenever another image is loaded.
+                        let stale = !thumbnail
+                            .as_ref()
+                            .is_some_and(|(source, _)| Arc::ptr_eq(source, image));
+                        if stale {
+                            let width = 256;
+                            let height = (width * image.height() / image.width()).max(1);
+                            let small: image::RgbaImage =
+                                image::imageops::thumbnail(&**image, width, height).convert();
+                            let small = ColorImage::from_rgba_unmultiplied(
+                                [width as usize, height as usize],
+                                small.as_raw(),
+                            );
+                            let texture = ctx.load_texture("thumbnail", small, Default::default());
+                            thumbnail = Some((Arc::clone(image), texture));
+                        }
+                        let (_, texture) = thumbnail.as_ref().unwrap();
+                        let response = ui
+                            .add(Image::new(texture).max_width(256.0).sense(Sense::click()))
+                            .on_hover_text(tr("Click to put this point at the planet's center"));
+                        let click = response
+                            .interact_pointer_pos()
+                            .filter(|_| response.clicked());
+                        if let Some(pos) = click {
+                            let p = (pos - response.rect.min) / response.rect.size();
+                            let dir = projection::equirect_to_sphere(nalgebra::vector![p.x, p.y]);
+                            let r = projection::center_on(params.view_rotation(), dir);
+                            params.set_view_rotation(r);
+                        }
+                    }
                     ui.shrink_width_to_current();
                     ui.separator();
 
-                    listener += ui.add(Slider::new(&mut scale, 0.1..=5.0).text("Scale"));
+                    ui.add(Slider::new(&mut params.scale, 0.1..=5.0).text(tr("Scale")));
                     ui.shrink_width_to_current();
                     ui.separator();
 
+                    egui::CollapsingHeader::new(tr("Color")).show(ui, |ui| {
+                        let color = &mut params.color;
+                        ui.add(
+                            Slider::new(&mut color.exposure, -3.0..=3.0)
+                                .text(tr("Exposure"))
+                                .suffix(" EV"),
+                        );
+                        for (value, name) in [
+                            (&mut color.contrast, tr("Contrast")),
+                            (&mut color.saturation, tr("Saturation")),
+                            (&mut color.temperature, tr("Temperature")),
+                            (&mut color.tint, tr("Tint")),
+                        ] {
+                            ui.add(Slider::new(value, -1.0..=1.0).text(name));
+                        }
+                        ui.horizontal(|ui| {
+                            for stage in Stage::ALL {
+                                let name = tr(stage.name());
+                                ui.radio_value(&mut color.stage, stage, name);
+                            }
+                        });
+                        if ui.button(tr("Reset Color")).clicked() {
+                            *color = ColorAdjust::default();
+                        }
+                    });
+
+                    egui::CollapsingHeader::new(tr("Vignette")).show(ui, |ui| {
+                        let vignette = &mut params.vignette;
+                        ui.horizontal(|ui| {
+                            for mode in VignetteMode::ALL {
+                                let name = tr(mode.name());
+                                ui.radio_value(&mut vignette.mode, mode, name);
+                            }
+                        });
+                        let strength = Slider::new(&mut vignette.strength, 0.0..=1.0);
+                        ui.add(strength.text(tr("Strength")));
+                        ui.add(Slider::new(&mut vignette.radius, 0.0..=2.0).text(tr("Radius")))
+                            .on_hover_text(tr("Where the falloff starts, from the planet center"));
+                    });
+
+                    egui::CollapsingHeader::new(tr("Grid")).show(ui, |ui| {
+                        let graticule = &mut params.graticule;
+                        ui.horizontal(|ui| {
+                            let label = tr("Latitude/Longitude");
+                            ui.checkbox(&mut graticule.enabled, label);
+                            ui.color_edit_button_rgba_unmultiplied(&mut graticule.color);
+                        });
+                        ui.add(
+                            Slider::new(&mut graticule.spacing, 5.0..=90.0)
+                                .text(tr("Spacing"))
+                                .suffix("°"),
+                        );
+                        ui.checkbox(&mut graticule.preview_only, tr("Preview Only"))
+                            .on_hover_text(tr("Leave the lines out of saved images"));
+                    });
+
+                    egui::CollapsingHeader::new(tr("Pole")).show(ui, |ui| {
+                        let cap = &mut params.pole;
+                        ui.checkbox(&mut cap.enabled, tr("Retouch Pole"))
+                            .on_hover_text(tr("Hides the tripod at the center of the planet"));
+                        ui.horizontal(|ui| {
+                            for pole in Pole::ALL {
+                                ui.radio_value(&mut cap.pole, pole, tr(pole.name()));
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            for mode in PoleMode::ALL {
+                                ui.radio_value(&mut cap.mode, mode, tr(mode.name()));
+                            }
+                        });
+                        let radius = Slider::new(&mut cap.radius, 1.0..=45.0);
+                        ui.add(radius.text(tr("Radius")).suffix("°"));
+                    });
+
+                    egui::CollapsingHeader::new(tr("Histogram")).show(ui, |ui| {
+                        let analysis = &renderer.analysis;
+                        if let Some(histogram) = analysis.histogram() {
+                            histogram.show(ui);
+                            let (highlights, shadows) = histogram.clipped();
+                            let highlights = format!("{:.1}", highlights * 100.0);
+                            let shadows = format!("{:.1}", shadows * 100.0);
+                            ui.label(trf(
+                                "Clipped: {}% highlights, {}% shadows",
+                                &[&highlights, &shadows],
+                            ));
+                        }
+                        let mut clipping = analysis.clipping.load(Ordering::Relaxed);
+                        let response = ui
+                            .checkbox(&mut clipping, tr("Show Clipping"))
+                            .on_hover_text(tr(
+                                "Marks clipped highlights red and clipped shadows blue",
+                            ));
+                        if response.changed() {
+                            analysis.clipping.store(clipping, Ordering::Relaxed);
+                            params += true;
+                        }
+                    });
+                    ui.separator();
+
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            DragValue::new(&mut params.size.0)
+                                .clamp_range(16..=32768)
+                                .suffix(" px"),
+                        );
+                        ui.label("×");
+                        ui.add(
+                            DragValue::new(&mut params.size.1)
+                                .clamp_range(16..=32768)
+                                .suffix(" px"),
+                        );
+                        ui.label(tr("Output Size"));
+                    });
+                    ComboBox::from_label(tr("Sampler"))
+                        .selected_text(tr(scene.sampler.name()))
+                        .show_ui(ui, |ui| {
+                            for s in Sampler::ALL {
+                                params += ui.selectable_value(&mut scene.sampler, s, tr(s.name()));
+                            }
+                        });
+                    ComboBox::from_label(tr("Source Size"))
+                        .selected_text(tr(scene.downscale.name()))
+                        .show_ui(ui, |ui| {
+                            for d in Downscale::ALL {
+                                params +=
+                                    ui.selectable_value(&mut scene.downscale, d, tr(d.name()));
+                            }
+                        })
+                        .response
+                        .on_hover_text(tr(
+                            "Samples large sources from smaller copies. Auto picks \
+                             the smallest one still as sharp as the output.",
+                        ));
+                    ui.horizontal(|ui| {
+                        ComboBox::from_label(tr("Edges"))
+                            .selected_text(tr(params.edge.name()))
+                            .show_ui(ui, |ui| {
+                                for mode in EdgeMode::ALL {
+                                    let selected = mode.name() == params.edge.name();
+                                    if ui.selectable_label(selected, tr(mode.name())).clicked()
+                                        && !selected
+                                    {
+                                        params.edge = mode;
+                                    }
+                                }
+                            })
+                            .response
+                            .on_hover_text(tr("What fills the areas off the source image"));
+                        if let EdgeMode::Color(color) = &mut params.edge {
+                            ui.color_edit_button_rgba_unmultiplied(color);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            DragValue::new(&mut params.fov.0)
+                                .clamp_range(1.0..=360.0)
+                                .suffix("°"),
+                        );
+                        ui.label("×");
+                        ui.add(
+                            DragValue::new(&mut params.fov.1)
+                                .clamp_range(1.0..=180.0)
+                                .suffix("°"),
+                        );
+                        ui.label(tr("Source FOV")).on_hover_text(tr(
+                            "How much of the sphere the source covers, around its center. \
+                             Wrapped edges are left transparent for partial panoramas.",
+                        ));
+                    });
+                    ui.horizontal(|ui| {
+                        ComboBox::from_label(tr("Stereo"))
+                            .selected_text(tr(scene.stereo.layout.name()))
+                            .show_ui(ui, |ui| {
+                                for layout in StereoLayout::ALL {
+                                    params += ui.selectable_value(
+                                        &mut scene.stereo.layout,
+                                        layout,
+                                        tr(layout.name()),
+                                    );
+                                }
+                            })
+                            .response
+                            .on_hover_text(tr("How the eyes of a stereo panorama are stacked. \
+                                 VR180 eyes also need a source FOV of 180° × 180°."));
+                        if scene.stereo.layout != StereoLayout::Mono {
+                            for eye in Eye::ALL {
+                                params +=
+                                    ui.radio_value(&mut scene.stereo.eye, eye, tr(eye.name()));
+                            }
+                            ui.checkbox(&mut scene.stereo.pair, tr("Save Both Eyes"));
+                        }
+                    });
+                    params += ui.add(
+                        Slider::new(&mut ssaa, 0..=3)
+                            .text(tr("Supersampling"))
+                            .custom_formatter(|v, _| format!("{}×", 1 << v as u32)),
+                    );
+                    scene.samples = 1 << ssaa;
+                    ui.separator();
+
+                    ComboBox::from_label(tr("Backend"))
+                        .selected_text(tr(backend.name()))
+                        .show_ui(ui, |ui| {
+                            for b in Backend::ALL {
+                                params += ui.selectable_value(&mut backend, b, tr(b.name()));
+                            }
+                        });
+                    if backend != Backend::Cpu && gpu.is_none() {
+                        gpu = Some(match frame.gl() {
+                            Some(gl) => GpuRenderer::new(Arc::clone(gl)),
+                            None => Err(tr("no GL context").into()),
+                        });
+                    }
+                    if let Some(Err(e)) = &gpu {
+                        if backend != Backend::Cpu {
+                            ui.label(format!("{}: {e}", tr("GPU unavailable")));
+                        }
+                    }
+                    ComboBox::from_label(tr("Language"))
+                        .selected_text(language.name())
+                        .show_ui(ui, |ui| {
+                            for l in Language::ALL {
+                                if ui.selectable_value(&mut language, l, l.name()).clicked() {
+                                    i18n::set_language(language);
+                                }
+                            }
+                        });
+                    egui::CollapsingHeader::new(tr("Debug")).show(ui, |ui| {
+                        const MIB: f32 = 1024.0 * 1024.0;
+                        let (textures, bytes) = render::texture_memory(ctx);
+                        let bytes = format!("{:.1}", bytes as f32 / MIB);
+                        ui.label(trf("Textures: {} ({} MiB)", &[&textures, &bytes]));
+                        let bytes = renderer.image_bytes()
+                            + views
+                                .iter()
+                                .map(|v| v.renderer.image_bytes())
+                                .sum::<usize>();
+                        let bytes = format!("{:.1}", bytes as f32 / MIB);
+                        ui.label(trf("Output image: {} MiB", &[&bytes]));
+                        ui.checkbox(&mut show_stats, tr("Show Render Stats"));
+                    });
+                    ui.separator();
+
                     ui.horizontal(|ui| {
-                        if ui.button("Select Image").clicked() {
-                            let path = rfd::FileDialog::new()
-                                .add_filter("Image", &["jpg", "jpeg", "png", "bmp", "gif", "webp"])
-                                .pick_file();
-                            if let Some(path) = path {
-                                match image::open(path) {
-                                    Ok(img) => {
-                                        image = Some(Arc::new(img));
-                                        listener += true;
+                        let mut open = None;
+                        if ui.button(tr("Select Image")).clicked() {
+                            let mut dialog = rfd::FileDialog::new().add_filter(
+                                tr("Image"),
+                                &[
+                                    "jpg", "jpeg", "png", "bmp", "gif", "webp", "tif", "tiff",
+                                    "hdr", "exr",
+                                ],
+                            );
+                            if let Some(dir) = &last_dir {
+                                dialog = dialog.set_directory(dir);
+                            }
+                            if let Some(path) = dialog.pick_file() {
+                                last_dir = path.parent().map(Into::into);
+                                open = Some(path);
+                            }
+                        }
+
+                        ui.add_enabled_ui(!recent.is_empty(), |ui| {
+                            ui.menu_button(tr("Recent"), |ui| {
+                                for path in &recent {
+                                    let name = path.file_name().unwrap_or(path.as_os_str());
+                                    if ui
+                                        .button(name.to_string_lossy())
+                                        .on_hover_text(path.display().to_string())
+                                        .clicked()
+                                    {
+                                        open = Some(path.clone());
+                                        ui.close_menu();
+                                    }
+                                }
+                                ui.separator();
+                                if ui.button(tr("Clear")).clicked() {
+                                    recent.clear();
+                                    ui.close_menu();
+                                }
+                            });
+                        });
+                        if ui
+                            .add_enabled(image_path.is_some(), egui::Button::new(tr("Reload")))
+                            .on_hover_text(tr("Read the image again from disk"))
+                            .clicked()
+                        {
+                            open = image_path.clone();
+                        }
+                        ui.checkbox(&mut watching, tr("Watch"))
+                            .on_hover_text(tr("Reload the image whenever it is written again, \
+                             keeping the current parameters"));
+                        if let Some(path) = image_path.as_ref().filter(|_| watching) {
+                            if watcher.poll(path) {
+                                open = Some(path.clone());
+                            }
+                            ctx.request_repaint_after(watch::INTERVAL);
+                        }
+
+                        if let Some(path) = open {
+                            match FrameSequence::open(&path) {
+                                Ok(seq) => {
+                                    config::add_recent(&mut recent, &path);
+                                    // Reloads keep the layout that was chosen.
+                                    if image_path.as_ref() != Some(&path) {
+                                        let (width, height) = seq.first().dimensions();
+                                        scene.stereo.layout = StereoLayout::detect(width, height);
+                                    }
+                                    // Off the tagged area of a partial panorama
+                                    // there is nothing to show.
+                                    if seq.metadata.gpano().is_some_and(|g| !g.is_full()) {
+                                        params.edge = EdgeMode::Transparent;
+                                    }
+                                    image = Some(Arc::clone(seq.first()));
+                                    scene.linear = seq.linear;
+                                    metadata = seq.metadata.clone();
+                                    sequence = seq.is_animated().then(|| Arc::new(seq));
+                                    image_path = Some(path);
+                                    params += true;
+                                }
+                                Err(e) => {
+                                    notify.error("Failed to open image", e);
+                                }
+                            }
+                        }
+
+                        if ui.button(tr("Import Cube Map…")).clicked() {
+                            let mut dialog = rfd::FileDialog::new()
+                                .set_title(tr("Select six faces or a single cross"))
+                                .add_filter(
+                                    tr("Image"),
+                                    &[
+                                        "jpg", "jpeg", "png", "bmp", "webp", "tif", "tiff", "hdr",
+                                        "exr",
+                                    ],
+                                );
+                            if let Some(dir) = &last_dir {
+                                dialog = dialog.set_directory(dir);
+                            }
+                            if let Some(paths) = dialog.pick_files() {
+                                last_dir = paths[0].parent().map(Into::into);
+                                match CubeMap::open(&paths) {
+                                    Ok(cube) => {
+                                        image = Some(Arc::new(cube.to_equirect(Sampler::Bilinear)));
+                                        scene.linear = false;
+                                        sequence = None;
+                                        image_path = None;
+                                        metadata = Metadata::default();
+                                        params += true;
                                     }
                                     Err(e) => {
-                                        rfd::MessageDialog::new()
-                                            .set_title("Error")
-                                            .set_description(format!("Failed to open image: {}", e))
-                                            .show();
+                                        notify.error("Failed to import cube map", e);
                                     }
                                 }
                             }
                         }
 
-                        if ui.button("Save Image").clicked() {
-                            if let Some(out_image) = &*out_image.read() {
-                                let path = rfd::FileDialog::new()
-                                    .add_filter("Image", &["png"])
-                                    .set_file_name("output.png")
-                                    .save_file();
-                                if let Some(path) = path {
-                                    if let Err(e) = out_image.save(path) {
-                                        rfd::MessageDialog::new()
-                                            .set_title("Error")
-                                            .set_description(format!("Failed to save image: {}", e))
-                                            .show();
+                        if ui.button(tr("Dual Fisheye…")).clicked() {
+                            show_fisheye = !show_fisheye;
+                        }
+
+                        if ui.button(tr("Save Image")).clicked() {
+                            let shown = if view_mode {
+                                viewer.params(&params)
+                            } else {
+                                *params
+                            };
+                            // The 360 viewer looks through a projection of its own.
+                            let unscripted = |mut request: RenderRequest| {
+                                request.script = request.script.filter(|_| !view_mode);
+                                request
+                            };
+                            let output = if scene.stereo.is_pair() {
+                                let requests =
+                                    image.as_ref().and_then(|i| scene.pair_requests(i, &shown));
+                                requests.map(|requests| {
+                                    Output::Stereo(
+                                        scene.stereo.layout,
+                                        Box::new(requests.map(unscripted)),
+                                    )
+                                })
+                            } else {
+                                let request = image
+                                    .as_ref()
+                                    .map(|image| unscripted(scene.request(image, &shown)));
+                                // The preview can't be saved as is when it has lines that
+                                // are left out of saved images, or when it is not yet the
+                                // full resolution render of the current parameters.
+                                let preview_lines =
+                                    params.graticule.enabled && params.graticule.preview_only;
+                                let current = !renderer.processing() && !params.has_pending();
+                                let preview =
+                                    renderer.image().filter(|_| current && !preview_lines);
+                                preview
+                                    .map(Output::Image)
+                                    .or_else(|| request.map(Output::Tiles))
+                            };
+                            if let Some(output) = output {
+                                let format = export.format;
+                                let mut dialog = rfd::FileDialog::new()
+                                    .add_filter(format.name(), format.extensions())
+                                    .set_file_name(format!("output.{}", format.extensions()[0]));
+                                if let Some(dir) = &last_dir {
+                                    dialog = dialog.set_directory(dir);
+                                }
+                                for f in Format::ALL.into_iter().filter(|&f| f != format) {
+                                    dialog = dialog.add_filter(f.name(), f.extensions());
+                                }
+                                if let Some(path) = dialog.save_file() {
+                                    last_dir = path.parent().map(Into::into);
+                                    let options = export;
+                                    let source = if export.metadata {
+                                        metadata.clone()
+                                    } else {
+                                        Metadata::default()
+                                    };
+                                    let panorama =
+                                        (params.inverse && !view_mode).then_some(params.size);
+                                    let metadata = source.for_output(panorama, export.gpano);
+                                    let cancel = CancelToken::next(&tile_generation);
+                                    let (sender, progress) = mpsc::channel();
+                                    let size = match &output {
+                                        Output::Image(_) => None,
+                                        Output::Tiles(request) => Some(request.size),
+                                        Output::Stereo(layout, requests) => {
+                                            Some(layout.size(requests[0].size))
+                                        }
+                                    };
+                                    if let Some(size) = size {
+                                        let total = export::tile_count(size);
+                                        tile_progress = Some((progress, 0, total));
                                     }
+                                    let notify = notify.clone();
+                                    saving = Some(thread::spawn(move || {
+                                        let result = match output {
+                                            Output::Image(out_image) => {
+                                                export::save(&out_image, &path, &options)
+                                                    .map_err(AppError::from)
+                                            }
+                                            Output::Tiles(request) => export::save_tiled(
+                                                &request,
+                                                &path,
+                                                &options,
+                                                &cancel,
+                                                |n| {
+                                                    sender.send(n).ok();
+                                                },
+                                            )
+                                            .map_err(AppError::from),
+                                            Output::Stereo(layout, requests) => {
+                                                export::save_stereo(
+                                                    layout,
+                                                    &requests,
+                                                    &path,
+                                                    &options,
+                                                    &cancel,
+                                                    |n| {
+                                                        sender.send(n).ok();
+                                                    },
+                                                )
+                                                .map_err(AppError::from)
+                                            }
+                                        };
+                                        let result =
+                                            result.and_then(|()| Ok(metadata.embed(&path)?));
+                                        match result {
+                                            Err(_) if cancel.is_canceled() => {}
+                                            Err(e) => {
+                                                notify.error("Failed to save image", e);
+                                            }
+                                            Ok(()) => {}
+                                        }
+                                    }));
                                 }
                             }
                         }
+
+                        let save_all = ui.add_enabled(
+                            !views.is_empty() && image.is_some(),
+                            egui::Button::new(tr("Save All Views…")),
+                        );
+                        if let Some(image) = image.as_ref().filter(|_| save_all.clicked()) {
+                            let format = export.format;
+                            let mut dialog = rfd::FileDialog::new()
+                                .add_filter(format.name(), format.extensions())
+                                .set_file_name(format!("output.{}", format.extensions()[0]));
+                            if let Some(dir) = &last_dir {
+                                dialog = dialog.set_directory(dir);
+                            }
+                            if let Some(path) = dialog.save_file() {
+                                last_dir = path.parent().map(Into::into);
+                                // The main output keeps the chosen name, and each view is
+                                // saved next to it with its number.
+                                let source = if export.metadata {
+                                    metadata.clone()
+                                } else {
+                                    Metadata::default()
+                                };
+                                // The views have projections of their own.
+                                let main = (path.clone(), *params, true);
+                                let others = views.iter().map(|view| {
+                                    let path = views::numbered(&path, view.number);
+                                    (path, *view.params, false)
+                                });
+                                let jobs: Vec<_> = std::iter::once(main)
+                                    .chain(others)
+                                    .map(|(path, p, scripted)| {
+                                        let mut request = scene.request(image, &p);
+                                        request.script = request.script.filter(|_| scripted);
+                                        let panorama = p.inverse.then_some(p.size);
+                                        let metadata = source.for_output(panorama, export.gpano);
+                                        (request, path, metadata)
+                                    })
+                                    .collect();
+                                let options = export;
+                                let cancel = CancelToken::next(&tile_generation);
+                                let (sender, progress) = mpsc::channel();
+                                let total =
+                                    jobs.iter().map(|(r, ..)| export::tile_count(r.size)).sum();
+                                tile_progress = Some((progress, 0, total));
+                                let notify = notify.clone();
+                                saving = Some(thread::spawn(move || {
+                                    // Each file counts its tiles from one, after those of the
+                                    // files before.
+                                    let mut before = 0;
+                                    for (request, path, metadata) in jobs {
+                                        let result = export::save_tiled(
+                                            &request,
+                                            &path,
+                                            &options,
+                                            &cancel,
+                                            |n| {
+                                                sender.send(before + n).ok();
+                                            },
+                                        )
+                                        .map_err(AppError::from)
+                                        .and_then(|()| Ok(metadata.embed(&path)?));
+                                        before += export::tile_count(request.size);
+                                        match result {
+                                            Err(_) if cancel.is_canceled() => return,
+                                            Err(e) => {
+                                                notify.error("Failed to save image", e);
+                                                return;
+                                            }
+                                            Ok(()) => {}
+                                        }
+                                    }
+                                }));
+                            }
+                        }
+
+                        if ui.button(tr("Export Options…")).clicked() {
+                            show_export = !show_export;
+                        }
+
+                        if ui.button(tr("Overlay…")).clicked() {
+                            show_overlay = !show_overlay;
+                        }
+
+                        if ui.button(tr("Stickers…")).clicked() {
+                            show_stickers = !show_stickers;
+                        }
+
+                        if ui.button(tr("Cube Map…")).clicked() {
+                            show_cube_export = !show_cube_export;
+                        }
+
+                        if ui.button(tr("Poster…")).clicked() {
+                            show_poster = !show_poster;
+                        }
+
+                        if ui.button(tr("Animation…")).clicked() {
+                            show_animation = !show_animation;
+                        }
+
+                        if ui.button(tr("Live…")).clicked() {
+                            show_live = !show_live;
+                        }
+
+                        if ui.button(tr("Batch…")).clicked() {
+                            show_batch = !show_batch;
+                        }
+
+                        if ui.button(tr("Explore…")).clicked() {
+                            show_explore = !show_explore;
+                            shuffle = show_explore;
+                        }
+
+                        if ui
+                            .button(tr("Add View"))
+                            .on_hover_text(tr(
+                                "Shows the same source in another window, with a projection \
+                                 of its own",
+                            ))
+                            .clicked()
+                        {
+                            views.add(*params);
+                        }
+
+                        if saving.as_ref().is_some_and(|job| !job.is_finished()) {
+                            ui.spinner();
+                            if let Some((progress, done, total)) = &mut tile_progress {
+                                *done = progress.try_iter().last().unwrap_or(*done);
+                                ui.label(trf("Tile {}/{}", &[&*done, &*total]));
+                                if ui.small_button(tr("Cancel")).clicked() {
+                                    CancelToken::next(&tile_generation);
+                                }
+                            }
+                            ctx.request_repaint();
+                        } else {
+                            tile_progress = None;
+                        }
                     });
 
-                    let offset = vector![offset.0, offset.1];
-                    let rotation = Rotation3::from_euler_angles(rotation.0, rotation.1, rotation.2);
+                    ui.horizontal(|ui| {
+                        // egui-winit turns Ctrl+V into a text paste and drops the key press,
+                        // so the shortcut is detected on release.
+                        let paste_key = ui.input(|i| {
+                            i.events.iter().any(|e| match e {
+                                Event::Key {
+                                    key: Key::V,
+                                    pressed: false,
+                                    modifiers,
+                                    ..
+                                } => modifiers.command,
+                                _ => false,
+                            })
+                        });
+                        let paste = ui.button(tr("Paste Image")).clicked() || paste_key;
+                        let copy = ui.button(tr("Copy Result")).clicked();
+                        let hover =
+                            "Copies the parameters, sampler and custom projection as text to share";
+                        let copy_settings = ui
+                            .button(tr("Copy Settings"))
+                            .on_hover_text(tr(hover))
+                            .clicked();
+                        let paste_settings = ui.button(tr("Paste Settings")).clicked();
+                        let used = paste || copy || copy_settings || paste_settings;
+                        if used && clipboard.is_none() {
+                            match arboard::Clipboard::new() {
+                                Ok(c) => clipboard = Some(c),
+                                Err(e) => {
+                                    notify.error("Failed to open clipboard", e);
+                                }
+                            }
+                        }
+                        let Some(clipboard) = &mut clipboard else {
+                            return;
+                        };
+                        if paste {
+                            match clipboard::paste(clipboard) {
+                                Ok(img) => {
+                                    image = Some(Arc::new(img));
+                                    scene.linear = false;
+                                    sequence = None;
+                                    image_path = None;
+                                    metadata = Metadata::default();
+                                    params += true;
+                                }
+                                Err(e) => {
+                                    notify.error("Failed to paste image", e);
+                                }
+                            }
+                        }
+                        if let Some(out_image) = renderer.image().filter(|_| copy) {
+                            if let Err(e) = clipboard::copy(clipboard, &out_image) {
+                                notify.error("Failed to copy image", e);
+                            }
+                        }
+                        if copy_settings {
+                            let text = share::encode(&Shared::new(*params, &scene));
+                            if let Err(e) = clipboard.set_text(text) {
+                                notify.error("Failed to copy settings", e);
+                            }
+                        }
+                        if paste_settings {
+                            let text = clipboard.get_text().map_err(AppError::from);
+                            match text.and_then(|text| share::decode(&text)) {
+                                Ok(shared) => {
+                                    *params = shared.apply(&mut scene);
+                                    ssaa = scene.samples.ilog2().min(3);
+                                    scene.samples = 1 << ssaa;
+                                }
+                                Err(e) => notify.error("Failed to paste settings", e),
+                            }
+                        }
+                    });
 
-                    if !listener.changed() {
-                        return;
+                    ui.horizontal(|ui| {
+                        if ui.button(tr("Open Project")).clicked() {
+                            let mut dialog = rfd::FileDialog::new()
+                                .add_filter(tr("Project"), &[project::EXTENSION]);
+                            if let Some(dir) = &last_dir {
+                                dialog = dialog.set_directory(dir);
+                            }
+                            if let Some(path) = dialog.pick_file() {
+                                last_dir = path.parent().map(Into::into);
+                                let loaded =
+                                    Project::open(&path).map_err(AppError::from).and_then(|p| {
+                                        let seq = p.source.load()?;
+                                        Ok((p, seq))
+                                    });
+                                match loaded {
+                                    Ok((p, seq)) => {
+                                        image = Some(Arc::clone(seq.first()));
+                                        scene.linear = seq.linear;
+                                        metadata = seq.metadata.clone();
+                                        sequence = seq.is_animated().then(|| Arc::new(seq));
+                                        (image_path, embed_image) = match p.source {
+                                            Source::Path(path) => (Some(path), false),
+                                            Source::Embedded(_) => (None, true),
+                                        };
+                                        *params = p.params;
+                                        scene.sampler = p.sampler;
+                                        ssaa = p.samples.ilog2().min(3);
+                                        scene.samples = 1 << ssaa;
+                                        scene.downscale = p.downscale;
+                                        export = p.export;
+                                        scene.custom = p.custom;
+                                        scene.overlay = p.overlay;
+                                        scene.layers.stickers = p.stickers;
+                                        scene.layers.changed();
+                                        scene.stereo = p.stereo;
+                                        params += true;
+                                    }
+                                    Err(e) => {
+                                        notify.error("Failed to open project", e);
+                                    }
+                                }
+                            }
+                        }
+
+                        if let Some(img) = image
+                            .as_ref()
+                            .filter(|_| ui.button(tr("Save Project")).clicked())
+                        {
+                            let mut dialog = rfd::FileDialog::new()
+                                .add_filter(tr("Project"), &[project::EXTENSION])
+                                .set_file_name(format!("untitled.{}", project::EXTENSION));
+                            if let Some(dir) = &last_dir {
+                                dialog = dialog.set_directory(dir);
+                            }
+                            if let Some(path) = dialog.save_file() {
+                                last_dir = path.parent().map(Into::into);
+                                let source = match &image_path {
+                                    Some(source) if !embed_image => {
+                                        Ok(Source::Path(source.clone()))
+                                    }
+                                    source => Source::embed(source.as_deref(), img),
+                                };
+                                let result = source.and_then(|source| {
+                                    Project {
+                                        source,
+                                        params: *params,
+                                        sampler: scene.sampler,
+                                        samples: scene.samples,
+                                        downscale: scene.downscale,
+                                        export,
+                                        custom: scene.custom.clone(),
+                                        overlay: scene.overlay.clone(),
+                                        stickers: scene.layers.stickers.clone(),
+                                        stereo: scene.stereo.clone(),
+                                    }
+                                    .save(&path)
+                                });
+                                if let Err(e) = result {
+                                    notify.error("Failed to save project", e);
+                                }
+                            }
+                        }
+
+                        ui.add_enabled(
+                            image_path.is_some(),
+                            Checkbox::new(&mut embed_image, tr("Embed Image")),
+                        )
+                        .on_disabled_hover_text(tr("Pasted images are always embedded"));
+                    });
+
+                    let mut save_preset = false;
+                    egui::Window::new(tr("Save Preset"))
+                        .open(&mut show_save_preset)
+                        .resizable(false)
+                        .show(ctx, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label(tr("Name"));
+                                let response = ui.text_edit_singleline(&mut preset_name);
+                                let enter = response.lost_focus()
+                                    && ui.input(|i| i.key_pressed(Key::Enter));
+                                save_preset = ui.button(tr("Save")).clicked() || enter;
+                            });
+                        });
+                    if save_preset {
+                        match preset::save(&preset_name, &params) {
+                            Ok(()) => {
+                                presets = preset::list();
+                                show_save_preset = false;
+                            }
+                            Err(e) => {
+                                notify.error("Failed to save preset", e);
+                            }
+                        }
                     }
 
-                    if processing.load(Ordering::Relaxed) {
-                        ui.spinner();
-                    } else if let Some(image) = &image {
-                        let image = Arc::clone(image);
-                        let out_image = Arc::clone(&out_image);
-                        let out_tex = Arc::clone(&out_tex);
-                        let processing = Arc::clone(&processing);
-                        let tex_manager = Arc::clone(&ctx.tex_manager());
-                        thread::spawn(move || {
-                            processing.store(true, Ordering::Relaxed);
-
-                            let mut out = RgbImage::new(600, 600);
-                            let img_size = vector![image.width(), image.height()];
-                            let proj_size = vector![out.width(), out.height()];
-                            let proj =
-                                Projection::new(img_size, proj_size, offset, rotation, scale);
-                            stereographic_projection(&image, &mut out, proj);
-
-                            out_tex.write().replace(SizedTexture::new(
-                                tex_manager.write().alloc(
-                                    "out".into(),
-                                    ColorImage::from_rgb(
-                                        proj_size.cast().into(),
-                                        out.as_flat_samples().as_slice(),
-                                    )
-                                    .into(),
-                                    Default::default(),
-                                ),
-                                <[f32; 2]>::from(proj_size.cast()),
+                    egui::Window::new(tr("Animation"))
+                        .open(&mut show_animation)
+                        .resizable(false)
+                        .show(ctx, |ui| {
+                            let max = timeline.duration;
+                            let scrub = ui.add(
+                                Slider::new(&mut playhead, 0.0..=max)
+                                    .text(tr("Time"))
+                                    .suffix(" s"),
+                            );
+                            if scrub.changed() {
+                                if let Some(p) = timeline.sample(playhead) {
+                                    *params = p;
+                                }
+                            }
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    DragValue::new(&mut timeline.duration)
+                                        .clamp_range(0.1..=600.0)
+                                        .speed(0.1)
+                                        .prefix(tr("Duration: "))
+                                        .suffix(" s"),
+                                );
+                                ui.add(
+                                    DragValue::new(&mut timeline.fps)
+                                        .clamp_range(1..=120)
+                                        .prefix(tr("FPS: ")),
+                                );
+                            });
+                            if ui.button(tr("Add Keyframe")).clicked() {
+                                timeline.insert(playhead, *params);
+                            }
+
+                            let mut remove = None;
+                            for (i, key) in timeline.keyframes().iter().enumerate() {
+                                ui.horizontal(|ui| {
+                                    ui.label(format!("{:.2} s", key.time));
+                                    if ui.button(tr("Go")).clicked() {
+                                        playhead = key.time;
+                                        *params = key.params;
+                                    }
+                                    if ui.button(tr("Remove")).clicked() {
+                                        remove = Some(i);
+                                    }
+                                });
+                            }
+                            if let Some(i) = remove {
+                                timeline.remove(i);
+                            }
+                            ui.separator();
+
+                            ComboBox::from_label(tr("Animation Format"))
+                                .selected_text(tr(animation_format.name()))
+                                .show_ui(ui, |ui| {
+                                    for f in AnimationFormat::ALL {
+                                        ui.selectable_value(&mut animation_format, f, tr(f.name()));
+                                    }
+                                });
+
+                            if let Some(job) = &mut animation_job {
+                                if let Some(done) = job.progress.try_iter().last() {
+                                    job.done = done;
+                                }
+                                if job.handle.is_finished() {
+                                    animation_job = None;
+                                } else {
+                                    ui.horizontal(|ui| {
+                                        let progress = job.done as f32 / job.total as f32;
+                                        let text = trf("{}/{} frames", &[&job.done, &job.total]);
+                                        ui.add(ProgressBar::new(progress).text(text));
+                                        if ui.button(tr("Cancel")).clicked() {
+                                            CancelToken::next(&animation_generation);
+                                        }
+                                    });
+                                    ctx.request_repaint();
+                                }
+                            }
+
+                            if let Some(sequence) = &sequence {
+                                ui.label(trf(
+                                    "The source has {} frames, which are all exported with the \
+                                     current parameters.",
+                                    &[&sequence.frames.len()],
+                                ));
+                            }
+                            let can_export = image.is_some()
+                                && (sequence.is_some() || !timeline.keyframes().is_empty())
+                                && animation_job.is_none();
+                            let export_clicked = ui
+                                .add_enabled(can_export, egui::Button::new(tr("Export Animation")))
+                                .clicked();
+                            let Some(image) = image.as_ref().filter(|_| export_clicked) else {
+                                return;
+                            };
+                            let format = animation_format;
+                            let mut dialog = rfd::FileDialog::new()
+                                .add_filter(format.name(), &[format.extension()])
+                                .set_file_name(format!("animation.{}", format.extension()));
+                            if let Some(dir) = &last_dir {
+                                dialog = dialog.set_directory(dir);
+                            }
+                            let Some(path) = dialog.save_file() else {
+                                return;
+                            };
+                            last_dir = path.parent().map(Into::into);
+                            let cancel = CancelToken::next(&animation_generation);
+                            let (sender, progress) = mpsc::channel();
+                            let total = sequence
+                                .as_ref()
+                                .map_or(timeline.frame_count(), |seq| seq.frames.len() as u32);
+                            let sequence = sequence.clone();
+                            let timeline = timeline.clone();
+                            let image = Arc::clone(image);
+                            let scene = scene.clone();
+                            let params = *params;
+                            let notify = notify.clone();
+                            let handle = thread::spawn(move || {
+                                let result = match sequence {
+                                    Some(sequence) => animation::export_sequence(
+                                        &sequence, &params, scene, &path, format, &cancel, &sender,
+                                    ),
+                                    None => animation::export(
+                                        &timeline, image, scene, &path, format, &cancel, &sender,
+                                    ),
+                                };
+                                match result {
+                                    Err(_) if cancel.is_canceled() => {}
+                                    Err(e) => {
+                                        notify.error("Failed to export animation", e);
+                                    }
+                                    Ok(()) => {}
+                                }
+                            });
+                            animation_job = Some(AnimationJob {
+                                handle,
+                                progress,
+                                done: 0,
+                                total,
+                            });
+                        });
+
+                    egui::Window::new(tr("Live Input"))
+                        .open(&mut show_live)
+                        .resizable(false)
+                        .show(ctx, |ui| {
+                            let mut source = None;
+                            ui.horizontal(|ui| {
+                                ui.label(tr("Device"));
+                                ui.text_edit_singleline(&mut live_device);
+                                if ui.button(tr("Start Webcam")).clicked() {
+                                    source = Some(LiveSource::Webcam(live_device.clone()));
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                if ui.button(tr("Open Video…")).clicked() {
+                                    let mut dialog = rfd::FileDialog::new().add_filter(
+                                        tr("Video"),
+                                        &["mp4", "mov", "mkv", "webm", "avi", "insv"],
+                                    );
+                                    if let Some(dir) = &last_dir {
+                                        dialog = dialog.set_directory(dir);
+                                    }
+                                    if let Some(path) = dialog.pick_file() {
+                                        last_dir = path.parent().map(Into::into);
+                                        source = Some(LiveSource::Video(path));
+                                    }
+                                }
+                                let running = live.as_ref().is_some_and(LiveInput::running);
+                                if ui
+                                    .add_enabled(live.is_some(), egui::Button::new(tr("Stop")))
+                                    .clicked()
+                                {
+                                    if let Some(input) = live.take() {
+                                        input.stop();
+                                    }
+                                }
+                                match &live {
+                                    Some(_) if running => ui.label(tr("Playing")),
+                                    Some(_) => ui.weak(tr("Stopped")),
+                                    None => ui.weak(tr("Off")),
+                                };
+                            });
+                            ui.weak(trf(
+                                "Frames are decoded by ffmpeg and scaled to {}×{}",
+                                &[&live::FRAME_SIZE.0, &live::FRAME_SIZE.1],
                             ));
-                            out_image.write().replace(out);
+                            let Some(source) = source else {
+                                return;
+                            };
+                            if let Some(input) = live.take() {
+                                input.stop();
+                            }
+                            match LiveInput::start(&source) {
+                                Ok(input) => live = Some(input),
+                                Err(e) => {
+                                    notify.error("Failed to start live input", e);
+                                }
+                            }
+                        });
+
+                    egui::Window::new(tr("Batch"))
+                        .open(&mut show_batch)
+                        .show(ctx, |ui| {
+                            let running = batch.running();
+                            ui.add_enabled_ui(!running, |ui| {
+                                for (label, folder) in [
+                                    (tr("Input Folder"), &mut batch.input),
+                                    (tr("Output Folder"), &mut batch.output),
+                                ] {
+                                    ui.horizontal(|ui| {
+                                        if ui.button(label).clicked() {
+                                            let mut dialog = rfd::FileDialog::new();
+                                            if let Some(dir) = folder.as_ref().or(last_dir.as_ref())
+                                            {
+                                                dialog = dialog.set_directory(dir);
+                                            }
+                                            if let Some(dir) = dialog.pick_folder() {
+                                                *folder = Some(dir);
+                                            }
+                                        }
+                                        match folder {
+                                            Some(dir) => ui.label(dir.display().to_string()),
+                                            None => ui.weak(tr("None")),
+                                        };
+                                    });
+                                }
+                                ui.horizontal(|ui| {
+                                    ui.label(tr("File Name"));
+                                    ui.text_edit_singleline(&mut batch.pattern);
+                                    ui.label(format!(".{}", export.format.extensions()[0]));
+                                })
+                                .response
+                                .on_hover_text(tr(
+                                    "{name} is replaced by the input file name and {index} by \
+                                     its number",
+                                ));
+                                ui.checkbox(&mut batch.parallel, tr("Process in Parallel"));
+                            });
 
-                            processing.store(false, Ordering::Relaxed);
+                            ui.horizontal(|ui| {
+                                if running {
+                                    if ui.button(tr("Cancel")).clicked() {
+                                        batch.cancel();
+                                    }
+                                    ui.spinner();
+                                    ctx.request_repaint();
+                                } else if ui.button(tr("Start")).clicked() {
+                                    if let Err(e) = batch.start(*params, scene.clone(), export) {
+                                        notify.error("Failed to start batch", e);
+                                    }
+                                }
+                            });
+
+                            batch.poll();
+                            egui::ScrollArea::vertical()
+                                .max_height(240.0)
+                                .show(ui, |ui| {
+                                    for (file, status) in batch.files() {
+                                        ui.horizontal(|ui| {
+                                            let name = file.file_name().unwrap_or_default();
+                                            ui.label(name.to_string_lossy());
+                                            match status {
+                                                batch::Status::Pending => ui.weak(tr("Pending")),
+                                                batch::Status::Running => ui.spinner(),
+                                                batch::Status::Done => ui.label(tr("Done")),
+                                                batch::Status::Failed(e) => ui.colored_label(
+                                                    ui.visuals().error_fg_color,
+                                                    format!("{}: {e}", tr("Failed")),
+                                                ),
+                                            };
+                                        });
+                                    }
+                                });
                         });
+
+                    views.show(ctx, &mut params);
+
+                    egui::Window::new(tr("Explore"))
+                        .open(&mut show_explore)
+                        .resizable(false)
+                        .show(ctx, |ui| {
+                            ui.horizontal(|ui| {
+                                let spread = Slider::new(&mut explore.spread, 0.05..=1.0);
+                                ui.add(spread.text(tr("Spread"))).on_hover_text(tr(
+                                    "How far the variations stray from the current one",
+                                ));
+                                shuffle |= ui.button(tr("Shuffle")).clicked();
+                                if explore.running() {
+                                    ui.spinner();
+                                }
+                            });
+                            if let Some(picked) = explore.show(ui) {
+                                params.rotation = picked.rotation;
+                                params.scale = picked.scale;
+                                params.offset = picked.offset;
+                            }
+                        });
+                    if !show_explore {
+                        explore.clear();
+                    } else if let Some(image) = image.as_ref().filter(|_| shuffle) {
+                        // Quick thumbnails without the overlay.
+                        let mut base = scene.request(image, &params);
+                        (base.samples, base.overlay) = (1, None);
+                        explore.generate(ctx, base, &params);
                     }
+
+                    egui::Window::new(tr("Dual Fisheye"))
+                        .open(&mut show_fisheye)
+                        .resizable(false)
+                        .show(ctx, |ui| {
+                            let mut convert = false;
+                            if ui.button(tr("Open Raw…")).clicked() {
+                                let mut dialog = rfd::FileDialog::new().add_filter(
+                                    tr("Image"),
+                                    &["jpg", "jpeg", "png", "tif", "tiff", "dng", "insp"],
+                                );
+                                if let Some(dir) = &last_dir {
+                                    dialog = dialog.set_directory(dir);
+                                }
+                                if let Some(path) = dialog.pick_file() {
+                                    last_dir = path.parent().map(Into::into);
+                                    match image::open(&path) {
+                                        Ok(raw) => {
+                                            fisheye_raw = Some(raw.into_rgba32f());
+                                            convert = true;
+                                        }
+                                        Err(e) => {
+                                            notify.error("Failed to open image", e);
+                                        }
+                                    }
+                                }
+                            }
+                            // Stitching a full resolution raw is slow, so it is only redone
+                            // once a slider is let go.
+                            for response in [
+                                ui.add(
+                                    Slider::new(&mut fisheye.fov, 180.0..=240.0)
+                                        .text(tr("Lens FOV"))
+                                        .suffix("°"),
+                                ),
+                                ui.add(
+                                    Slider::new(&mut fisheye.overlap, 0.0..=30.0)
+                                        .text(tr("Stitch Overlap"))
+                                        .suffix("°"),
+                                ),
+                            ] {
+                                convert |= response.drag_released()
+                                    || (response.changed() && !response.dragged());
+                            }
+                            if let Some(raw) = fisheye_raw.as_ref().filter(|_| convert) {
+                                image = Some(Arc::new(fisheye.stitch(raw, scene.sampler)));
+                                scene.linear = false;
+                                sequence = None;
+                                image_path = None;
+                                metadata = Metadata::default();
+                                params += true;
+                            }
+                        });
+
+                    egui::Window::new(tr("Overlay"))
+                        .open(&mut show_overlay)
+                        .resizable(false)
+                        .show(ctx, |ui| {
+                            params += ui.checkbox(&mut scene.overlay.enabled, tr("Show Overlay"));
+                            ui.horizontal(|ui| {
+                                for kind in StampKind::ALL {
+                                    params += ui.radio_value(
+                                        &mut scene.overlay.kind,
+                                        kind,
+                                        tr(kind.name()),
+                                    );
+                                }
+                            });
+                            match scene.overlay.kind {
+                                StampKind::Text => {
+                                    params += ui.text_edit_multiline(&mut scene.overlay.text);
+                                    ui.horizontal(|ui| {
+                                        params += ui.color_edit_button_rgba_unmultiplied(
+                                            &mut scene.overlay.color,
+                                        );
+                                        let mut font = ui.button(tr("Font…"));
+                                        if fonts::cjk().is_none() {
+                                            font = font.on_hover_text(tr(
+                                                "No system font with Chinese characters was found",
+                                            ));
+                                        }
+                                        if font.clicked() {
+                                            let mut dialog = rfd::FileDialog::new()
+                                                .add_filter(tr("Font"), &["ttf", "otf", "ttc"]);
+                                            if let Some(dir) = &last_dir {
+                                                dialog = dialog.set_directory(dir);
+                                            }
+                                            if let Some(path) = dialog.pick_file() {
+                                                match overlay::load_font(&path) {
+                                                    Ok(font) => {
+                                                        scene.overlay.font = font;
+                                                        scene.overlay.font_path = Some(path);
+                                                        params += true;
+                                                    }
+                                                    Err(e) => {
+                                                        notify.error("Failed to load font", e);
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    });
+                                }
+                                StampKind::Logo => {
+                                    if ui.button(tr("Open Logo…")).clicked() {
+                                        let mut dialog = rfd::FileDialog::new().add_filter(
+                                            tr("Image"),
+                                            &["png", "jpg", "jpeg", "webp"],
+                                        );
+                                        if let Some(dir) = &last_dir {
+                                            dialog = dialog.set_directory(dir);
+                                        }
+                                        if let Some(path) = dialog.pick_file() {
+                                            match image::open(&path) {
+                                                Ok(logo) => {
+                                                    scene.overlay.logo =
+                                                        Some(Arc::new(logo.into_rgba32f()));
+                                                    params += true;
+                                                }
+                                                Err(e) => {
+                                                    notify.error("Failed to open image", e);
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                            let horizontal = Slider::new(&mut scene.overlay.position.0, 0.0..=1.0);
+                            params += ui.add(horizontal.text(tr("Horizontal")));
+                            let vertical = Slider::new(&mut scene.overlay.position.1, 0.0..=1.0);
+                            params += ui.add(vertical.text(tr("Vertical")));
+                            params += ui.add(
+                                Slider::new(&mut scene.overlay.size, 0.01..=0.5).text(tr("Size")),
+                            );
+                            let opacity = Slider::new(&mut scene.overlay.opacity, 0.0..=1.0);
+                            params += ui.add(opacity.text(tr("Opacity")));
+                        });
+
+                    egui::Window::new(tr("Stickers"))
+                        .open(&mut show_stickers)
+                        .resizable(false)
+                        .show(ctx, |ui| {
+                            let mut edited = false;
+                            if ui.button(tr("Add Sticker…")).clicked() {
+                                let mut dialog = rfd::FileDialog::new()
+                                    .add_filter(tr("Image"), &["png", "jpg", "jpeg", "webp"]);
+                                if let Some(dir) = &last_dir {
+                                    dialog = dialog.set_directory(dir);
+                                }
+                                if let Some(path) = dialog.pick_file() {
+                                    last_dir = path.parent().map(Into::into);
+                                    match image::open(&path) {
+                                        Ok(img) => {
+                                            let name = path
+                                                .file_stem()
+                                                .unwrap_or_default()
+                                                .to_string_lossy()
+                                                .into_owned();
+                                            let img = Arc::new(img.into_rgba32f());
+                                            scene.layers.stickers.push(Sticker::new(name, img));
+                                            edited = true;
+                                        }
+                                        Err(e) => {
+                                            notify.error("Failed to open image", e);
+                                        }
+                                    }
+                                }
+                            }
+                            let mut removed = None;
+                            for (i, sticker) in scene.layers.stickers.iter_mut().enumerate() {
+                                ui.separator();
+                                ui.horizontal(|ui| {
+                                    edited |=
+                                        ui.checkbox(&mut sticker.visible, &sticker.name).changed();
+                                    if ui.button(tr("Remove")).clicked() {
+                                        removed = Some(i);
+                                    }
+                                });
+                                for response in [
+                                    ui.add(
+                                        Slider::new(&mut sticker.longitude, -180.0..=180.0)
+                                            .text(tr("Longitude"))
+                                            .suffix("°"),
+                                    ),
+                                    ui.add(
+                                        Slider::new(&mut sticker.latitude, -90.0..=90.0)
+                                            .text(tr("Latitude"))
+                                            .suffix("°"),
+                                    ),
+                                    ui.add(
+                                        Slider::new(&mut sticker.size, 1.0..=120.0)
+                                            .text(tr("Size"))
+                                            .suffix("°"),
+                                    ),
+                                ] {
+                                    edited |= response.changed();
+                                }
+                            }
+                            if let Some(i) = removed {
+                                scene.layers.stickers.remove(i);
+                                edited = true;
+                            }
+                            if edited {
+                                scene.layers.changed();
+                                params += true;
+                            }
+                        });
+
+                    egui::Window::new(tr("Cube Map Export"))
+                        .open(&mut show_cube_export)
+                        .resizable(false)
+                        .show(ctx, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label(tr("Face Size"));
+                                ui.add(DragValue::new(&mut cube_size).clamp_range(16..=8192));
+                            });
+                            ComboBox::from_label(tr("Layout"))
+                                .selected_text(tr(cube_layout.name()))
+                                .show_ui(ui, |ui| {
+                                    for l in Layout::ALL {
+                                        ui.selectable_value(&mut cube_layout, l, tr(l.name()));
+                                    }
+                                });
+                            ui.label(trf("Saved as {}", &[&export.format.name()]));
+                            let busy = saving.as_ref().is_some_and(|job| !job.is_finished());
+                            let export_button = egui::Button::new(tr("Export"));
+                            let enabled = image.is_some() && !busy;
+                            let export_button = ui.add_enabled(enabled, export_button);
+                            if !export_button.clicked() {
+                                return;
+                            }
+                            let Some(source) = image.clone() else {
+                                return;
+                            };
+                            let format = export.format;
+                            let name = match cube_layout {
+                                Layout::Cross => "cubemap",
+                                Layout::Separate => "skybox",
+                            };
+                            let mut dialog = rfd::FileDialog::new()
+                                .add_filter(format.name(), format.extensions())
+                                .set_file_name(format!("{name}.{}", format.extensions()[0]));
+                            if let Some(dir) = &last_dir {
+                                dialog = dialog.set_directory(dir);
+                            }
+                            let Some(path) = dialog.save_file() else {
+                                return;
+                            };
+                            last_dir = path.parent().map(Into::into);
+                            let (size, layout, options) = (cube_size, cube_layout, export);
+                            let notify = notify.clone();
+                            saving = Some(thread::spawn(move || {
+                                let cube = CubeMap::from_equirect(&source, size, scene.sampler);
+                                if let Err(e) = cube.save(&path, layout, &options) {
+                                    notify.error("Failed to export cube map", e);
+                                }
+                            }));
+                        });
+
+                    egui::Window::new(tr("Poster"))
+                        .open(&mut show_poster)
+                        .resizable(false)
+                        .show(ctx, |ui| {
+                            ComboBox::from_label(tr("Paper"))
+                                .selected_text(tr(poster.paper.name()))
+                                .show_ui(ui, |ui| {
+                                    for p in Paper::ALL {
+                                        ui.selectable_value(&mut poster.paper, p, tr(p.name()));
+                                    }
+                                });
+                            ui.checkbox(&mut poster.landscape, tr("Landscape"));
+                            ui.horizontal(|ui| {
+                                let dpi = DragValue::new(&mut poster.dpi).clamp_range(36..=1200);
+                                ui.add(dpi.suffix(" dpi"));
+                                ui.label(tr("Resolution"));
+                            });
+                            ui.horizontal(|ui| {
+                                let overlap = DragValue::new(&mut poster.overlap)
+                                    .clamp_range(0.0..=50.0)
+                                    .suffix(" mm");
+                                ui.add(overlap);
+                                ui.label(tr("Overlap"))
+                                    .on_hover_text(tr("How far pages reach over their neighbors"));
+                            });
+                            let (cols, rows) = poster.grid(params.size);
+                            let (w, h) = poster.printed_size(params.size);
+                            let (w, h) = (format!("{w:.0}"), format!("{h:.0}"));
+                            ui.label(trf("{} × {} pages, {} × {} cm", &[&cols, &rows, &w, &h]));
+                            ui.checkbox(&mut poster.pdf, tr("Save as PDF"));
+                            if !poster.pdf {
+                                ui.label(trf("Saved as {}", &[&export.format.name()]));
+                            }
+                            let busy = saving.as_ref().is_some_and(|job| !job.is_finished());
+                            let export_button = egui::Button::new(tr("Export"));
+                            let enabled = image.is_some() && !busy;
+                            let export_button = ui.add_enabled(enabled, export_button);
+                            if !export_button.clicked() {
+                                return;
+                            }
+                            let Some(image) = &image else {
+                                return;
+                            };
+                            let format = export.format;
+                            let (filter, extensions) = if poster.pdf {
+                                ("PDF", &["pdf"][..])
+                            } else {
+                                (format.name(), format.extensions())
+                            };
+                            let mut dialog = rfd::FileDialog::new()
+                                .add_filter(filter, extensions)
+                                .set_file_name(format!("poster.{}", extensions[0]));
+                            if let Some(dir) = &last_dir {
+                                dialog = dialog.set_directory(dir);
+                            }
+                            let Some(path) = dialog.save_file() else {
+                                return;
+                            };
+                            last_dir = path.parent().map(Into::into);
+                            let request = scene.request(image, &params);
+                            let cancel = CancelToken::next(&tile_generation);
+                            let (sender, progress) = mpsc::channel();
+                            tile_progress = Some((progress, 0, cols * rows));
+                            let (options, notify) = (poster, notify.clone());
+                            saving = Some(thread::spawn(move || {
+                                let result = poster::save(
+                                    &request, &path, &options, &export, &cancel, &sender,
+                                );
+                                match result {
+                                    Err(_) if cancel.is_canceled() => {}
+                                    Err(e) => notify.error("Failed to export poster", e),
+                                    Ok(()) => {}
+                                }
+                            }));
+                        });
+
+                    egui::Window::new(tr("Export Options"))
+                        .open(&mut show_export)
+                        .resizable(false)
+                        .show(ctx, |ui| {
+                            ComboBox::from_label(tr("Format"))
+                                .selected_text(tr(export.format.name()))
+                                .show_ui(ui, |ui| {
+                                    for f in Format::ALL {
+                                        ui.selectable_value(&mut export.format, f, tr(f.name()));
+                                    }
+                                });
+                            ui.add_enabled(
+                                export.format.has_sixteen_bit(),
+                                Checkbox::new(&mut export.sixteen_bit, tr("16-bit")),
+                            );
+                            ui.add_enabled(
+                                export.format.has_quality(),
+                                Slider::new(&mut export.quality, 1..=100).text(tr("Quality")),
+                            );
+                            ui.checkbox(&mut export.metadata, tr("Keep Metadata"))
+                                .on_hover_text(tr(
+                                    "Copies the EXIF data of the source into JPEG and PNG files, \
+                                     and its XMP data into panoramas",
+                                ));
+                            ui.checkbox(&mut export.gpano, tr("Tag Panoramas"))
+                                .on_hover_text(tr(
+                                    "Writes GPano tags into unwrapped panoramas, so that \
+                                     panorama viewers show them in 360°",
+                                ));
+                            if export.format == Format::WebP {
+                                ui.weak(tr("WebP is always saved lossless"));
+                            }
+                            if !export.format.streams() {
+                                ui.weak(trf(
+                                    "{} images can't be larger than {} pixels; save them as \
+                                     PNG, TIFF or OpenEXR",
+                                    &[&export.format.name(), &render::MAX_PREVIEW_SIZE],
+                                ));
+                            }
+                            ui.separator();
+
+                            ComboBox::from_label(tr("Coordinate Map"))
+                                .selected_text(tr(remap_format.name()))
+                                .show_ui(ui, |ui| {
+                                    for f in RemapFormat::ALL {
+                                        ui.selectable_value(&mut remap_format, f, tr(f.name()));
+                                    }
+                                });
+                            let clicked = ui
+                                .add_enabled(image.is_some(), egui::Button::new(tr("Export Map…")))
+                                .on_hover_text(tr(
+                                    "Saves the source pixel of every output pixel, to apply the \
+                                     same warp to videos",
+                                ))
+                                .clicked();
+                            let Some(image) = image.as_ref().filter(|_| clicked) else {
+                                return;
+                            };
+                            let format = remap_format;
+                            let mut dialog = rfd::FileDialog::new()
+                                .add_filter(format.name(), &[format.extension()])
+                                .set_file_name(format!("map.{}", format.extension()));
+                            if let Some(dir) = &last_dir {
+                                dialog = dialog.set_directory(dir);
+                            }
+                            let Some(path) = dialog.save_file() else {
+                                return;
+                            };
+                            last_dir = path.parent().map(Into::into);
+                            // The eye, stickers and script of the preview.
+                            let request = scene.request(image, &params);
+                            let notify = notify.clone();
+                            saving = Some(thread::spawn(move || {
+                                if let Err(e) = remap::export(&request, &path, format) {
+                                    notify.error("Failed to export map", e);
+                                }
+                            }));
+                        });
+
+                    if renderer.processing() {
+                        let progress = renderer.progress().unwrap_or_default();
+                        ui.add(ProgressBar::new(progress).show_percentage());
+                        ctx.request_repaint();
+                    }
+
+                    // Changes are recorded once the pointer is released, so that a
+                    // whole drag is undone at once.
+                    let now = Instant::now();
+                    let changes = params.update(now);
+                    history_pending |= changes.in_params();
+                    if history_pending && !ctx.input(|i| i.pointer.any_down()) {
+                        history.record(&params);
+                        history_pending = false;
+                    }
+
+                    if let Some(image) = &image {
+                        for view in views.due(now, changes.contains(Changes::OTHER)) {
+                            let mut request = scene.request(image, &view.params);
+                            if view.params.graticule.enabled {
+                                request.graticule = Some(view.params.graticule);
+                            }
+                            request.script = None;
+                            view.renderer.submit(request, ctx);
+                        }
+                    }
+                    if let Some(wait) = views.wait(now) {
+                        ctx.request_repaint_after(wait);
+                    }
+
+                    if params.due(now).is_none() {
+                        if let Some(wait) = params.wait(now) {
+                            ctx.request_repaint_after(wait);
+                        }
+                        return;
+                    }
+
+                    let Some(image) = &image else {
+                        return;
+                    };
+                    let shown = if view_mode {
+                        viewer.params(&params)
+                    } else {
+                        *params
+                    };
+                    let mut request = scene.request(image, &shown);
+                    if shown.graticule.enabled {
+                        request.graticule = Some(shown.graticule);
+                    }
+                    request.script = request.script.filter(|_| !view_mode);
+
+                    if let Some(Ok(gpu)) = gpu.as_mut().filter(|_| backend != Backend::Cpu) {
+                        let start = Instant::now();
+                        match gpu.render(&request) {
+                            Ok(out) => {
+                                let size = request.size;
+                                let stats = RenderStats::new(&request, size, start.elapsed());
+                                renderer.publish(ctx, out, stats);
+                                return;
+                            }
+                            Err(e) if backend == Backend::Gpu => {
+                                ui.label(format!("{}: {e}", tr("GPU render failed")));
+                            }
+                            Err(_) => {}
+                        }
+                    }
+
+                    renderer.submit(request, ctx);
                 });
 
-                if let Some(out_tex) = *out_tex.read() {
-                    ui.image(ImageSource::Texture(out_tex));
+                if let Some(out_tex) = renderer.texture() {
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui| {
+                            if ui.button(tr("Fit")).clicked() {
+                                zoom.fit();
+                            }
+                            if ui.button("100%").clicked() {
+                                zoom.actual_size(ui);
+                            }
+                            if let Some(percent) = zoom.percent(ui) {
+                                ui.label(format!("{percent:.0}%"));
+                            }
+                            ui.separator();
+                            let rendered = renderer.image();
+                            let pin = ui
+                                .add_enabled(rendered.is_some(), egui::Button::new(tr("Pin as A")))
+                                .on_hover_text(tr(
+                                    "Keeps this render to compare the next ones with",
+                                ));
+                            if let Some(rendered) = rendered.filter(|_| pin.clicked()) {
+                                compare.pin(ctx, &rendered, *params);
+                            }
+                            if let Some(snapshot) = &compare.snapshot {
+                                if ui.button(tr("Restore A")).clicked() {
+                                    *params = snapshot.params;
+                                    preview_changed = true;
+                                }
+                                for mode in CompareMode::ALL {
+                                    ui.radio_value(&mut compare.mode, mode, tr(mode.name()));
+                                }
+                            }
+                        });
+                        let response =
+                            zoom.show(ui, out_tex.size, compare.columns(), |ui, size| {
+                                let response = compare.show(ui, out_tex, size);
+                                if let Some(mask) = renderer.analysis.mask() {
+                                    let rect = response.rect;
+                                    let uv = Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0));
+                                    ui.painter_at(rect).image(mask.id, rect, uv, Color32::WHITE);
+                                }
+                                response
+                            });
+                        if let Some(stats) = renderer.stats().filter(|_| show_stats) {
+                            let rect = response.rect.intersect(ui.clip_rect());
+                            render_stats(ui, rect, &stats);
+                        }
+                        let extent = response.rect.size();
+                        let delta = response.drag_delta();
+                        if view_mode {
+                            if response.dragged_by(PointerButton::Primary) && delta != Vec2::ZERO {
+                                viewer.look(delta, extent.min_elem());
+                                preview_changed = true;
+                            }
+                            let scroll = ui.input(|i| i.raw_scroll_delta.y);
+                            if response.hovered() && scroll != 0.0 {
+                                viewer.zoom(scroll);
+                                preview_changed = true;
+                            }
+                        } else if response.dragged_by(PointerButton::Primary)
+                            && delta != Vec2::ZERO
+                            && !compare.is_dragging()
+                        {
+                            let r = params.view_rotation()
+                                * gizmo::drag_rotation(delta, extent.min_elem());
+                            params.set_view_rotation(r);
+                            preview_changed = true;
+                        }
+                        if !view_mode
+                            && response.dragged_by(PointerButton::Middle)
+                            && delta != Vec2::ZERO
+                        {
+                            params.offset.0 -= delta.x / extent.x;
+                            params.offset.1 -= delta.y / extent.y;
+                            preview_changed = true;
+                        }
+                        if !view_mode && response.hovered() {
+                            let scroll = ui.input(|i| i.raw_scroll_delta.y);
+                            if scroll != 0.0 {
+                                params.scale =
+                                    (params.scale * (scroll * 0.002).exp()).clamp(0.1, 5.0);
+                                preview_changed = true;
+                            }
+                        }
+                        if preview_changed {
+                            ctx.request_repaint();
+                        }
+                    });
                 }
             });
         });
+        toasts.show(ctx);
     })
 }
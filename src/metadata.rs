@@ -0,0 +1,344 @@
+//! EXIF and XMP metadata of JPEG and PNG files, which `image` neither reads
+//! nor writes, and the color space they are tagged with.
+
+use std::{fs, io, path::Path};
+
+use image::{DynamicImage, Rgba32FImage};
+
+use crate::icc;
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+const ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const ORIENTATION: u16 = 0x0112;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    /// The TIFF structure of the EXIF data.
+    pub exif: Option<Vec<u8>>,
+    /// The XMP packet.
+    pub xmp: Option<String>,
+    /// Whether the file is tagged as sRGB, which every output is.
+    pub srgb: bool,
+}
+
+/// Segments of a JPEG file before the image data, as marker and payload.
+fn jpeg_segments(bytes: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    let mut rest = bytes.strip_prefix(b"\xff\xd8").unwrap_or_default();
+    std::iter::from_fn(move || {
+        let [0xff, marker, hi, lo, ..] = *rest else {
+            return None;
+        };
+        let len = u16::from_be_bytes([hi, lo]) as usize;
+        if marker == 0xda || len < 2 || rest.len() < 2 + len {
+            return None;
+        }
+        let payload = &rest[4..2 + len];
+        rest = &rest[2 + len..];
+        Some((marker, payload))
+    })
+}
+
+/// Chunks of a PNG file, as type and data.
+fn png_chunks(bytes: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut rest = bytes.strip_prefix(PNG_SIGNATURE).unwrap_or_default();
+    std::iter::from_fn(move || {
+        let len = u32::from_be_bytes(rest.get(..4)?.try_into().unwrap()) as usize;
+        let kind = rest.get(4..8)?;
+        let data = rest.get(8..8 + len)?;
+        rest = rest.get(12 + len..)?;
+        Some((kind, data))
+    })
+}
+
+fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    chunk.extend_from_slice(&crc.finalize().to_be_bytes());
+    chunk
+}
+
+/// Offset of the value of `tag` in the first IFD of a TIFF structure, with
+/// whether it is big-endian.
+fn find_tag(tiff: &[u8], tag: u16) -> Option<(usize, bool)> {
+    let big = match tiff.get(..4)? {
+        b"MM\0*" => true,
+        b"II*\0" => false,
+        _ => return None,
+    };
+    let u16_at = |i: usize| {
+        let b = tiff.get(i..i + 2)?.try_into().unwrap();
+        Some(if big {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        })
+    };
+    let b = tiff.get(4..8)?.try_into().unwrap();
+    let ifd = if big {
+        u32::from_be_bytes(b)
+    } else {
+        u32::from_le_bytes(b)
+    } as usize;
+    (0..u16_at(ifd)? as usize)
+        .map(|i| ifd + 2 + 12 * i)
+        .find(|&entry| u16_at(entry) == Some(tag))
+        .map(|entry| (entry + 8, big))
+}
+
+impl Metadata {
+    /// Reads the metadata of a JPEG or PNG file, and nothing from other files.
+    pub fn read(bytes: &[u8]) -> Self {
+        let mut metadata = Metadata::default();
+        for (_, payload) in jpeg_segments(bytes).filter(|&(marker, _)| marker == 0xe1) {
+            if let Some(exif) = payload.strip_prefix(EXIF_HEADER) {
+                metadata.exif = Some(exif.to_vec());
+            } else if let Some(xmp) = payload.strip_prefix(XMP_HEADER) {
+                metadata.xmp = String::from_utf8(xmp.to_vec()).ok();
+            }
+        }
+        for (kind, data) in png_chunks(bytes) {
+            match kind {
+                b"eXIf" => metadata.exif = Some(data.to_vec()),
+                // Only uncompressed text, which is how XMP is usually stored.
+                b"iTXt" if data.starts_with(XMP_KEYWORD) => {
+                    let rest = &data[XMP_KEYWORD.len()..];
+                    if let [0, 0, 0, rest @ ..] = rest {
+                        let mut fields = rest.splitn(3, |&b| b == 0);
+                        if let (Some(_), Some(_), Some(text)) =
+                            (fields.next(), fields.next(), fields.next())
+                        {
+                            metadata.xmp = String::from_utf8(text.to_vec()).ok();
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        metadata
+    }
+
+    /// The EXIF orientation, from 1 (upright) to 8.
+    pub fn orientation(&self) -> u16 {
+        self.exif
+            .as_deref()
+            .and_then(|tiff| {
+                let (i, big) = find_tag(tiff, ORIENTATION)?;
+                let b = tiff.get(i..i + 2)?.try_into().unwrap();
+                Some(if big {
+                    u16::from_be_bytes(b)
+                } else {
+                    u16::from_le_bytes(b)
+                })
+            })
+            .filter(|o| (1..=8).contains(o))
+            .unwrap_or(1)
+    }
+
+    /// The EXIF data marked upright, since loaded images are turned upright.
+    fn upright_exif(&self) -> Option<Vec<u8>> {
+        let mut tiff = self.exif.clone()?;
+        if let Some((i, big)) = find_tag(&tiff, ORIENTATION) {
+            if let Some(value) = tiff.get_mut(i..i + 2) {
+                value.copy_from_slice(&if big {
+                    1u16.to_be_bytes()
+                } else {
+                    1u16.to_le_bytes()
+                });
+            }
+        }
+        Some(tiff)
+    }
+
+    pub fn gpano(&self) -> Option<GPano> {
+        GPano::parse(self.xmp.as_deref()?)
+    }
+
+    /// The metadata to write into an output, which is a panorama of `size`
+    /// for inverse projections. XMP is only kept for panoramas, as it mostly
+    /// describes the panorama, and is replaced by fresh GPano tags with
+    /// `gpano`.
+    pub fn for_output(&self, panorama: Option<(u32, u32)>, gpano: bool) -> Metadata {
+        let xmp = match panorama {
+            Some(size) if gpano => Some(GPano::full(size).to_xmp()),
+            Some(_) => self.xmp.clone(),
+            None => None,
+        };
+        Metadata {
+            exif: self.exif.clone(),
+            xmp,
+            srgb: true,
+        }
+    }
+
+    /// Copies the metadata into the JPEG or PNG file at `path`, leaving other
+    /// files as they are.
+    pub fn embed(&self, path: &Path) -> io::Result<()> {
+        let exif = self.upright_exif();
+        let xmp = self.xmp.as_deref();
+        if exif.is_none() && xmp.is_none() && !self.srgb {
+            return Ok(());
+        }
+        let bytes = fs::read(path)?;
+        let out = if let Some(body) = bytes.strip_prefix(b"\xff\xd8") {
+            let mut out = b"\xff\xd8".to_vec();
+            // The profile fits in one segment, the first of one.
+            let segments = [
+                exif.map(|exif| (0xe1, [EXIF_HEADER, &exif].concat())),
+                xmp.map(|xmp| (0xe1, [XMP_HEADER, xmp.as_bytes()].concat())),
+                self.srgb
+                    .then(|| (0xe2, [ICC_HEADER, &[1, 1], &icc::srgb_profile()].concat())),
+            ];
+            // Segments are limited to 64 KiB, so larger ones are left out.
+            let segments = segments.into_iter().flatten();
+            for (marker, payload) in segments.filter(|(_, p)| p.len() <= 0xfffd) {
+                out.extend_from_slice(&[0xff, marker]);
+                out.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+                out.extend_from_slice(&payload);
+            }
+            out.extend_from_slice(body);
+            out
+        } else if bytes.starts_with(PNG_SIGNATURE) {
+            // The new chunks go right after the header chunk.
+            let header = PNG_SIGNATURE.len() + 25;
+            let mut out = bytes[..header.min(bytes.len())].to_vec();
+            if let Some(exif) = exif {
+                out.extend(png_chunk(b"eXIf", &exif));
+            }
+            if let Some(xmp) = xmp {
+                let text = [XMP_KEYWORD, b"\0\0\0\0\0", xmp.as_bytes()].concat();
+                out.extend(png_chunk(b"iTXt", &text));
+            }
+            if self.srgb {
+                // With the perceptual rendering intent.
+                out.extend(png_chunk(b"sRGB", &[0]));
+            }
+            out.extend_from_slice(bytes.get(header..).unwrap_or_default());
+            out
+        } else {
+            return Ok(());
+        };
+        fs::write(path, out)
+    }
+}
+
+/// Turns an image stored with an EXIF `orientation` upright.
+pub fn orient(img: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+/// The Google Photo Sphere tags placing an image on the sphere, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GPano {
+    pub full_size: (u32, u32),
+    pub cropped_size: (u32, u32),
+    /// Position of the image in the full panorama.
+    pub cropped_origin: (u32, u32),
+}
+
+/// Value of a `GPano` property, written either as an attribute or as an
+/// element.
+fn property<'a>(xmp: &'a str, name: &str) -> Option<&'a str> {
+    let attribute = format!("GPano:{name}=");
+    if let Some(i) = xmp.find(&attribute) {
+        let rest = &xmp[i + attribute.len()..];
+        let quote = rest.chars().next()?;
+        let rest = &rest[quote.len_utf8()..];
+        return Some(&rest[..rest.find(quote)?]);
+    }
+    let open = format!("<GPano:{name}>");
+    let i = xmp.find(&open)? + open.len();
+    let rest = &xmp[i..];
+    Some(&rest[..rest.find('<')?])
+}
+
+impl GPano {
+    pub fn full(size: (u32, u32)) -> Self {
+        Self {
+            full_size: size,
+            cropped_size: size,
+            cropped_origin: (0, 0),
+        }
+    }
+
+    /// Reads the tags of an equirectangular panorama.
+    pub fn parse(xmp: &str) -> Option<Self> {
+        let projection = property(xmp, "ProjectionType")?;
+        if !projection.trim().eq_ignore_ascii_case("equirectangular") {
+            return None;
+        }
+        let get = |name: &str| property(xmp, name)?.trim().parse::<u32>().ok();
+        let full_size = (get("FullPanoWidthPixels")?, get("FullPanoHeightPixels")?);
+        let cropped_size = (
+            get("CroppedAreaImageWidthPixels").unwrap_or(full_size.0),
+            get("CroppedAreaImageHeightPixels").unwrap_or(full_size.1),
+        );
+        let cropped_origin = (
+            get("CroppedAreaLeftPixels").unwrap_or(0),
+            get("CroppedAreaTopPixels").unwrap_or(0),
+        );
+        (full_size.0 > 0 && full_size.1 > 0 && cropped_size.0 > 0 && cropped_size.1 > 0).then_some(
+            Self {
+                full_size,
+                cropped_size,
+                cropped_origin,
+            },
+        )
+    }
+
+    /// Whether the image covers the whole sphere.
+    pub fn is_full(&self) -> bool {
+        self.cropped_size == self.full_size
+    }
+
+    /// Places a partial panorama `img` where it belongs in a transparent full
+    /// panorama, scaled to the resolution of `img`.
+    pub fn expand(&self, img: &Rgba32FImage) -> Rgba32FImage {
+        let scale = img.width() as f64 / self.cropped_size.0 as f64;
+        let px = |v: u32| (v as f64 * scale).round() as i64;
+        let mut full = Rgba32FImage::new(
+            px(self.full_size.0).max(1) as u32,
+            px(self.full_size.1).max(1) as u32,
+        );
+        image::imageops::replace(
+            &mut full,
+            img,
+            px(self.cropped_origin.0),
+            px(self.cropped_origin.1),
+        );
+        full
+    }
+
+    pub fn to_xmp(self) -> String {
+        let (w, h) = self.full_size;
+        let (cw, ch) = self.cropped_size;
+        let (left, top) = self.cropped_origin;
+        format!(
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\
+             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\
+             <rdf:Description rdf:about=\"\" \
+             xmlns:GPano=\"http://ns.google.com/photos/1.0/panorama/\" \
+             GPano:ProjectionType=\"equirectangular\" GPano:UsePanoramaViewer=\"True\" \
+             GPano:FullPanoWidthPixels=\"{w}\" GPano:FullPanoHeightPixels=\"{h}\" \
+             GPano:CroppedAreaImageWidthPixels=\"{cw}\" \
+             GPano:CroppedAreaImageHeightPixels=\"{ch}\" \
+             GPano:CroppedAreaLeftPixels=\"{left}\" GPano:CroppedAreaTopPixels=\"{top}\"/>\
+             </rdf:RDF></x:xmpmeta>"
+        )
+    }
+}
@@ -0,0 +1,178 @@
+//! Halved copies of large sources, so that small outputs sample an image of
+//! about their own resolution instead of skipping through a huge one.
+
+use std::sync::{Arc, OnceLock};
+
+use image::Rgba32FImage;
+use rayon::prelude::*;
+
+use crate::{icc, render::RenderRequest};
+
+/// Sources no wider than this are always sampled as they are.
+const MIN_WIDTH: u32 = 1024;
+
+/// Source pixels wanted across the width of the source per pixel of the
+/// longer output side. The horizon of a little planet as wide as the output
+/// is about π times as long, and gets the whole width of the panorama.
+const AUTO_RATIO: f32 = 4.0;
+
+/// Which copy of the source is sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Downscale {
+    Full,
+    /// The smallest copy still as sharp as the output.
+    Auto,
+    Half,
+    Quarter,
+    Eighth,
+}
+
+impl Downscale {
+    pub const ALL: [Downscale; 5] = [
+        Downscale::Full,
+        Downscale::Auto,
+        Downscale::Half,
+        Downscale::Quarter,
+        Downscale::Eighth,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Downscale::Full => "Full",
+            Downscale::Auto => "Auto",
+            Downscale::Half => "1/2",
+            Downscale::Quarter => "1/4",
+            Downscale::Eighth => "1/8",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Downscale::ALL.into_iter().find(|d| d.name() == name)
+    }
+}
+
+/// The source and its copies, each half the size of the one before. The
+/// copies are only made once they are asked for.
+pub struct Mipmap {
+    levels: Vec<OnceLock<Arc<Rgba32FImage>>>,
+    /// The levels hold linear light rather than sRGB.
+    linear: bool,
+}
+
+impl Mipmap {
+    pub fn new(image: Arc<Rgba32FImage>, linear: bool) -> Self {
+        let mut count = 1;
+        while image.width() >> count >= MIN_WIDTH {
+            count += 1;
+        }
+        let levels: Vec<_> = (0..count).map(|_| OnceLock::new()).collect();
+        levels[0].set(image).ok();
+        Self { levels, linear }
+    }
+
+    pub fn source(&self) -> &Arc<Rgba32FImage> {
+        self.level(0)
+    }
+
+    fn level(&self, i: usize) -> &Arc<Rgba32FImage> {
+        if i == 0 {
+            return self.levels[0].get().unwrap();
+        }
+        self.levels[i].get_or_init(|| Arc::new(halve(self.level(i - 1), self.linear)))
+    }
+
+    /// The copy to sample for an output whose longer side is `longer`
+    /// pixels, with `samples` samples per pixel.
+    pub fn get(&self, downscale: Downscale, longer: u32, samples: u32) -> &Arc<Rgba32FImage> {
+        let last = self.levels.len() - 1;
+        let i = match downscale {
+            Downscale::Full => 0,
+            Downscale::Auto => {
+                let wanted = longer as f32 * (samples as f32).sqrt() * AUTO_RATIO;
+                let width = self.source().width() as f32;
+                (width / wanted).log2().floor().max(0.0) as usize
+            }
+            Downscale::Half => 1,
+            Downscale::Quarter => 2,
+            Downscale::Eighth => 3,
+        };
+        self.level(i.min(last))
+    }
+}
+
+/// Averages each 2×2 block of `img` in linear light, weighting colors by
+/// their alpha. An odd last row or column is folded into the one before.
+/// `linear` images are averaged as they are, without going through sRGB.
+fn halve(img: &Rgba32FImage, linear: bool) -> Rgba32FImage {
+    let decode = |c: f32| if linear { c } else { icc::to_linear(c) };
+    let encode = |c: f32| if linear { c } else { icc::linear_to_srgb(c) };
+    let (width, height) = img.dimensions();
+    let (w, h) = ((width / 2).max(1), (height / 2).max(1));
+    let mut out = Rgba32FImage::new(w, h);
+    out.par_chunks_mut(w as usize * 4)
+        .enumerate()
+        .for_each(|(y, line)| {
+            let y = y as u32;
+            let rows = if y + 1 == h {
+                2 * y..height
+            } else {
+                2 * y..2 * y + 2
+            };
+            for (x, pixel) in line.chunks_exact_mut(4).enumerate() {
+                let x = x as u32;
+                let cols = if x + 1 == w {
+                    2 * x..width
+                } else {
+                    2 * x..2 * x + 2
+                };
+                let mut sum = [0.0; 4];
+                let mut count = 0.0;
+                for sy in rows.clone() {
+                    for sx in cols.clone() {
+                        let [r, g, b, a] = img.get_pixel(sx, sy).0;
+                        sum[0] += decode(r) * a;
+                        sum[1] += decode(g) * a;
+                        sum[2] += decode(b) * a;
+                        sum[3] += a;
+                        count += 1.0;
+                    }
+                }
+                let alpha = sum[3];
+                if alpha > 0.0 {
+                    for (p, c) in pixel.iter_mut().zip(&sum[..3]) {
+                        *p = encode(c / alpha);
+                    }
+                }
+                pixel[3] = alpha / count;
+            }
+        });
+    out
+}
+
+/// The mipmap of the last source, made again when the source changes.
+#[derive(Clone, Default)]
+pub struct MipmapCache {
+    last: Option<Arc<Mipmap>>,
+}
+
+impl MipmapCache {
+    pub fn get(&mut self, image: &Arc<Rgba32FImage>, linear: bool) -> Arc<Mipmap> {
+        match &self.last {
+            Some(mipmap) if Arc::ptr_eq(mipmap.source(), image) && mipmap.linear == linear => {
+                Arc::clone(mipmap)
+            }
+            _ => {
+                let mipmap = Arc::new(Mipmap::new(Arc::clone(image), linear));
+                self.last = Some(Arc::clone(&mipmap));
+                mipmap
+            }
+        }
+    }
+
+    /// Makes `request` sample the copy of its source picked by `downscale`.
+    pub fn apply(&mut self, request: &mut RenderRequest, downscale: Downscale) {
+        request.downscale = downscale;
+        request.mipmap =
+            (downscale != Downscale::Full).then(|| self.get(&request.image, request.linear));
+    }
+}
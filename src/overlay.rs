@@ -0,0 +1,262 @@
+//! A caption or logo stamped over the output, laid out relative to the
+//! output size so that exports at any resolution match the preview.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use ab_glyph::{point, Font, FontArc, ScaleFont};
+use image::{imageops, Rgba, Rgba32FImage};
+
+use crate::{
+    fonts,
+    preset::f32s,
+    project,
+    toml::{Table, Value},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StampKind {
+    Text,
+    Logo,
+}
+
+impl StampKind {
+    pub const ALL: [StampKind; 2] = [StampKind::Text, StampKind::Logo];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StampKind::Text => "Text",
+            StampKind::Logo => "Logo",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        StampKind::ALL.into_iter().find(|k| k.name() == name)
+    }
+}
+
+#[derive(Clone)]
+pub struct Overlay {
+    pub enabled: bool,
+    pub kind: StampKind,
+    pub text: String,
+    pub font: FontArc,
+    /// Where `font` was loaded from, or `None` for the default font.
+    pub font_path: Option<PathBuf>,
+    /// Straight alpha.
+    pub color: [f32; 4],
+    pub logo: Option<Arc<Rgba32FImage>>,
+    /// Center of the stamp, normalized to the output size.
+    pub position: (f32, f32),
+    /// Height of a line of text or of the logo, as a fraction of the output
+    /// height.
+    pub size: f32,
+    pub opacity: f32,
+}
+
+impl Default for Overlay {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            kind: StampKind::Text,
+            text: "说的道理".to_owned(),
+            font: default_font(),
+            font_path: None,
+            color: [1.0, 1.0, 1.0, 1.0],
+            logo: None,
+            position: (0.5, 0.85),
+            size: 0.1,
+            opacity: 1.0,
+        }
+    }
+}
+
+/// The CJK font of the system for the Chinese default caption, or else the
+/// proportional font egui draws its own text with.
+fn default_font() -> FontArc {
+    if let Some(font) = fonts::cjk().and_then(|font| FontArc::try_from_slice(font).ok()) {
+        return font;
+    }
+    let mut fonts = egui::FontDefinitions::default();
+    let data = fonts.font_data.remove("Ubuntu-Light").unwrap();
+    FontArc::try_from_vec(data.font.into_owned()).unwrap()
+}
+
+pub fn load_font(path: &Path) -> io::Result<FontArc> {
+    FontArc::try_from_vec(fs::read(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Composites a straight-alpha color over another.
+pub fn over(below: [f32; 4], above: [f32; 4]) -> [f32; 4] {
+    let (a, c) = (below[3], above[3]);
+    let alpha = c + a * (1.0 - c);
+    if alpha <= 0.0 {
+        return below;
+    }
+    let [r, g, b] = [0, 1, 2].map(|i| (above[i] * c + below[i] * a * (1.0 - c)) / alpha);
+    [r, g, b, alpha]
+}
+
+impl Overlay {
+    /// Writes the overlay into `table`. The logo is embedded, and the font is
+    /// referred to by its path.
+    pub fn write(&self, table: &mut Table, prefix: &str) -> io::Result<()> {
+        table.insert(format!("{prefix}enabled"), self.enabled);
+        table.insert(format!("{prefix}kind"), self.kind.name());
+        table.insert(format!("{prefix}text"), self.text.as_str());
+        if let Some(path) = self.font_path.as_ref().and_then(|path| path.to_str()) {
+            table.insert(format!("{prefix}font"), path);
+        }
+        table.insert(format!("{prefix}color"), self.color);
+        if let Some(logo) = &self.logo {
+            project::write_image(table, &format!("{prefix}logo"), logo)?;
+        }
+        table.insert(
+            format!("{prefix}position"),
+            [self.position.0, self.position.1],
+        );
+        table.insert(format!("{prefix}size"), self.size);
+        table.insert(format!("{prefix}opacity"), self.opacity);
+        Ok(())
+    }
+
+    /// Reads the overlay written by [`Overlay::write`], keeping the defaults
+    /// for anything that is missing or malformed, and the default font when
+    /// the saved one can no longer be loaded.
+    pub fn read(table: &Table, prefix: &str) -> Self {
+        let get = |key: &str| table.get(&format!("{prefix}{key}"));
+        let mut overlay = Overlay::default();
+        if let Some(enabled) = get("enabled").and_then(Value::as_bool) {
+            overlay.enabled = enabled;
+        }
+        if let Some(kind) = get("kind").and_then(Value::as_str) {
+            overlay.kind = StampKind::from_name(kind).unwrap_or(overlay.kind);
+        }
+        if let Some(text) = get("text").and_then(Value::as_str) {
+            overlay.text = text.to_owned();
+        }
+        if let Some(path) = get("font").and_then(Value::as_str).map(PathBuf::from) {
+            if let Ok(font) = load_font(&path) {
+                overlay.font = font;
+                overlay.font_path = Some(path);
+            }
+        }
+        if let Some(color) = f32s(get("color")) {
+            overlay.color = color;
+        }
+        overlay.logo = project::read_image(table, &format!("{prefix}logo"));
+        if let Some([x, y]) = f32s(get("position")) {
+            overlay.position = (x, y);
+        }
+        if let Some(size) = get("size").and_then(Value::as_f32) {
+            overlay.size = size;
+        }
+        if let Some(opacity) = get("opacity").and_then(Value::as_f32) {
+            overlay.opacity = opacity;
+        }
+        overlay
+    }
+
+    /// The stamp rasterized for an output of `size`, in straight alpha.
+    fn layer(&self, size: (u32, u32)) -> Option<Rgba32FImage> {
+        let height = self.size * size.1 as f32;
+        if height < 1.0 {
+            return None;
+        }
+        match self.kind {
+            StampKind::Text => self.text_layer(height),
+            StampKind::Logo => {
+                let logo = self.logo.as_ref()?;
+                let width = height * logo.width() as f32 / logo.height() as f32;
+                let (width, height) = (width.round() as u32, height.round() as u32);
+                (width > 0).then(|| {
+                    imageops::resize(&**logo, width, height, imageops::FilterType::Triangle)
+                })
+            }
+        }
+    }
+
+    fn text_layer(&self, line_height: f32) -> Option<Rgba32FImage> {
+        let font = self.font.as_scaled(line_height);
+        let lines: Vec<&str> = self.text.lines().collect();
+        let advance = |line: &str| -> f32 {
+            let mut width = 0.0;
+            let mut last = None;
+            for c in line.chars() {
+                let id = font.glyph_id(c);
+                if let Some(last) = last {
+                    width += font.kern(last, id);
+                }
+                width += font.h_advance(id);
+                last = Some(id);
+            }
+            width
+        };
+        let width = lines.iter().map(|line| advance(line)).fold(0.0, f32::max);
+        let height = font.height() * lines.len() as f32;
+        if width < 1.0 {
+            return None;
+        }
+        let [r, g, b, a] = self.color;
+        let mut layer = Rgba32FImage::from_pixel(
+            width.ceil() as u32,
+            height.ceil() as u32,
+            Rgba([r, g, b, 0.0]),
+        );
+        for (i, line) in lines.iter().enumerate() {
+            // Lines are centered on each other.
+            let mut caret = point(
+                (width - advance(line)) / 2.0,
+                font.ascent() + font.height() * i as f32,
+            );
+            let mut last = None;
+            for c in line.chars() {
+                let id = font.glyph_id(c);
+                if let Some(last) = last {
+                    caret.x += font.kern(last, id);
+                }
+                if let Some(outline) =
+                    font.outline_glyph(id.with_scale_and_position(font.scale, caret))
+                {
+                    let bounds = outline.px_bounds();
+                    outline.draw(|x, y, coverage| {
+                        let x = bounds.min.x as i64 + x as i64;
+                        let y = bounds.min.y as i64 + y as i64;
+                        if x >= 0 && y >= 0 && x < layer.width() as i64 && y < layer.height() as i64
+                        {
+                            let pixel = layer.get_pixel_mut(x as u32, y as u32);
+                            pixel.0[3] = (pixel.0[3] + coverage * a).min(1.0);
+                        }
+                    });
+                }
+                caret.x += font.h_advance(id);
+                last = Some(id);
+            }
+        }
+        Some(layer)
+    }
+
+    /// Stamps the overlay onto `out`, which holds the rows from `first_row`
+    /// of an output of `size`.
+    pub fn composite(&self, out: &mut Rgba32FImage, size: (u32, u32), first_row: u32) {
+        let Some(layer) = self.layer(size) else {
+            return;
+        };
+        let left = (self.position.0 * size.0 as f32 - layer.width() as f32 / 2.0).round() as i64;
+        let top = (self.position.1 * size.1 as f32 - layer.height() as f32 / 2.0).round() as i64;
+        for (x, y, pixel) in layer.enumerate_pixels() {
+            let (ox, oy) = (left + x as i64, top + y as i64 - first_row as i64);
+            if ox < 0 || oy < 0 || ox >= out.width() as i64 || oy >= out.height() as i64 {
+                continue;
+            }
+            let mut above = pixel.0;
+            above[3] *= self.opacity;
+            let below = out.get_pixel_mut(ox as u32, oy as u32);
+            below.0 = over(below.0, above);
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! The composition being edited, which notices by itself which of its
+//! parameters changed and holds renders back until the edits settle.
+
+#![allow(clippy::suspicious_op_assign_impl)]
+
+use std::{
+    ops::{AddAssign, BitOr, BitOrAssign, Deref, DerefMut},
+    time::{Duration, Instant},
+};
+
+use egui::Response;
+
+use crate::preset::Params;
+
+/// How long after the last change a render is started.
+pub const DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// Groups of parameters that changed, as a set of bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Changes(u8);
+
+impl Changes {
+    pub const NONE: Changes = Changes(0);
+    /// The projection and how the source is read: kind, direction, tunnel,
+    /// edges and source field of view.
+    pub const PROJECTION: Changes = Changes(1);
+    /// Where the view looks: offset, rotation and scale.
+    pub const VIEW: Changes = Changes(1 << 1);
+    pub const SIZE: Changes = Changes(1 << 2);
+    /// Color adjustments, vignette, graticule and pole retouching.
+    pub const LOOK: Changes = Changes(1 << 3);
+    /// Anything else the render depends on, like the source image or the
+    /// sampler, which the caller reports.
+    pub const OTHER: Changes = Changes(1 << 4);
+
+    /// The groups in which `a` and `b` differ.
+    pub fn between(a: &Params, b: &Params) -> Self {
+        let mut changes = Changes::NONE;
+        let mut check = |changed: bool, group: Changes| {
+            if changed {
+                changes |= group;
+            }
+        };
+        check(
+            a.kind != b.kind
+                || a.inverse != b.inverse
+                || a.tunnel != b.tunnel
+                || a.edge != b.edge
+                || a.fov != b.fov,
+            Changes::PROJECTION,
+        );
+        check(
+            a.offset != b.offset || a.rotation != b.rotation || a.scale != b.scale,
+            Changes::VIEW,
+        );
+        check(a.size != b.size, Changes::SIZE);
+        check(
+            a.color != b.color
+                || a.vignette != b.vignette
+                || a.graticule != b.graticule
+                || a.pole != b.pole,
+            Changes::LOOK,
+        );
+        changes
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, other: Changes) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether any of the parameters changed, rather than only state around
+    /// them.
+    pub fn in_params(self) -> bool {
+        self.0 & !Changes::OTHER.0 != 0
+    }
+}
+
+impl BitOr for Changes {
+    type Output = Changes;
+
+    fn bitor(self, rhs: Changes) -> Changes {
+        Changes(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Changes {
+    fn bitor_assign(&mut self, rhs: Changes) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// The parameters of the composition, used like a [`Params`] through
+/// `Deref`. Edits are found by comparing with the parameters of the last
+/// frame, so widgets editing them need not report anything; changes to other
+/// state are added with `+=`.
+pub struct AppParams {
+    params: Params,
+    /// The parameters as of the last [`AppParams::update`].
+    seen: Params,
+    other: bool,
+    /// Skips the debounce for the next render.
+    immediate: bool,
+    /// Changes not rendered yet, and when the last of them was made.
+    pending: Changes,
+    last_change: Option<Instant>,
+}
+
+impl AppParams {
+    pub fn new(params: Params) -> Self {
+        Self {
+            params,
+            seen: params,
+            other: false,
+            immediate: false,
+            pending: Changes::NONE,
+            last_change: None,
+        }
+    }
+
+    /// Finds what changed since the last call, once a frame after all
+    /// widgets are shown, returning the changes of this frame.
+    pub fn update(&mut self, now: Instant) -> Changes {
+        let mut changes = Changes::between(&self.seen, &self.params);
+        if std::mem::take(&mut self.other) {
+            changes |= Changes::OTHER;
+        }
+        self.seen = self.params;
+        if !changes.is_empty() {
+            self.pending |= changes;
+            self.last_change = Some(now);
+        }
+        changes
+    }
+
+    /// Renders the next changes without waiting, for those made by dragging
+    /// the preview or streamed in like live frames, which would otherwise be
+    /// held back for as long as they keep coming.
+    pub fn refresh(&mut self) {
+        self.other = true;
+        self.immediate = true;
+    }
+
+    /// The changes to render, once [`DEBOUNCE`] has passed since the last
+    /// one.
+    pub fn due(&mut self, now: Instant) -> Option<Changes> {
+        let immediate = std::mem::take(&mut self.immediate);
+        let settled = self.wait(now).is_some_and(|wait| wait.is_zero());
+        (immediate || settled).then(|| {
+            self.last_change = None;
+            std::mem::take(&mut self.pending)
+        })
+    }
+
+    /// Whether there are changes, of this frame or held back by the
+    /// debounce, that haven't been submitted for rendering yet.
+    pub fn has_pending(&self) -> bool {
+        self.params != self.seen || self.other || self.immediate || !self.pending.is_empty()
+    }
+
+    /// How long until the pending changes are due.
+    pub fn wait(&self, now: Instant) -> Option<Duration> {
+        let elapsed = now - self.last_change?;
+        Some(DEBOUNCE.saturating_sub(elapsed))
+    }
+}
+
+impl Deref for AppParams {
+    type Target = Params;
+
+    fn deref(&self) -> &Params {
+        &self.params
+    }
+}
+
+impl DerefMut for AppParams {
+    fn deref_mut(&mut self) -> &mut Params {
+        &mut self.params
+    }
+}
+
+impl AddAssign<bool> for AppParams {
+    fn add_assign(&mut self, rhs: bool) {
+        self.other |= rhs;
+    }
+}
+
+impl AddAssign<Response> for AppParams {
+    fn add_assign(&mut self, rhs: Response) {
+        self.other |= rhs.changed();
+    }
+}
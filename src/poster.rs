@@ -0,0 +1,343 @@
+//! Posters for ordinary printers: the output split into pages that overlap a
+//! little, to be trimmed and taped together, saved as numbered images or as
+//! one PDF.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+};
+
+use image::{buffer::ConvertBuffer, codecs::jpeg::JpegEncoder, Rgb, RgbImage, Rgba32FImage};
+
+use crate::{
+    error::AppError,
+    export::{self, ExportOptions, TILE_ROWS},
+    render::{CancelToken, RenderRequest},
+    toml::{Table, Value},
+};
+
+const MM_PER_INCH: f32 = 25.4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Paper {
+    A4,
+    A3,
+    Letter,
+    Tabloid,
+}
+
+impl Paper {
+    pub const ALL: [Paper; 4] = [Paper::A4, Paper::A3, Paper::Letter, Paper::Tabloid];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Paper::A4 => "A4",
+            Paper::A3 => "A3",
+            Paper::Letter => "Letter",
+            Paper::Tabloid => "Tabloid",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Paper::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    /// Width and height in millimeters, upright.
+    pub fn size(self) -> (f32, f32) {
+        match self {
+            Paper::A4 => (210.0, 297.0),
+            Paper::A3 => (297.0, 420.0),
+            Paper::Letter => (215.9, 279.4),
+            Paper::Tabloid => (279.4, 431.8),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PosterOptions {
+    pub paper: Paper,
+    pub landscape: bool,
+    /// Output pixels per inch of paper.
+    pub dpi: u32,
+    /// How far pages reach over their neighbors, in millimeters.
+    pub overlap: f32,
+    /// Saves one PDF instead of an image for each page.
+    pub pdf: bool,
+}
+
+impl Default for PosterOptions {
+    fn default() -> Self {
+        Self {
+            paper: Paper::A4,
+            landscape: false,
+            dpi: 150,
+            overlap: 10.0,
+            pdf: true,
+        }
+    }
+}
+
+impl PosterOptions {
+    pub fn write(&self, table: &mut Table, prefix: &str) {
+        table.insert(format!("{prefix}paper"), self.paper.name());
+        table.insert(format!("{prefix}landscape"), self.landscape);
+        table.insert(format!("{prefix}dpi"), self.dpi);
+        table.insert(format!("{prefix}overlap"), self.overlap);
+        table.insert(format!("{prefix}pdf"), self.pdf);
+    }
+
+    /// Reads the options written by [`PosterOptions::write`], keeping the
+    /// defaults for anything that is missing or malformed.
+    pub fn read(table: &Table, prefix: &str) -> Self {
+        let get = |key: &str| table.get(&format!("{prefix}{key}"));
+        let mut options = PosterOptions::default();
+        if let Some(paper) = get("paper").and_then(Value::as_str) {
+            options.paper = Paper::from_name(paper).unwrap_or(options.paper);
+        }
+        if let Some(landscape) = get("landscape").and_then(Value::as_bool) {
+            options.landscape = landscape;
+        }
+        if let Some(dpi) = get("dpi").and_then(Value::as_u32) {
+            options.dpi = dpi.clamp(36, 1200);
+        }
+        if let Some(overlap) = get("overlap").and_then(Value::as_f32) {
+            options.overlap = overlap.clamp(0.0, 50.0);
+        }
+        if let Some(pdf) = get("pdf").and_then(Value::as_bool) {
+            options.pdf = pdf;
+        }
+        options
+    }
+
+    /// Width and height of the paper in millimeters, turned as it is printed.
+    pub fn paper_size(&self) -> (f32, f32) {
+        let (w, h) = self.paper.size();
+        if self.landscape {
+            (h, w)
+        } else {
+            (w, h)
+        }
+    }
+
+    /// Output pixels that fit on a page.
+    pub fn page_size(&self) -> (u32, u32) {
+        let (w, h) = self.paper_size();
+        let px = |mm: f32| (mm / MM_PER_INCH * self.dpi as f32).round().max(1.0) as u32;
+        (px(w), px(h))
+    }
+
+    /// The overlap in pixels, at most half a page so that every page still
+    /// moves on.
+    fn overlap_px(&self) -> (u32, u32) {
+        let (w, h) = self.page_size();
+        let overlap = (self.overlap / MM_PER_INCH * self.dpi as f32).round() as u32;
+        (overlap.min(w / 2), overlap.min(h / 2))
+    }
+
+    /// How far each page starts from the one before, in pixels.
+    fn step(&self) -> (u32, u32) {
+        let (w, h) = self.page_size();
+        let (ox, oy) = self.overlap_px();
+        (w - ox, h - oy)
+    }
+
+    /// Pages across and down for an output of `size`.
+    pub fn grid(&self, size: (u32, u32)) -> (u32, u32) {
+        let (w, h) = self.page_size();
+        let (sx, sy) = self.step();
+        let count = |n: u32, page: u32, step: u32| n.saturating_sub(page).div_ceil(step) + 1;
+        (count(size.0, w, sx), count(size.1, h, sy))
+    }
+
+    /// Width and height of the printed output in centimeters.
+    pub fn printed_size(&self, size: (u32, u32)) -> (f32, f32) {
+        let cm = |px: u32| px as f32 / self.dpi as f32 * MM_PER_INCH / 10.0;
+        (cm(size.0), cm(size.1))
+    }
+}
+
+/// Where the page at `row` and `col`, counted from one, is saved when the
+/// poster goes to `path`.
+fn page_path(path: &Path, row: u32, col: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}-{row}-{col}.{}", ext.to_string_lossy()),
+        None => format!("{stem}-{row}-{col}"),
+    };
+    path.with_file_name(name)
+}
+
+/// Renders `request` and saves it as a poster, sending the number of pages
+/// done to `progress`. A row of pages is rendered at a time, in bands, so
+/// that the whole output is never held in memory. Numbered pages are saved
+/// in the format of `export`; the pages of a PDF are JPEGs of its quality.
+pub fn save(
+    request: &RenderRequest,
+    path: &Path,
+    options: &PosterOptions,
+    export: &ExportOptions,
+    cancel: &CancelToken,
+    progress: &Sender<u32>,
+) -> Result<(), AppError> {
+    let size = request.size;
+    let (width, height) = options.page_size();
+    let (step_x, step_y) = options.step();
+    let (cols, rows) = options.grid(size);
+    let mut pdf = if options.pdf {
+        Some(Pdf::create(path)?)
+    } else {
+        None
+    };
+    let result = (|| {
+        for row in 0..rows {
+            let top = row * step_y;
+            let bottom = (top + height).min(size.1);
+            // Paper is white where the output is transparent or ends.
+            let mut pages = vec![RgbImage::from_pixel(width, height, Rgb([255; 3])); cols as usize];
+            for start in (top..bottom).step_by(TILE_ROWS as usize) {
+                let end = (start + TILE_ROWS).min(bottom);
+                let band = request.render_rows(size, start..end, cancel, None)?;
+                for (col, page) in pages.iter_mut().enumerate() {
+                    let left = col as u32 * step_x;
+                    let right = (left + width).min(size.0);
+                    for y in 0..band.height() {
+                        for x in left..right {
+                            let [r, g, b, a] = band.get_pixel(x, y).0;
+                            let a = a.clamp(0.0, 1.0);
+                            let over = |c: f32| (c * a + 1.0 - a).clamp(0.0, 1.0) * 255.0;
+                            let pixel = [r, g, b].map(|c| over(c).round() as u8);
+                            page.put_pixel(x - left, y + start - top, Rgb(pixel));
+                        }
+                    }
+                }
+            }
+            for (col, page) in pages.iter().enumerate() {
+                match &mut pdf {
+                    Some(pdf) => pdf.add_page(page, options.paper_size(), export.quality)?,
+                    None => {
+                        let page: Rgba32FImage = page.convert();
+                        let path = page_path(path, row + 1, col as u32 + 1);
+                        export::save(&page, &path, export)?;
+                    }
+                }
+                progress.send(row * cols + col as u32 + 1).ok();
+            }
+        }
+        if let Some(pdf) = pdf.take() {
+            pdf.finish()?;
+        }
+        Ok(())
+    })();
+    if result.is_err() && options.pdf {
+        fs::remove_file(path).ok();
+    }
+    result
+}
+
+/// PDF points per millimeter.
+const PT_PER_MM: f32 = 72.0 / MM_PER_INCH;
+
+/// A PDF written a page at a time, each page one JPEG filling it.
+struct Pdf {
+    file: BufWriter<File>,
+    /// Bytes written so far.
+    len: usize,
+    /// Where each object starts, by number from one.
+    offsets: Vec<usize>,
+    pages: Vec<usize>,
+}
+
+/// The objects written last, whose numbers are kept from the start.
+const CATALOG: usize = 1;
+const PAGES: usize = 2;
+
+impl Pdf {
+    fn create(path: &Path) -> io::Result<Self> {
+        let mut pdf = Pdf {
+            file: BufWriter::new(File::create(path)?),
+            len: 0,
+            offsets: vec![0, 0],
+            pages: vec![],
+        };
+        // The comment of binary bytes tells tools the file is not text.
+        pdf.write(b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n")?;
+        Ok(pdf)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.file.write_all(bytes)?;
+        self.len += bytes.len();
+        Ok(())
+    }
+
+    /// Starts the object numbered `n`, or the next one for `None`, returning
+    /// its number.
+    fn object(&mut self, n: Option<usize>, body: &[u8]) -> io::Result<usize> {
+        let n = n.unwrap_or_else(|| {
+            self.offsets.push(0);
+            self.offsets.len()
+        });
+        self.offsets[n - 1] = self.len;
+        self.write(format!("{n} 0 obj\n").as_bytes())?;
+        self.write(body)?;
+        self.write(b"\nendobj\n")?;
+        Ok(n)
+    }
+
+    fn stream(&mut self, dict: &str, data: &[u8]) -> io::Result<usize> {
+        let head = format!("<< {dict} /Length {} >>\nstream\n", data.len());
+        let body = [head.as_bytes(), data, b"\nendstream"].concat();
+        self.object(None, &body)
+    }
+
+    fn add_page(&mut self, page: &RgbImage, paper: (f32, f32), quality: u8) -> io::Result<()> {
+        let mut jpeg = vec![];
+        JpegEncoder::new_with_quality(&mut jpeg, quality)
+            .encode_image(page)
+            .map_err(io::Error::other)?;
+        let (w, h) = page.dimensions();
+        let image = self.stream(
+            &format!(
+                "/Type /XObject /Subtype /Image /Width {w} /Height {h} \
+                 /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /DCTDecode"
+            ),
+            &jpeg,
+        )?;
+        let (pw, ph) = (paper.0 * PT_PER_MM, paper.1 * PT_PER_MM);
+        let content = format!("q {pw:.2} 0 0 {ph:.2} 0 0 cm /Im0 Do Q");
+        let content = self.stream("", content.as_bytes())?;
+        let page = format!(
+            "<< /Type /Page /Parent {PAGES} 0 R /MediaBox [0 0 {pw:.2} {ph:.2}] \
+             /Resources << /XObject << /Im0 {image} 0 R >> >> /Contents {content} 0 R >>"
+        );
+        let page = self.object(None, page.as_bytes())?;
+        self.pages.push(page);
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        let kids: Vec<_> = self.pages.iter().map(|n| format!("{n} 0 R")).collect();
+        let pages = format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            kids.len()
+        );
+        self.object(Some(PAGES), pages.as_bytes())?;
+        let catalog = format!("<< /Type /Catalog /Pages {PAGES} 0 R >>");
+        self.object(Some(CATALOG), catalog.as_bytes())?;
+
+        let xref = self.len;
+        let count = self.offsets.len() + 1;
+        let mut table = format!("xref\n0 {count}\n0000000000 65535 f \n");
+        for offset in &self.offsets {
+            table.push_str(&format!("{offset:010} 00000 n \n"));
+        }
+        table.push_str(&format!(
+            "trailer\n<< /Size {count} /Root {CATALOG} 0 R >>\nstartxref\n{xref}\n%%EOF\n"
+        ));
+        self.write(table.as_bytes())?;
+        self.file.flush()
+    }
+}
@@ -0,0 +1,314 @@
+use std::{fs, io, path::PathBuf};
+
+use nalgebra::{Rotation3, Vector3};
+
+use crate::{
+    color::ColorAdjust,
+    config,
+    effect::{Graticule, PoleCap, Vignette},
+    i18n::tr,
+    projection::ProjectionKind,
+    sampler::EdgeMode,
+    toml::{Table, Value},
+};
+
+/// The parameters that describe a composition, independently of the source
+/// image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Params {
+    pub kind: ProjectionKind,
+    pub inverse: bool,
+    pub offset: (f32, f32),
+    /// Euler angles in degrees.
+    pub rotation: (f32, f32, f32),
+    pub scale: f32,
+    /// Puts the opposite pole at the center, turning a little planet into a
+    /// tunnel and back.
+    pub tunnel: bool,
+    pub size: (u32, u32),
+    /// How areas off the source image are filled.
+    pub edge: EdgeMode,
+    /// Horizontal and vertical field of view of the source in degrees,
+    /// around the center of the panorama.
+    pub fov: (f32, f32),
+    pub color: ColorAdjust,
+    pub vignette: Vignette,
+    pub graticule: Graticule,
+    pub pole: PoleCap,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            kind: ProjectionKind::Stereographic,
+            inverse: false,
+            offset: (0.0, 0.4),
+            rotation: (0.0, 0.09f32.to_degrees(), 0.0),
+            scale: 1.5,
+            tunnel: false,
+            size: (600, 600),
+            edge: EdgeMode::Wrap,
+            fov: (360.0, 180.0),
+            color: ColorAdjust::default(),
+            vignette: Vignette::default(),
+            graticule: Graticule::default(),
+            pole: PoleCap::default(),
+        }
+    }
+}
+
+/// Builds a rotation from Euler angles in degrees.
+pub fn rotation_from_degrees(angles: (f32, f32, f32)) -> Rotation3<f32> {
+    Rotation3::from_euler_angles(
+        angles.0.to_radians(),
+        angles.1.to_radians(),
+        angles.2.to_radians(),
+    )
+}
+
+pub fn rotation_to_degrees(rotation: Rotation3<f32>) -> (f32, f32, f32) {
+    let (x, y, z) = rotation.euler_angles();
+    (x.to_degrees(), y.to_degrees(), z.to_degrees())
+}
+
+/// Half turn swapping the poles of the view, which is its own inverse.
+fn pole_flip() -> Rotation3<f32> {
+    Rotation3::from_axis_angle(&Vector3::x_axis(), std::f32::consts::PI)
+}
+
+pub fn f32s<const N: usize>(value: Option<&Value>) -> Option<[f32; N]> {
+    let values = value?.as_array()?;
+    let mut out = [0.0; N];
+    if values.len() != N {
+        return None;
+    }
+    for (o, v) in out.iter_mut().zip(values) {
+        *o = v.as_f32()?;
+    }
+    Some(out)
+}
+
+fn u32s<const N: usize>(value: Option<&Value>) -> Option<[u32; N]> {
+    let values = value?.as_array()?;
+    let mut out = [0; N];
+    if values.len() != N {
+        return None;
+    }
+    for (o, v) in out.iter_mut().zip(values) {
+        *o = v.as_u32()?;
+    }
+    Some(out)
+}
+
+impl Params {
+    /// The rotation used by the projections, which includes the tunnel flip.
+    pub fn view_rotation(&self) -> Rotation3<f32> {
+        let rotation = rotation_from_degrees(self.rotation);
+        if self.tunnel {
+            rotation * pole_flip()
+        } else {
+            rotation
+        }
+    }
+
+    pub fn set_view_rotation(&mut self, rotation: Rotation3<f32>) {
+        let rotation = if self.tunnel {
+            rotation * pole_flip()
+        } else {
+            rotation
+        };
+        self.rotation = rotation_to_degrees(rotation);
+    }
+
+    /// Writes the parameters into `table`, with keys prefixed by `prefix`.
+    pub fn write(&self, table: &mut Table, prefix: &str) {
+        table.insert(format!("{prefix}projection"), self.kind.name());
+        table.insert(format!("{prefix}inverse"), self.inverse);
+        table.insert(format!("{prefix}offset"), [self.offset.0, self.offset.1]);
+        let (x, y, z) = self.rotation;
+        table.insert(format!("{prefix}rotation"), [x, y, z]);
+        table.insert(format!("{prefix}scale"), self.scale);
+        table.insert(format!("{prefix}tunnel"), self.tunnel);
+        table.insert(format!("{prefix}size"), [self.size.0, self.size.1]);
+        table.insert(format!("{prefix}edge"), self.edge.name());
+        if let EdgeMode::Color(c) = self.edge {
+            table.insert(format!("{prefix}edge_color"), c);
+        }
+        table.insert(format!("{prefix}source_fov"), [self.fov.0, self.fov.1]);
+        self.color.write(table, prefix);
+        self.vignette.write(table, prefix);
+        self.graticule.write(table, prefix);
+        self.pole.write(table, prefix);
+    }
+
+    /// Reads the parameters written by [`Params::write`], keeping the
+    /// defaults for anything that is missing or malformed.
+    pub fn read(table: &Table, prefix: &str) -> Self {
+        let get = |key: &str| table.get(&format!("{prefix}{key}"));
+        let mut params = Params::default();
+        if let Some(kind) = get("projection").and_then(Value::as_str) {
+            params.kind = ProjectionKind::from_name(kind).unwrap_or(params.kind);
+        }
+        if let Some(inverse) = get("inverse").and_then(Value::as_bool) {
+            params.inverse = inverse;
+        }
+        if let Some([x, y]) = f32s(get("offset")) {
+            params.offset = (x, y);
+        }
+        if let Some([x, y, z]) = f32s(get("rotation")) {
+            params.rotation = (x, y, z);
+        }
+        if let Some(scale) = get("scale").and_then(Value::as_f32) {
+            params.scale = scale;
+        }
+        if let Some(tunnel) = get("tunnel").and_then(Value::as_bool) {
+            params.tunnel = tunnel;
+        }
+        if let Some([w, h]) = u32s(get("size")) {
+            params.size = (w.max(1), h.max(1));
+        }
+        if let Some(edge) = get("edge").and_then(Value::as_str) {
+            let color = f32s(get("edge_color")).unwrap_or([0.0, 0.0, 0.0, 1.0]);
+            params.edge = EdgeMode::from_name(edge, color).unwrap_or(params.edge);
+        }
+        if let Some([h, v]) = f32s(get("source_fov")) {
+            params.fov = (h.clamp(1.0, 360.0), v.clamp(1.0, 180.0));
+        }
+        params.color = ColorAdjust::read(table, prefix);
+        params.vignette = Vignette::read(table, prefix);
+        params.graticule = Graticule::read(table, prefix);
+        params.pole = PoleCap::read(table, prefix);
+        params
+    }
+}
+
+fn presets_dir() -> io::Result<PathBuf> {
+    config::config_dir()
+        .map(|dir| dir.join("presets"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))
+}
+
+fn preset_path(name: &str) -> io::Result<PathBuf> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && !name.contains(|c: char| matches!(c, '/' | '\\' | ':') || c.is_control());
+    if !valid {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} {name:?}", tr("invalid preset name")),
+        ));
+    }
+    Ok(presets_dir()?.join(format!("{name}.toml")))
+}
+
+/// Names of the saved presets, sorted.
+pub fn list() -> Vec<String> {
+    let Ok(entries) = presets_dir().and_then(fs::read_dir) else {
+        return vec![];
+    };
+    let mut names: Vec<_> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "toml" {
+                return None;
+            }
+            Some(path.file_stem()?.to_str()?.to_owned())
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+pub fn load(name: &str) -> io::Result<Params> {
+    let table: Table = fs::read_to_string(preset_path(name)?)?
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(Params::read(&table, ""))
+}
+
+pub fn save(name: &str, params: &Params) -> io::Result<()> {
+    let path = preset_path(name)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut table = Table::new();
+    params.write(&mut table, "");
+    fs::write(path, table.to_string())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        color::Stage,
+        effect::{Pole, PoleMode, VignetteMode},
+    };
+
+    /// Parameters with every field away from its default.
+    pub(crate) fn edited() -> Params {
+        Params {
+            kind: ProjectionKind::Pannini,
+            inverse: true,
+            offset: (0.25, -0.125),
+            rotation: (12.5, -30.0, 170.25),
+            scale: 0.75,
+            tunnel: true,
+            size: (1920, 1080),
+            edge: EdgeMode::Color([0.5, 0.25, 1.0, 0.75]),
+            fov: (180.0, 90.0),
+            color: ColorAdjust {
+                exposure: 1.5,
+                contrast: -0.25,
+                saturation: 0.5,
+                temperature: 0.1,
+                tint: -0.2,
+                stage: Stage::AfterProjection,
+            },
+            vignette: Vignette {
+                mode: VignetteMode::Fade,
+                strength: 0.6,
+                radius: 0.8,
+            },
+            graticule: Graticule {
+                enabled: true,
+                spacing: 15.0,
+                color: [1.0, 0.0, 0.0, 0.5],
+                preview_only: true,
+            },
+            pole: PoleCap {
+                enabled: true,
+                pole: Pole::Zenith,
+                mode: PoleMode::Fill,
+                radius: 20.0,
+            },
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let params = edited();
+        assert_ne!(params, Params::default());
+        let mut table = Table::new();
+        params.write(&mut table, "preset.");
+        let table: Table = table.to_string().parse().unwrap();
+        assert_eq!(Params::read(&table, "preset."), params);
+    }
+
+    #[test]
+    fn keeps_defaults_for_missing_or_malformed_values() {
+        let table: Table = "scale = \"big\"\nsize = [0, 10]\nprojection = \"Mercator\""
+            .parse()
+            .unwrap();
+        let params = Params::read(&table, "");
+        assert_eq!(params.scale, Params::default().scale);
+        assert_eq!(params.size, (1, 10));
+        assert_eq!(params.kind, ProjectionKind::Mercator);
+    }
+
+    #[test]
+    fn rejects_names_outside_of_the_presets() {
+        for name in ["", ".hidden", "a/b", "..\\b", "c:d"] {
+            assert!(preset_path(name).is_err(), "{name:?}");
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    fs,
+    io::{self, Cursor},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use image::{buffer::ConvertBuffer, DynamicImage, ImageBuffer, ImageResult, Rgba, Rgba32FImage};
+
+use crate::{
+    base64,
+    export::ExportOptions,
+    mipmap::Downscale,
+    overlay::Overlay,
+    preset::Params,
+    render::FrameSequence,
+    sampler::Sampler,
+    script::CustomProjection,
+    stereo::Stereo,
+    sticker::Sticker,
+    toml::{Table, Value},
+};
+
+pub const EXTENSION: &str = "sddl";
+
+/// Where the source panorama of a project comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Path(PathBuf),
+    /// The bytes of an encoded image file.
+    Embedded(Vec<u8>),
+}
+
+impl Source {
+    /// Embeds the file at `path` as is, or `img` as a 16-bit PNG when there
+    /// is no file to take it from.
+    pub fn embed(path: Option<&Path>, img: &Rgba32FImage) -> io::Result<Self> {
+        if let Some(path) = path {
+            return Ok(Source::Embedded(fs::read(path)?));
+        }
+        Ok(Source::Embedded(encode_png(img)?))
+    }
+
+    pub fn load(&self) -> ImageResult<FrameSequence> {
+        match self {
+            Source::Path(path) => FrameSequence::open(path),
+            Source::Embedded(bytes) => FrameSequence::load_from_memory(bytes),
+        }
+    }
+}
+
+fn encode_png(img: &Rgba32FImage) -> io::Result<Vec<u8>> {
+    let img: ImageBuffer<Rgba<u16>, Vec<u16>> = img.convert();
+    let mut bytes = Cursor::new(vec![]);
+    DynamicImage::ImageRgba16(img)
+        .write_to(&mut bytes, image::ImageFormat::Png)
+        .map_err(io::Error::other)?;
+    Ok(bytes.into_inner())
+}
+
+/// Embeds `img` under `key` as a 16-bit PNG, for the logos and stickers of
+/// projects.
+pub fn write_image(table: &mut Table, key: &str, img: &Rgba32FImage) -> io::Result<()> {
+    table.insert(key, base64::encode(&encode_png(img)?));
+    Ok(())
+}
+
+/// The image written by [`write_image`], or `None` when there is none or it
+/// can't be decoded.
+pub fn read_image(table: &Table, key: &str) -> Option<Arc<Rgba32FImage>> {
+    let bytes = base64::decode(table.get(key)?.as_str()?)?;
+    let img = image::load_from_memory(&bytes).ok()?;
+    Some(Arc::new(img.into_rgba32f()))
+}
+
+/// A composition saved to a `.sddl` file, which can be reopened exactly as it
+/// was left. The other output views and the settings of the app itself, like
+/// the backend and the poster options, are not part of it.
+#[derive(Clone)]
+pub struct Project {
+    pub source: Source,
+    pub params: Params,
+    pub sampler: Sampler,
+    pub samples: u32,
+    pub downscale: Downscale,
+    pub export: ExportOptions,
+    pub custom: CustomProjection,
+    pub overlay: Overlay,
+    pub stickers: Vec<Sticker>,
+    pub stereo: Stereo,
+}
+
+impl Project {
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut table = Table::new();
+        match &self.source {
+            Source::Path(source) => {
+                let source = source.to_str().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "source path is not UTF-8")
+                })?;
+                table.insert("source.path", source);
+            }
+            Source::Embedded(bytes) => table.insert("source.data", base64::encode(bytes)),
+        }
+        self.params.write(&mut table, "");
+        table.insert("sampler", self.sampler.name());
+        table.insert("samples", self.samples);
+        table.insert("downscale", self.downscale.name());
+        self.export.write(&mut table, "export.");
+        self.custom.write(&mut table, "script.");
+        self.overlay.write(&mut table, "overlay.")?;
+        for (i, sticker) in self.stickers.iter().enumerate() {
+            sticker.write(&mut table, &format!("sticker{}.", i + 1))?;
+        }
+        self.stereo.write(&mut table, "stereo.");
+        fs::write(path, table.to_string())
+    }
+
+    /// Opens a project file. Relative source paths are resolved against the
+    /// directory of the project.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        let table: Table = fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let source = if let Some(data) = table.get("source.data").and_then(Value::as_str) {
+            Source::Embedded(base64::decode(data).ok_or_else(|| invalid("invalid image data"))?)
+        } else if let Some(source) = table.get("source.path").and_then(Value::as_str) {
+            let dir = path.parent().unwrap_or(Path::new(""));
+            Source::Path(dir.join(source))
+        } else {
+            return Err(invalid("missing source image"));
+        };
+        let get = |key| table.get(key);
+        let stickers = (1..)
+            .map_while(|i| Sticker::read(&table, &format!("sticker{i}.")))
+            .collect();
+        Ok(Project {
+            source,
+            params: Params::read(&table, ""),
+            sampler: get("sampler")
+                .and_then(Value::as_str)
+                .and_then(Sampler::from_name)
+                .unwrap_or(Sampler::Bilinear),
+            samples: get("samples").and_then(Value::as_u32).unwrap_or(1).max(1),
+            downscale: get("downscale")
+                .and_then(Value::as_str)
+                .and_then(Downscale::from_name)
+                .unwrap_or(Downscale::Auto),
+            export: ExportOptions::read(&table, "export."),
+            custom: CustomProjection::read(&table, "script."),
+            overlay: Overlay::read(&table, "overlay."),
+            stickers,
+            stereo: Stereo::read(&table, "stereo."),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        export::Format,
+        overlay::StampKind,
+        preset::{self, tests::edited},
+        stereo::{Eye, StereoLayout},
+    };
+
+    fn project(source: Source) -> Project {
+        let img = |w, h| Arc::new(Rgba32FImage::from_pixel(w, h, Rgba([1.0, 0.5, 0.0, 0.5])));
+        let mut custom = CustomProjection::new("lon = x * 2\nlat = y");
+        custom.enabled = true;
+        let overlay = Overlay {
+            enabled: true,
+            kind: StampKind::Logo,
+            text: "说的\n\"道理\"".to_owned(),
+            logo: Some(img(4, 2)),
+            position: (0.25, 0.75),
+            size: 0.2,
+            opacity: 0.5,
+            ..Overlay::default()
+        };
+        let mut sticker = Sticker::new("sun".to_owned(), img(2, 3));
+        (sticker.longitude, sticker.latitude, sticker.visible) = (45.0, 10.0, false);
+        let mut stereo = Stereo::default();
+        (stereo.layout, stereo.eye, stereo.pair) = (StereoLayout::SideBySide, Eye::Right, true);
+        Project {
+            source,
+            params: edited(),
+            sampler: Sampler::Lanczos3,
+            samples: 4,
+            downscale: Downscale::Quarter,
+            export: ExportOptions {
+                format: Format::Jpeg,
+                quality: 70,
+                ..ExportOptions::default()
+            },
+            custom,
+            overlay,
+            stickers: vec![sticker.clone(), Sticker::new("moon".to_owned(), img(1, 1))],
+            stereo,
+        }
+    }
+
+    fn assert_same(a: &Project, b: &Project) {
+        assert_eq!(a.source, b.source);
+        assert_eq!(a.params, b.params);
+        assert_eq!(
+            (a.sampler, a.samples, a.downscale),
+            (b.sampler, b.samples, b.downscale)
+        );
+        assert_eq!(a.export, b.export);
+        assert_eq!(a.custom.source(), b.custom.source());
+        assert_eq!(a.custom.enabled, b.custom.enabled);
+        let overlay = |o: &Overlay| {
+            let logo = o.logo.as_ref().map(|logo| logo.dimensions());
+            let layout = (o.position, o.size, o.opacity, o.color);
+            (
+                o.enabled,
+                o.kind,
+                o.text.clone(),
+                o.font_path.clone(),
+                logo,
+                layout,
+            )
+        };
+        assert_eq!(overlay(&a.overlay), overlay(&b.overlay));
+        let sticker = |s: &Sticker| {
+            let place = (s.longitude, s.latitude, s.size);
+            (s.name.clone(), s.image.dimensions(), s.visible, place)
+        };
+        let stickers = |p: &Project| p.stickers.iter().map(sticker).collect::<Vec<_>>();
+        assert_eq!(stickers(a), stickers(b));
+        let stereo = |s: &Stereo| (s.layout, s.eye, s.pair);
+        assert_eq!(stereo(&a.stereo), stereo(&b.stereo));
+    }
+
+    /// Saves `project` and opens it again, in a directory of its own.
+    fn reopen(project: &Project, name: &str) -> Project {
+        let dir = std::env::temp_dir().join(format!("shuodedaoli-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("test.{EXTENSION}"));
+        project.save(&path).unwrap();
+        let reopened = Project::open(&path).unwrap();
+        fs::remove_dir_all(&dir).ok();
+        reopened
+    }
+
+    #[test]
+    fn round_trip_with_a_path() {
+        let project = project(Source::Path(std::env::temp_dir().join("source.jpg")));
+        assert_same(&reopen(&project, "path"), &project);
+    }
+
+    #[test]
+    fn round_trip_with_an_embedded_image() {
+        let img = Rgba32FImage::from_pixel(3, 2, Rgba([0.25, 0.5, 1.0, 1.0]));
+        let project = project(Source::embed(None, &img).unwrap());
+        let reopened = reopen(&project, "embedded");
+        assert_same(&reopened, &project);
+        let seq = reopened.source.load().unwrap();
+        assert_eq!(seq.first().dimensions(), (3, 2));
+        let logo = reopened.overlay.logo.unwrap();
+        assert!((logo.get_pixel(0, 0).0[1] - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rejects_projects_without_a_source() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!(
+            "shuodedaoli-{}-empty.{EXTENSION}",
+            std::process::id()
+        ));
+        let mut table = Table::new();
+        preset::Params::default().write(&mut table, "");
+        fs::write(&path, table.to_string()).unwrap();
+        let error = Project::open(&path).err().map(|e| e.kind());
+        fs::remove_file(&path).ok();
+        assert_eq!(error, Some(io::ErrorKind::InvalidData));
+    }
+}
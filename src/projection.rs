@@ -1,58 +1,463 @@
-use std::f32::consts::PI;
+use std::{
+    f32::consts::{FRAC_PI_2, PI, SQRT_2},
+    sync::Arc,
+};
 
 use nalgebra::{vector, Rotation3, SVector, Unit};
+use wide::f32x8;
+
+use crate::script::{Script, Target};
 
 type Vec2u = SVector<u32, 2>;
 type Vec2f = SVector<f32, 2>;
 type Vec3f = SVector<f32, 3>;
 
-pub struct Projection {
-    radius: f32,
-    image_size: Vec2f,
-    proj_size: Vec2f,
-    offset: Vec2f,
-    rotation: Rotation3<f32>,
+/// Maps an output pixel to a pixel of the equirectangular source image.
+///
+/// Points outside of the projection's domain map to `NaN`.
+pub trait SphereProjection: Send + Sync {
+    fn proj(&self, p: Vec2f) -> Vec2f;
+
+    /// Maps every pixel of row `y`, from the left edge to the length of
+    /// `out`. Only [`Stereographic`] maps eight pixels at a time.
+    fn proj_row(&self, y: u32, out: &mut [Vec2f]) {
+        for (x, out) in out.iter_mut().enumerate() {
+            *out = self.proj(vector![x as f32, y as f32]);
+        }
+    }
+}
+
+/// Maps a direction to a point of an equirectangular image, in coordinates
+/// normalized to `[0, 1]`. The zenith is `+z` and the center of the image
+/// faces `+y`.
+pub fn sphere_to_equirect(p: Unit<Vec3f>) -> Vec2f {
+    let row = p.z.clamp(-1.0, 1.0).acos() / PI;
+    let col = p.x.atan2(p.y) / (2.0 * PI) + 0.5;
+    vector![col, row]
+}
+
+/// The inverse of [`sphere_to_equirect`].
+pub fn equirect_to_sphere(p: Vec2f) -> Unit<Vec3f> {
+    let theta = p.y * PI;
+    let phi = (p.x - 0.5) * 2.0 * PI;
+    let (sin_theta, cos_theta) = theta.sin_cos();
+    let p = vector![sin_theta * phi.sin(), sin_theta * phi.cos(), cos_theta];
+    Unit::new_normalize(p)
 }
 
-impl Projection {
+/// Turns `rotation` by the smallest angle that makes the center of the
+/// projection look along `dir`.
+pub fn center_on(rotation: Rotation3<f32>, dir: Unit<Vec3f>) -> Rotation3<f32> {
+    let center = rotation * Vec3f::z();
+    let turn = Rotation3::rotation_between(&center, &dir)
+        .unwrap_or_else(|| Rotation3::from_axis_angle(&Vec3f::x_axis(), PI));
+    turn * rotation
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectionKind {
+    Stereographic,
+    Rectilinear,
+    Equidistant,
+    Equisolid,
+    Pannini,
+    Mercator,
+    Orthographic,
+}
+
+impl ProjectionKind {
+    pub const ALL: [ProjectionKind; 7] = [
+        ProjectionKind::Stereographic,
+        ProjectionKind::Rectilinear,
+        ProjectionKind::Equidistant,
+        ProjectionKind::Equisolid,
+        ProjectionKind::Pannini,
+        ProjectionKind::Mercator,
+        ProjectionKind::Orthographic,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ProjectionKind::Stereographic => "Stereographic",
+            ProjectionKind::Rectilinear => "Rectilinear",
+            ProjectionKind::Equidistant => "Fisheye (Equidistant)",
+            ProjectionKind::Equisolid => "Fisheye (Equisolid)",
+            ProjectionKind::Pannini => "Pannini",
+            ProjectionKind::Mercator => "Mercator",
+            ProjectionKind::Orthographic => "Orthographic",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        ProjectionKind::ALL.into_iter().find(|k| k.name() == name)
+    }
+
+    pub fn build(self, view: View) -> Box<dyn SphereProjection> {
+        match self {
+            ProjectionKind::Stereographic => Box::new(Stereographic(view)),
+            ProjectionKind::Rectilinear => Box::new(Rectilinear(view)),
+            ProjectionKind::Equidistant => Box::new(Fisheye::equidistant(view)),
+            ProjectionKind::Equisolid => Box::new(Fisheye::equisolid(view)),
+            ProjectionKind::Pannini => Box::new(Pannini::new(view, 1.0)),
+            ProjectionKind::Mercator => Box::new(Mercator(view)),
+            ProjectionKind::Orthographic => Box::new(Orthographic(view)),
+        }
+    }
+}
+
+/// The part shared by all projections: output framing and sphere orientation.
+///
+/// The view direction is `+z`; a plane distance of `radius` corresponds to
+/// the equator for the azimuthal projections.
+pub struct View {
+    pub(crate) radius: f32,
+    pub(crate) image_size: Vec2f,
+    pub(crate) proj_size: Vec2f,
+    pub(crate) offset: Vec2f,
+    pub(crate) rotation: Rotation3<f32>,
+    /// Fractions of the longitudes and latitudes covered by the source,
+    /// around its center.
+    pub(crate) coverage: Vec2f,
+}
+
+impl View {
     pub fn new(
         image_size: Vec2u,
         proj_size: Vec2u,
         offset: Vec2f,
         rotation: Rotation3<f32>,
         scale: f32,
+        fov: (f32, f32),
     ) -> Self {
         let image_size = image_size.cast();
         let proj_size = proj_size.cast();
         let radius = proj_size.min() / 10. * scale;
-        Projection {
+        View {
             radius,
             image_size,
             proj_size,
             offset,
             rotation,
+            coverage: vector![fov.0 / 360.0, fov.1 / 180.0],
         }
     }
 
-    pub fn proj(&self, p: Vec2f) -> Vec2f {
-        let p = p + self.offset.add_scalar(-0.5).component_mul(&self.proj_size);
-        let p = self.image_to_sphere(p);
-        let p = self.rotation * p;
-        self.sphere_to_image(p)
+    /// Whether the source covers the whole sphere.
+    pub fn is_full(&self) -> bool {
+        self.coverage == vector![1.0, 1.0]
+    }
+
+    /// Stretches a point of a full panorama onto the covered part of it,
+    /// both in normalized coordinates. Exact for full panoramas.
+    fn full_to_covered(&self, p: Vec2f) -> Vec2f {
+        let c = self.coverage;
+        (p - (Vec2f::repeat(1.0) - c) / 2.0).component_div(&c)
+    }
+
+    /// The inverse of [`View::full_to_covered`].
+    fn covered_to_full(&self, p: Vec2f) -> Vec2f {
+        let c = self.coverage;
+        p.component_mul(&c) + (Vec2f::repeat(1.0) - c) / 2.0
+    }
+
+    fn pixel_to_plane(&self, p: Vec2f) -> Vec2f {
+        p + self.offset.add_scalar(-0.5).component_mul(&self.proj_size)
     }
 
+    fn plane_to_pixel(&self, p: Vec2f) -> Vec2f {
+        p - self.offset.add_scalar(-0.5).component_mul(&self.proj_size)
+    }
+
+    fn sphere_to_image(&self, p: Unit<Vec3f>) -> Vec2f {
+        let mut p = self.rotation * p;
+        p.renormalize_fast();
+        let p = self.full_to_covered(sphere_to_equirect(p));
+        p.component_mul(&self.image_size)
+    }
+
+    /// [`View::sphere_to_image`] of eight unit vectors at once.
+    fn sphere_to_image8(&self, p: [f32x8; 3]) -> [f32x8; 2] {
+        let m = self.rotation.matrix();
+        let [x, y, z] = [0, 1, 2].map(|i| p[0] * m[(i, 0)] + p[1] * m[(i, 1)] + p[2] * m[(i, 2)]);
+        // The same first order correction as `renormalize_fast`.
+        let k = (f32x8::splat(3.0) - (x * x + y * y + z * z)) * 0.5;
+        let row = (z * k).max(f32x8::splat(-1.0)).min(f32x8::ONE).acos() / PI;
+        let col = (x * k).atan2(y * k) / (2.0 * PI) + 0.5;
+        let (c, size) = (self.coverage, self.image_size);
+        let col = (col - (1.0 - c.x) / 2.0) / c.x;
+        let row = (row - (1.0 - c.y) / 2.0) / c.y;
+        [col * size.x, row * size.y]
+    }
+
+    fn image_to_sphere(&self, p: Vec2f) -> Unit<Vec3f> {
+        let p = equirect_to_sphere(self.covered_to_full(p.component_div(&self.image_size)));
+        self.rotation.inverse() * p
+    }
+
+    /// Azimuthal projections, given the polar angle as a function of the
+    /// normalized plane distance.
+    fn radial(&self, p: Vec2f, theta: impl Fn(f32) -> Option<f32>) -> Vec2f {
+        let p = self.pixel_to_plane(p) / self.radius;
+        let rho = p.norm();
+        let Some(theta) = theta(rho) else {
+            return Vec2f::repeat(f32::NAN);
+        };
+        let dir = if rho > 0.0 {
+            p * (theta.sin() / rho)
+        } else {
+            Vec2f::zeros()
+        };
+        let p = vector![dir.x, dir.y, theta.cos()];
+        self.sphere_to_image(Unit::new_normalize(p))
+    }
+}
+
+pub struct Stereographic(View);
+
+impl Stereographic {
     fn image_to_sphere(&self, p: Vec2f) -> Unit<Vec3f> {
-        let r2 = self.radius.powi(2);
+        let r2 = self.0.radius.powi(2);
         let k = 2.0 * r2 / (p.norm_squared() + r2);
-        let result = vector![k * p.x, k * p.y, (k - 1.0) * self.radius];
+        let result = vector![k * p.x, k * p.y, (k - 1.0) * self.0.radius];
         Unit::new_normalize(result)
     }
+}
 
-    fn sphere_to_image(&self, mut p: Unit<Vec3f>) -> Vec2f {
-        p.renormalize_fast();
-        let row = p.z.acos() / PI;
-        let col = p.x.atan2(p.y) / (2.0 * PI) + 0.5;
-        let p = vector![col, row];
-        p.component_mul(&self.image_size)
+impl SphereProjection for Stereographic {
+    fn proj(&self, p: Vec2f) -> Vec2f {
+        let p = self.0.pixel_to_plane(p);
+        let p = self.image_to_sphere(p);
+        self.0.sphere_to_image(p)
+    }
+
+    fn proj_row(&self, y: u32, out: &mut [Vec2f]) {
+        let view = &self.0;
+        let shift = view.offset.add_scalar(-0.5).component_mul(&view.proj_size);
+        let r = view.radius;
+        let lanes = f32x8::from([0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+        let start = out.len() / 8 * 8;
+        let mut blocks = out.chunks_exact_mut(8);
+        for (i, block) in blocks.by_ref().enumerate() {
+            let px = lanes + (i * 8) as f32 + shift.x;
+            let py = f32x8::splat(y as f32 + shift.y);
+            let k = f32x8::splat(2.0 * r * r) / (px * px + py * py + r * r);
+            let p = [k * px, k * py, (k - 1.0) * r];
+            let norm = (p[0] * p[0] + p[1] * p[1] + p[2] * p[2]).sqrt();
+            let [col, row] = view.sphere_to_image8(p.map(|c| c / norm));
+            let (col, row) = (col.to_array(), row.to_array());
+            for (j, out) in block.iter_mut().enumerate() {
+                *out = vector![col[j], row[j]];
+            }
+        }
+        for (x, out) in blocks.into_remainder().iter_mut().enumerate() {
+            *out = self.proj(vector![(start + x) as f32, y as f32]);
+        }
+    }
+}
+
+/// Unwraps a little planet back into an equirectangular panorama.
+///
+/// The `view` is the same as for [`Stereographic`]: its image size is the
+/// panorama (here the output) and its projection size is the planet (here
+/// the source).
+pub struct InverseProjection(View);
+
+impl InverseProjection {
+    pub fn new(view: View) -> Self {
+        InverseProjection(view)
+    }
+
+    fn sphere_to_plane(&self, p: Unit<Vec3f>) -> Vec2f {
+        let k = p.z + 1.0;
+        p.xy() * (self.0.radius / k)
+    }
+}
+
+impl SphereProjection for InverseProjection {
+    fn proj(&self, p: Vec2f) -> Vec2f {
+        let p = self.0.image_to_sphere(p);
+        let p = self.sphere_to_plane(p);
+        self.0.plane_to_pixel(p)
+    }
+}
+
+pub struct Rectilinear(View);
+
+impl SphereProjection for Rectilinear {
+    fn proj(&self, p: Vec2f) -> Vec2f {
+        self.0.radial(p, |rho| Some(rho.atan()))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FisheyeMapping {
+    Equidistant,
+    Equisolid,
+}
+
+pub struct Fisheye {
+    view: View,
+    mapping: FisheyeMapping,
+}
+
+impl Fisheye {
+    pub fn equidistant(view: View) -> Self {
+        let mapping = FisheyeMapping::Equidistant;
+        Fisheye { view, mapping }
+    }
+
+    pub fn equisolid(view: View) -> Self {
+        let mapping = FisheyeMapping::Equisolid;
+        Fisheye { view, mapping }
+    }
+}
+
+impl SphereProjection for Fisheye {
+    fn proj(&self, p: Vec2f) -> Vec2f {
+        match self.mapping {
+            FisheyeMapping::Equidistant => self
+                .view
+                .radial(p, |rho| (rho <= 2.0).then_some(rho * FRAC_PI_2)),
+            FisheyeMapping::Equisolid => self.view.radial(p, |rho| {
+                (rho <= SQRT_2).then(|| 2.0 * (rho / SQRT_2).asin())
+            }),
+        }
+    }
+}
+
+pub struct Pannini {
+    view: View,
+    distance: f32,
+}
+
+impl Pannini {
+    pub fn new(view: View, distance: f32) -> Self {
+        Pannini { view, distance }
+    }
+}
+
+impl SphereProjection for Pannini {
+    fn proj(&self, p: Vec2f) -> Vec2f {
+        let p = self.view.pixel_to_plane(p) / self.view.radius;
+        let d = self.distance;
+        let k = p.x.powi(2) / (d + 1.0).powi(2);
+        let dscr = k.powi(2) * d.powi(2) - (k + 1.0) * (k * d.powi(2) - 1.0);
+        if dscr < 0.0 {
+            return Vec2f::repeat(f32::NAN);
+        }
+        let clon = (-k * d + dscr.sqrt()) / (k + 1.0);
+        let s = (d + 1.0) / (d + clon);
+        let lon = p.x.atan2(s * clon);
+        let p = vector![lon.sin(), p.y / s, lon.cos()];
+        self.view.sphere_to_image(Unit::new_normalize(p))
+    }
+}
+
+pub struct Mercator(View);
+
+impl SphereProjection for Mercator {
+    fn proj(&self, p: Vec2f) -> Vec2f {
+        let p = self.0.pixel_to_plane(p) / self.0.radius;
+        let lon = p.x;
+        let lat = p.y.sinh().atan();
+        let p = vector![lat.cos() * lon.sin(), lat.sin(), lat.cos() * lon.cos()];
+        self.0.sphere_to_image(Unit::new_normalize(p))
+    }
+}
+
+pub struct Orthographic(View);
+
+impl SphereProjection for Orthographic {
+    fn proj(&self, p: Vec2f) -> Vec2f {
+        self.0.radial(p, |rho| (rho <= 1.0).then(|| rho.asin()))
+    }
+}
+
+/// A projection written as a [`Script`]. Scripts that output a point of the
+/// source bypass the rotation and field of view of the view.
+pub struct Scripted {
+    view: View,
+    script: Arc<Script>,
+}
+
+impl Scripted {
+    pub fn new(view: View, script: Arc<Script>) -> Self {
+        Scripted { view, script }
+    }
+}
+
+impl SphereProjection for Scripted {
+    fn proj(&self, p: Vec2f) -> Vec2f {
+        let view = &self.view;
+        let q = view.pixel_to_plane(p) / view.radius;
+        let size = view.proj_size;
+        let [a, b] = self.script.run([q.x, q.y, p.x, p.y, size.x, size.y]);
+        match self.script.target() {
+            Target::Direction => {
+                let p = vector![b.cos() * a.sin(), b.sin(), b.cos() * a.cos()];
+                view.sphere_to_image(Unit::new_normalize(p))
+            }
+            Target::Source => vector![a, b].component_mul(&view.image_size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view() -> View {
+        let rotation = Rotation3::from_euler_angles(0.3, -0.2, 1.1);
+        View::new(
+            vector![2048, 1024],
+            vector![600, 400],
+            vector![0.1, 0.4],
+            rotation,
+            1.5,
+            (360.0, 180.0),
+        )
+    }
+
+    #[test]
+    fn equirect_round_trip() {
+        for (x, y) in [(0.1, 0.2), (0.5, 0.5), (0.9, 0.7), (0.3, 0.95)] {
+            let p = vector![x, y];
+            let q = sphere_to_equirect(equirect_to_sphere(p));
+            assert!((q - p).norm() < 1e-5, "{p} -> {q}");
+        }
+    }
+
+    #[test]
+    fn inverse_undoes_stereographic() {
+        let forward = ProjectionKind::Stereographic.build(view());
+        let inverse = InverseProjection::new(view());
+        for y in (0..400).step_by(37) {
+            for x in (0..600).step_by(41) {
+                let p = vector![x as f32, y as f32];
+                let q = inverse.proj(forward.proj(p));
+                assert!((q - p).norm() < 0.05, "{p} -> {q}");
+            }
+        }
+    }
+
+    #[test]
+    fn rows_match_single_points() {
+        for kind in ProjectionKind::ALL {
+            let projection = kind.build(view());
+            let mut row = vec![Vec2f::zeros(); 21];
+            projection.proj_row(123, &mut row);
+            for (x, q) in row.into_iter().enumerate() {
+                let p = projection.proj(vector![x as f32, 123.0]);
+                let same = (p.x.is_nan() && q.x.is_nan()) || (p - q).norm() < 0.01;
+                assert!(same, "{}: {p} != {q}", kind.name());
+            }
+        }
+    }
+
+    #[test]
+    fn center_on_looks_along_the_direction() {
+        let dir = Unit::new_normalize(vector![1.0, -2.0, 0.5]);
+        let rotation = center_on(Rotation3::identity(), dir);
+        assert!((rotation * Vec3f::z() - dir.into_inner()).norm() < 1e-5);
     }
 }
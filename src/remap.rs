@@ -0,0 +1,252 @@
+//! Exports the warp itself, as the source pixel that every output pixel is
+//! taken from, so that it can be applied to videos by other tools.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use image::Rgba32FImage;
+use nalgebra::{Rotation3, SVector};
+use rayon::prelude::*;
+
+use crate::{
+    projection::ProjectionKind,
+    render::{self, RenderRequest},
+    sampler::{self, EdgeMode, Sampler},
+};
+
+type Vec2f = SVector<f32, 2>;
+type Vec4f = SVector<f32, 4>;
+
+/// Largest number of coordinates kept in a table, 128 MiB of them.
+const MAX_TABLE_LEN: usize = 1 << 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemapFormat {
+    /// 16-bit binary PGM files, as read by ffmpeg's `remap` filter. Pixels
+    /// without a source are set to 65535.
+    Pgm16,
+    /// Grayscale PFM files of float coordinates, which OpenCV reads as the
+    /// maps of `cv::remap`. Pixels without a source are set to -1.
+    Pfm,
+}
+
+impl RemapFormat {
+    pub const ALL: [RemapFormat; 2] = [RemapFormat::Pgm16, RemapFormat::Pfm];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RemapFormat::Pgm16 => "PGM16 (ffmpeg)",
+            RemapFormat::Pfm => "PFM (OpenCV)",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            RemapFormat::Pgm16 => "pgm",
+            RemapFormat::Pfm => "pfm",
+        }
+    }
+}
+
+/// Everything the source coordinates of a render depend on.
+#[derive(Debug, Clone, PartialEq)]
+struct Key {
+    kind: ProjectionKind,
+    script: Option<String>,
+    inverse: bool,
+    offset: Vec2f,
+    rotation: Rotation3<f32>,
+    scale: f32,
+    fov: (f32, f32),
+    source: (u32, u32),
+    size: (u32, u32),
+    samples: u32,
+}
+
+impl Key {
+    fn new(request: &RenderRequest, size: (u32, u32)) -> Self {
+        Self {
+            kind: request.kind,
+            script: request.script.as_ref().map(|s| s.source().to_owned()),
+            inverse: request.inverse,
+            offset: request.offset,
+            rotation: request.rotation,
+            scale: request.scale,
+            fov: request.fov,
+            source: request.image.dimensions(),
+            size,
+            samples: request.samples_at(size),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.size.0 as usize * self.size.1 as usize * self.samples as usize
+    }
+}
+
+/// The source coordinates of every sample of every output pixel, so that
+/// frames that only differ in their source image are not projected again.
+pub struct RemapTable {
+    key: Key,
+    /// Row by row, `samples` for each pixel.
+    coords: Vec<Vec2f>,
+}
+
+impl RemapTable {
+    pub fn new(request: &RenderRequest, size: (u32, u32)) -> Self {
+        let key = Key::new(request, size);
+        let proj = request.projection(size);
+        let samples = key.samples as usize;
+        let mut coords = vec![Vec2f::zeros(); key.len()];
+        coords
+            .par_chunks_mut(size.0 as usize * samples)
+            .enumerate()
+            .for_each(|(y, line)| {
+                for (x, pixel) in line.chunks_exact_mut(samples).enumerate() {
+                    let points = render::sample_points(x as u32, y as u32, key.samples);
+                    for (c, p) in pixel.iter_mut().zip(points) {
+                        *c = proj.proj(p);
+                    }
+                }
+            });
+        Self { key, coords }
+    }
+
+    /// Averages the samples of the output pixel at `x`, `y` from `img`.
+    pub fn sample(
+        &self,
+        img: &Rgba32FImage,
+        sampler: Sampler,
+        edge: EdgeMode,
+        linear: bool,
+        x: u32,
+        y: u32,
+    ) -> Vec4f {
+        let samples = self.key.samples as usize;
+        let start = (y as usize * self.key.size.0 as usize + x as usize) * samples;
+        let sum: Vec4f = self.coords[start..start + samples]
+            .iter()
+            .map(|p| sampler.sample_linear(img, p.x, p.y, edge, linear))
+            .sum();
+        sampler::encode(sum / samples as f32)
+    }
+}
+
+/// The last table built, shared by all frames of an export.
+#[derive(Default)]
+pub struct RemapCache(Mutex<Option<Arc<RemapTable>>>);
+
+impl RemapCache {
+    /// The table for `request` at `size`, built again when the projection
+    /// differs from the last one. Returns `None` for outputs too large to
+    /// keep one for.
+    pub fn get(&self, request: &RenderRequest, size: (u32, u32)) -> Option<Arc<RemapTable>> {
+        let key = Key::new(request, size);
+        if key.len() > MAX_TABLE_LEN {
+            return None;
+        }
+        let mut table = self.0.lock().unwrap();
+        match &*table {
+            Some(table) if table.key == key => Some(Arc::clone(table)),
+            _ => Some(Arc::clone(
+                table.insert(Arc::new(RemapTable::new(request, size))),
+            )),
+        }
+    }
+}
+
+/// Moves a point off the source image to where `edge` samples it from, or
+/// returns `None` when nothing is sampled there.
+fn fold(x: f32, y: f32, size: (u32, u32), edge: EdgeMode) -> Option<(f32, f32)> {
+    if !(x.is_finite() && y.is_finite()) {
+        return None;
+    }
+    let (w, h) = (size.0 as f32, size.1 as f32);
+    let inside = |x: f32, y: f32| x >= -0.5 && y >= -0.5 && x <= w - 0.5 && y <= h - 0.5;
+    let mirror = |i: f32, n: f32| {
+        let i = (i + 0.5).rem_euclid(2.0 * n);
+        if i < n {
+            i - 0.5
+        } else {
+            2.0 * n - i - 0.5
+        }
+    };
+    let folded = match edge {
+        EdgeMode::Wrap => {
+            let (x, y) = if y < -0.5 {
+                (x + w / 2.0, -1.0 - y)
+            } else if y > h - 0.5 {
+                (x + w / 2.0, 2.0 * h - 1.0 - y)
+            } else {
+                (x, y)
+            };
+            ((x + 0.5).rem_euclid(w) - 0.5, y)
+        }
+        EdgeMode::Mirror => (mirror(x, w), mirror(y, h)),
+        EdgeMode::Color(_) | EdgeMode::Transparent if inside(x, y) => (x, y),
+        EdgeMode::Color(_) | EdgeMode::Transparent => return None,
+    };
+    // Half a pixel past the border still rounds to an edge pixel.
+    Some((folded.0.clamp(0.0, w - 1.0), folded.1.clamp(0.0, h - 1.0)))
+}
+
+/// Paths of the x and y maps for `path`, named with `_x` and `_y` suffixes.
+fn map_paths(path: &Path, format: RemapFormat) -> [PathBuf; 2] {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    ["x", "y"].map(|axis| path.with_file_name(format!("{stem}_{axis}.{}", format.extension())))
+}
+
+/// Writes the x and y maps of `request` next to `path`, a row at a time so
+/// that maps of any size can be written.
+pub fn export(request: &RenderRequest, path: &Path, format: RemapFormat) -> io::Result<()> {
+    let (width, height) = request.size;
+    let proj = request.projection(request.size);
+    let source = request.image.dimensions();
+    let edge = request.edge_mode(&request.view(request.size));
+    let [x_path, y_path] = map_paths(path, format);
+    let mut maps = [
+        BufWriter::new(File::create(x_path)?),
+        BufWriter::new(File::create(y_path)?),
+    ];
+    // PFM rows go bottom to top.
+    let rows: Box<dyn Iterator<Item = u32>> = match format {
+        RemapFormat::Pgm16 => {
+            for w in &mut maps {
+                write!(w, "P5\n{width} {height}\n65535\n")?;
+            }
+            Box::new(0..height)
+        }
+        RemapFormat::Pfm => {
+            // A negative scale means little-endian.
+            for w in &mut maps {
+                write!(w, "Pf\n{width} {height}\n-1.0\n")?;
+            }
+            Box::new((0..height).rev())
+        }
+    };
+    let mut row = vec![Vec2f::zeros(); width as usize];
+    for y in rows {
+        proj.proj_row(y, &mut row);
+        for p in &row {
+            let c = fold(p.x, p.y, source, edge);
+            let values = [c.map(|c| c.0), c.map(|c| c.1)];
+            for (w, value) in maps.iter_mut().zip(values) {
+                match format {
+                    RemapFormat::Pgm16 => {
+                        let v = value.map_or(u16::MAX, |v| v.round().clamp(0.0, 65534.0) as u16);
+                        w.write_all(&v.to_be_bytes())?;
+                    }
+                    RemapFormat::Pfm => w.write_all(&value.unwrap_or(-1.0).to_le_bytes())?,
+                }
+            }
+        }
+    }
+    for w in &mut maps {
+        w.flush()?;
+    }
+    Ok(())
+}
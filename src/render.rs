@@ -0,0 +1,849 @@
+use std::{
+    fs,
+    io::{BufRead, Cursor, Seek},
+    ops::Range,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use egui::{epaint::ImageDelta, load::SizedTexture, mutex::RwLock, ColorImage, Context};
+use image::{
+    buffer::ConvertBuffer,
+    codecs::{
+        gif::GifDecoder, jpeg::JpegDecoder, png::PngDecoder, tiff::TiffDecoder, webp::WebPDecoder,
+    },
+    error::{DecodingError, ImageFormatHint},
+    AnimationDecoder, Delay, DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageResult,
+    Rgba32FImage, RgbaImage,
+};
+use nalgebra::{vector, Rotation3};
+use rayon::prelude::*;
+
+use crate::{
+    color::{ColorAdjust, Stage},
+    effect::{Graticule, Vignette},
+    histogram::Analysis,
+    icc::Profile,
+    metadata::{self, Metadata},
+    mipmap::{Downscale, Mipmap},
+    overlay::Overlay,
+    preset::Params,
+    projection::{InverseProjection, ProjectionKind, Scripted, SphereProjection, View},
+    remap::{RemapCache, RemapTable},
+    sampler::{self, EdgeMode, Sampler},
+    script::Script,
+};
+
+type Vec2f = nalgebra::SVector<f32, 2>;
+type Vec4f = nalgebra::SVector<f32, 4>;
+
+/// Longer side of the coarse passes rendered before the full resolution one.
+const PREVIEW_SIZES: [u32; 2] = [150, 300];
+
+/// Number of output rows rendered by one parallel task.
+const BAND_ROWS: usize = 16;
+
+/// Longest output side rendered in one piece. Larger outputs are only
+/// previewed at this size and saved tile by tile.
+pub const MAX_PREVIEW_SIZE: u32 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Canceled;
+
+/// Why a render stopped without an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderError {
+    Canceled,
+    /// The output is zero pixels wide or high.
+    Empty,
+}
+
+impl From<Canceled> for RenderError {
+    fn from(_: Canceled) -> Self {
+        RenderError::Canceled
+    }
+}
+
+/// Becomes canceled as soon as a newer job is started on the same generation
+/// counter.
+#[derive(Clone)]
+pub struct CancelToken {
+    generation: Arc<AtomicU64>,
+    id: u64,
+}
+
+impl CancelToken {
+    /// Starts a new job on `generation`, canceling the tokens of all older
+    /// ones.
+    pub fn next(generation: &Arc<AtomicU64>) -> Self {
+        let id = generation.fetch_add(1, Ordering::Relaxed) + 1;
+        Self {
+            generation: Arc::clone(generation),
+            id,
+        }
+    }
+
+    pub fn is_canceled(&self) -> bool {
+        self.generation.load(Ordering::Relaxed) != self.id
+    }
+
+    pub fn check(&self) -> Result<(), Canceled> {
+        if self.is_canceled() {
+            Err(Canceled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn hash(x: u32, y: u32, i: u32) -> f32 {
+    let mut h = x.wrapping_mul(73856093) ^ y.wrapping_mul(19349663) ^ i.wrapping_mul(83492791);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846ca68b);
+    h ^= h >> 16;
+    h as f32 / u32::MAX as f32
+}
+
+/// Points of the output pixel at `x`, `y` that are sampled: its center, or
+/// one jittered point in each cell of a grid laid over it.
+pub fn sample_points(x: u32, y: u32, samples: u32) -> impl Iterator<Item = Vec2f> {
+    let cols = 1 << samples.max(1).ilog2().div_ceil(2);
+    let rows = samples.max(1) / cols;
+    let center = vector![x as f32, y as f32];
+    (0..samples.max(1)).map(move |i| {
+        if samples <= 1 {
+            return center;
+        }
+        let jitter = vector![hash(x, y, 2 * i), hash(x, y, 2 * i + 1)];
+        let cell = vector![(i % cols) as f32, (i / cols) as f32];
+        let d = (cell + jitter).component_div(&vector![cols as f32, rows as f32]);
+        center + d.add_scalar(-0.5)
+    })
+}
+
+/// Averages the samples of the output pixel at `x`, `y`.
+fn supersample(
+    request: &RenderRequest,
+    proj: &dyn SphereProjection,
+    edge: EdgeMode,
+    x: u32,
+    y: u32,
+    samples: u32,
+) -> Vec4f {
+    let (img, sampler) = (&*request.image, request.sampler);
+    let sum: Vec4f = sample_points(x, y, samples)
+        .map(|p| {
+            let p = proj.proj(p);
+            sampler.sample_linear(img, p.x, p.y, edge, request.linear)
+        })
+        .sum();
+    sampler::encode(sum / samples.max(1) as f32)
+}
+
+/// Renders rows of `request` at `size` into `out` in bands, sending the
+/// number of finished pixels of each band to `progress`. The first row of
+/// `out` is row `first_row` of the projection. Source coordinates are looked
+/// up in `table` when there is one for `request`. Returns the time spent on
+/// the bands, summed over all threads.
+pub fn stereographic_projection(
+    request: &RenderRequest,
+    size: (u32, u32),
+    out: &mut Rgba32FImage,
+    first_row: u32,
+    table: Option<&RemapTable>,
+    cancel: &CancelToken,
+    progress: Option<&Sender<u64>>,
+) -> Result<Duration, RenderError> {
+    if size.0 == 0 || size.1 == 0 || out.width() == 0 {
+        return Err(RenderError::Empty);
+    }
+    let proj = request.projection(size);
+    let samples = request.samples_at(size);
+    let edge = request.edge_mode(&request.view(size));
+    let vignette = request.vignette.map(|vignette| {
+        let center = (vector![0.5, 0.5] - request.offset)
+            .component_mul(&vector![size.0 as f32, size.1 as f32]);
+        let unit = size.0.min(size.1) as f32 / 2.0;
+        (vignette, center, unit)
+    });
+    let graticule = request.graticule.map(|graticule| {
+        let source = request.image.dimensions();
+        let source = vector![source.0 as f32, source.1 as f32];
+        let output = vector![size.0 as f32, size.1 as f32];
+        let width = (size.0.min(size.1) as f32 / 540.0).max(1.0);
+        (graticule, source, output, width)
+    });
+    let width = out.width() as usize;
+    // Without supersampling, whole rows are projected at once.
+    let by_row = table.is_none() && samples <= 1;
+    let busy = AtomicU64::new(0);
+    out.par_chunks_mut(width * 4 * BAND_ROWS)
+        .enumerate()
+        .try_for_each(|(band, chunk)| {
+            let start = Instant::now();
+            let mut coords = vec![Vec2f::zeros(); if by_row { width } else { 0 }];
+            for (row, line) in chunk.chunks_exact_mut(width * 4).enumerate() {
+                cancel.check()?;
+                let y = first_row + (band * BAND_ROWS + row) as u32;
+                if by_row {
+                    proj.proj_row(y, &mut coords);
+                }
+                for (x, pixel) in line.chunks_exact_mut(4).enumerate() {
+                    let q = match table {
+                        _ if by_row => {
+                            let p = coords[x];
+                            let (image, linear) = (&request.image, request.linear);
+                            sampler::encode(
+                                request.sampler.sample_linear(image, p.x, p.y, edge, linear),
+                            )
+                        }
+                        Some(table) => table.sample(
+                            &request.image,
+                            request.sampler,
+                            edge,
+                            request.linear,
+                            x as u32,
+                            y,
+                        ),
+                        None => supersample(request, proj.as_ref(), edge, x as u32, y, samples),
+                    };
+                    let mut color = sampler::unpremultiply(q).0;
+                    if let Some(adjust) = &request.color {
+                        color = adjust.apply(color);
+                    }
+                    if let Some((vignette, center, unit)) = &vignette {
+                        let p = vector![x as f32 + 0.5, y as f32 + 0.5];
+                        color = vignette.apply(color, (p - center).norm() / unit);
+                    }
+                    if let Some((graticule, source, output, width)) = &graticule {
+                        // Inverse projections output the panorama itself.
+                        let degrees = |p: Vec2f| {
+                            let uv = if request.inverse {
+                                (p + vector![0.5, 0.5]).component_div(output)
+                            } else {
+                                (proj.proj(p) + vector![0.5, 0.5]).component_div(source)
+                            };
+                            Graticule::degrees(uv)
+                        };
+                        let p = vector![x as f32, y as f32];
+                        let at = degrees(p);
+                        if at.iter().all(|v| v.is_finite()) {
+                            let right = degrees(p + vector![1.0, 0.0]);
+                            let below = degrees(p + vector![0.0, 1.0]);
+                            let coverage = graticule.coverage(at, right, below, *width);
+                            color = graticule.apply(color, coverage);
+                        }
+                    }
+                    pixel.copy_from_slice(&color);
+                }
+            }
+            if let Some(progress) = progress {
+                progress.send((chunk.len() / 4) as u64).ok();
+            }
+            busy.fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+            Ok::<_, Canceled>(())
+        })?;
+    Ok(Duration::from_nanos(busy.into_inner()))
+}
+
+/// How long a render took, and how busy it kept the threads.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderStats {
+    pub size: (u32, u32),
+    /// Samples taken over all pixels.
+    pub samples: u64,
+    pub time: Duration,
+    /// Time spent rendering summed over all threads, unknown on the GPU.
+    pub busy: Option<Duration>,
+    pub threads: usize,
+}
+
+impl RenderStats {
+    pub fn new(request: &RenderRequest, size: (u32, u32), time: Duration) -> Self {
+        let pixels = size.0 as u64 * size.1 as u64;
+        Self {
+            size,
+            samples: pixels * request.samples_at(size) as u64,
+            time,
+            busy: None,
+            threads: 1,
+        }
+    }
+
+    pub fn samples_per_second(&self) -> f64 {
+        self.samples as f64 / self.time.as_secs_f64()
+    }
+
+    /// Fraction of the threads' time spent rendering.
+    pub fn utilization(&self) -> Option<f32> {
+        let total = self.time.as_secs_f32() * self.threads as f32;
+        Some(self.busy?.as_secs_f32() / total)
+    }
+}
+
+/// A source panorama, with one image per frame when it is animated.
+pub struct FrameSequence {
+    pub frames: Vec<(Arc<Rgba32FImage>, Delay)>,
+    pub metadata: Metadata,
+    /// The frames hold linear light, as decoded from HDR and OpenEXR files,
+    /// rather than sRGB.
+    pub linear: bool,
+}
+
+impl FrameSequence {
+    pub fn single(image: Arc<Rgba32FImage>) -> Self {
+        Self {
+            frames: vec![(image, Delay::from_numer_denom_ms(0, 1))],
+            metadata: Metadata::default(),
+            linear: false,
+        }
+    }
+
+    /// Decodes all frames of animated GIF, WebP and PNG files, and the only
+    /// frame of anything else, turned upright by its EXIF orientation and
+    /// converted into sRGB from its ICC profile. Partial panoramas with GPano
+    /// tags are placed in a full one.
+    pub fn open(path: &Path) -> ImageResult<Self> {
+        Self::load_from_memory(&fs::read(path)?)
+    }
+
+    pub fn load_from_memory(bytes: &[u8]) -> ImageResult<Self> {
+        let metadata = Metadata::read(bytes);
+        let mut sequence =
+            Self::read(image::io::Reader::new(Cursor::new(bytes)).with_guessed_format()?)?;
+        let orientation = metadata.orientation();
+        if orientation != 1 && !sequence.is_animated() {
+            let img = DynamicImage::ImageRgba32F((**sequence.first()).clone());
+            let img = metadata::orient(img, orientation).into_rgba32f();
+            sequence.frames = Self::single(Arc::new(img)).frames;
+        }
+        if let Some(gpano) = metadata.gpano().filter(|g| !g.is_full()) {
+            for (frame, _) in &mut sequence.frames {
+                *frame = Arc::new(gpano.expand(frame));
+            }
+        }
+        sequence.metadata = metadata;
+        Ok(sequence)
+    }
+
+    fn read<R: BufRead + Seek>(reader: image::io::Reader<R>) -> ImageResult<Self> {
+        let (frames, profile) = match reader.format() {
+            Some(ImageFormat::Gif) => (GifDecoder::new(reader.into_inner())?.into_frames(), None),
+            Some(ImageFormat::WebP) => {
+                let mut decoder = WebPDecoder::new(reader.into_inner())?;
+                if !decoder.has_animation() {
+                    return Self::decode(decoder);
+                }
+                let profile = decoder.icc_profile();
+                (decoder.into_frames(), profile)
+            }
+            Some(ImageFormat::Png) => {
+                let mut decoder = PngDecoder::new(reader.into_inner())?;
+                if !decoder.is_apng() {
+                    return Self::decode(decoder);
+                }
+                let profile = decoder.icc_profile();
+                (decoder.apng().into_frames(), profile)
+            }
+            Some(ImageFormat::Jpeg) => return Self::decode(JpegDecoder::new(reader.into_inner())?),
+            Some(ImageFormat::Tiff) => return Self::decode(TiffDecoder::new(reader.into_inner())?),
+            format => {
+                let linear = matches!(format, Some(ImageFormat::Hdr | ImageFormat::OpenExr));
+                let img = reader.decode()?.into_rgba32f();
+                return Ok(Self {
+                    linear,
+                    ..Self::single(Arc::new(img))
+                });
+            }
+        };
+        let profile = source_profile(profile);
+        let frames = frames
+            .map(|frame| {
+                let frame = frame?;
+                let delay = frame.delay();
+                let mut img = DynamicImage::ImageRgba8(frame.into_buffer()).into_rgba32f();
+                if let Some(profile) = &profile {
+                    profile.to_srgb(&mut img);
+                }
+                Ok((Arc::new(img), delay))
+            })
+            .collect::<ImageResult<Vec<_>>>()?;
+        if frames.is_empty() {
+            return Err(ImageError::Decoding(DecodingError::new(
+                ImageFormatHint::Unknown,
+                "animation has no frames",
+            )));
+        }
+        Ok(Self {
+            frames,
+            metadata: Metadata::default(),
+            linear: false,
+        })
+    }
+
+    /// Decodes the only frame of `decoder`, converted into sRGB from the
+    /// profile embedded in it.
+    fn decode<'a>(mut decoder: impl ImageDecoder<'a>) -> ImageResult<Self> {
+        let profile = source_profile(decoder.icc_profile());
+        let mut img = DynamicImage::from_decoder(decoder)?.into_rgba32f();
+        if let Some(profile) = profile {
+            profile.to_srgb(&mut img);
+        }
+        Ok(Self::single(Arc::new(img)))
+    }
+
+    pub fn first(&self) -> &Arc<Rgba32FImage> {
+        &self.frames[0].0
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.frames.len() > 1
+    }
+}
+
+/// The profile to convert a source from, unless it has none or one that is
+/// sRGB or not understood, all of which are read as sRGB.
+fn source_profile(icc: Option<Vec<u8>>) -> Option<Profile> {
+    Profile::parse(&icc?).filter(|profile| !profile.is_srgb())
+}
+
+/// Everything needed to render one output image.
+#[derive(Clone)]
+pub struct RenderRequest {
+    pub image: Arc<Rgba32FImage>,
+    /// Set by the app when `image` holds linear light rather than sRGB.
+    pub linear: bool,
+    pub kind: ProjectionKind,
+    /// Set by the app for custom projections, which replace `kind`.
+    pub script: Option<Arc<Script>>,
+    pub inverse: bool,
+    pub offset: Vec2f,
+    pub rotation: Rotation3<f32>,
+    pub scale: f32,
+    pub size: (u32, u32),
+    pub sampler: Sampler,
+    pub edge: EdgeMode,
+    /// Field of view of the source in degrees.
+    pub fov: (f32, f32),
+    /// Only the adjustments after projection; the others are already applied
+    /// to `image`.
+    pub color: Option<ColorAdjust>,
+    /// Left out for inverse projections, which have no planet.
+    pub vignette: Option<Vignette>,
+    /// Only set by [`RenderRequest::new`] for lines that are saved too; the
+    /// preview sets it for preview-only lines itself.
+    pub graticule: Option<Graticule>,
+    /// Set by the app, stamped over the rows as they are rendered.
+    pub overlay: Option<Arc<Overlay>>,
+    /// Samples per pixel of the full resolution pass.
+    pub samples: u32,
+    /// Set by exports whose frames share the projection and only differ in
+    /// their source image.
+    pub remap: Option<Arc<RemapCache>>,
+    /// Set by the app to sample a smaller copy of `image`, picked by
+    /// `downscale`.
+    pub mipmap: Option<Arc<Mipmap>>,
+    pub downscale: Downscale,
+}
+
+impl RenderRequest {
+    pub fn new(image: Arc<Rgba32FImage>, params: &Params, sampler: Sampler, samples: u32) -> Self {
+        let adjust = params.color;
+        let adjust_before = adjust.stage == Stage::BeforeProjection && !adjust.is_identity();
+        // Only panoramas have poles; inverse projections read a planet.
+        let pole = params.pole.enabled && !params.inverse;
+        let image = if adjust_before || pole {
+            let mut image = (*image).clone();
+            if adjust_before {
+                adjust.apply_image(&mut image);
+            }
+            if pole {
+                params.pole.apply_image(&mut image, params.fov.1);
+            }
+            Arc::new(image)
+        } else {
+            image
+        };
+        Self {
+            image,
+            linear: false,
+            kind: params.kind,
+            script: None,
+            inverse: params.inverse,
+            offset: vector![params.offset.0, params.offset.1],
+            rotation: params.view_rotation(),
+            scale: params.scale,
+            size: params.size,
+            sampler,
+            edge: params.edge,
+            fov: params.fov,
+            color: (adjust.stage == Stage::AfterProjection && !adjust.is_identity())
+                .then_some(adjust),
+            vignette: (params.vignette.strength > 0.0 && !params.inverse)
+                .then_some(params.vignette),
+            graticule: (params.graticule.enabled && !params.graticule.preview_only)
+                .then_some(params.graticule),
+            overlay: None,
+            samples,
+            remap: None,
+            mipmap: None,
+            downscale: Downscale::Full,
+        }
+    }
+
+    /// The request with the copy of the source picked for rendering at
+    /// `size`, or `None` when that is the source itself.
+    pub fn downscaled(&self, size: (u32, u32)) -> Option<RenderRequest> {
+        let mipmap = self.mipmap.as_ref()?;
+        let longer = size.0.max(size.1);
+        let image = mipmap.get(self.downscale, longer, self.samples_at(size));
+        (!Arc::ptr_eq(image, &self.image)).then(|| RenderRequest {
+            image: Arc::clone(image),
+            mipmap: None,
+            ..self.clone()
+        })
+    }
+
+    /// Samples per pixel when rendering at `size`; the coarse passes take one.
+    pub fn samples_at(&self, size: (u32, u32)) -> u32 {
+        if size == self.size {
+            self.samples
+        } else {
+            1
+        }
+    }
+
+    pub fn view(&self, size: (u32, u32)) -> View {
+        let img_size = vector![self.image.width(), self.image.height()];
+        let proj_size = vector![size.0, size.1];
+        if self.inverse {
+            View::new(
+                proj_size,
+                img_size,
+                self.offset,
+                self.rotation,
+                self.scale,
+                self.fov,
+            )
+        } else {
+            View::new(
+                img_size,
+                proj_size,
+                self.offset,
+                self.rotation,
+                self.scale,
+                self.fov,
+            )
+        }
+    }
+
+    /// What fills the areas off the source. Only full panoramas wrap around;
+    /// the rest of the sphere around partial ones, and the area around an
+    /// unwrapped planet, are left transparent instead.
+    pub fn edge_mode(&self, view: &View) -> EdgeMode {
+        match self.edge {
+            _ if self.inverse => EdgeMode::Transparent,
+            EdgeMode::Wrap if !view.is_full() => EdgeMode::Transparent,
+            edge => edge,
+        }
+    }
+
+    pub fn projection(&self, size: (u32, u32)) -> Box<dyn SphereProjection> {
+        let view = self.view(size);
+        if self.inverse {
+            Box::new(InverseProjection::new(view))
+        } else if let Some(script) = &self.script {
+            Box::new(Scripted::new(view, Arc::clone(script)))
+        } else {
+            self.kind.build(view)
+        }
+    }
+
+    pub fn render(
+        &self,
+        size: (u32, u32),
+        cancel: &CancelToken,
+        progress: Option<&Sender<u64>>,
+    ) -> Result<Rgba32FImage, RenderError> {
+        Ok(self.render_timed(size, cancel, progress)?.0)
+    }
+
+    /// [`RenderRequest::render`], also returning how it went.
+    pub fn render_timed(
+        &self,
+        size: (u32, u32),
+        cancel: &CancelToken,
+        progress: Option<&Sender<u64>>,
+    ) -> Result<(Rgba32FImage, RenderStats), RenderError> {
+        let start = Instant::now();
+        let (out, busy) = self.render_part(size, 0..size.1, cancel, progress)?;
+        let stats = RenderStats {
+            busy: Some(busy),
+            threads: rayon::current_num_threads(),
+            ..RenderStats::new(self, size, start.elapsed())
+        };
+        Ok((out, stats))
+    }
+
+    /// Renders only `rows` of the output at `size`.
+    pub fn render_rows(
+        &self,
+        size: (u32, u32),
+        rows: Range<u32>,
+        cancel: &CancelToken,
+        progress: Option<&Sender<u64>>,
+    ) -> Result<Rgba32FImage, RenderError> {
+        Ok(self.render_part(size, rows, cancel, progress)?.0)
+    }
+
+    fn render_part(
+        &self,
+        size: (u32, u32),
+        rows: Range<u32>,
+        cancel: &CancelToken,
+        progress: Option<&Sender<u64>>,
+    ) -> Result<(Rgba32FImage, Duration), RenderError> {
+        if let Some(request) = self.downscaled(size) {
+            return request.render_part(size, rows, cancel, progress);
+        }
+        let mut out = Rgba32FImage::new(size.0, rows.len() as u32);
+        // Tables are only kept for whole images, not for the tiles of huge ones.
+        let table = (self.remap.as_ref())
+            .filter(|_| rows == (0..size.1))
+            .and_then(|cache| cache.get(self, size));
+        let table = table.as_deref();
+        let busy =
+            stereographic_projection(self, size, &mut out, rows.start, table, cancel, progress)?;
+        if let Some(overlay) = &self.overlay {
+            overlay.composite(&mut out, size, rows.start);
+        }
+        Ok((out, busy))
+    }
+
+    /// Whether the output is too large to be rendered in one piece.
+    pub fn is_tiled(&self) -> bool {
+        self.size.0.max(self.size.1) > MAX_PREVIEW_SIZE
+    }
+
+    /// Sizes of the progressive passes, ending with the full resolution, or
+    /// with the largest preview for tiled outputs.
+    fn passes(&self) -> Vec<(u32, u32)> {
+        let (width, height) = self.size;
+        let longer = width.max(height);
+        let scaled = |s: u32| {
+            let k = s as f32 / longer as f32;
+            let w = (width as f32 * k).round().max(1.0) as u32;
+            let h = (height as f32 * k).round().max(1.0) as u32;
+            (w, h)
+        };
+        let mut passes: Vec<_> = PREVIEW_SIZES
+            .into_iter()
+            .filter(|&s| s < longer)
+            .map(scaled)
+            .collect();
+        passes.push(if self.is_tiled() {
+            scaled(MAX_PREVIEW_SIZE)
+        } else {
+            self.size
+        });
+        passes
+    }
+}
+
+/// Owns the rendered output and the background render jobs.
+///
+/// Every submitted job bumps the generation counter, which cancels all
+/// older jobs and keeps their results from being published.
+pub struct Renderer {
+    out_image: Arc<RwLock<Option<Arc<Rgba32FImage>>>>,
+    out_tex: Arc<RwLock<Option<SizedTexture>>>,
+    stats: Arc<RwLock<Option<RenderStats>>>,
+    /// The histogram of the last render, and where it clips.
+    pub analysis: Arc<Analysis>,
+    generation: Arc<AtomicU64>,
+    job: Option<Job>,
+}
+
+struct Job {
+    handle: JoinHandle<()>,
+    progress: Receiver<u64>,
+    done: u64,
+    total: u64,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Renderer {
+    pub fn new() -> Self {
+        Self {
+            out_image: Arc::new(RwLock::new(None)),
+            out_tex: Arc::new(RwLock::new(None)),
+            stats: Arc::new(RwLock::new(None)),
+            analysis: Arc::new(Analysis::default()),
+            generation: Arc::new(AtomicU64::new(0)),
+            job: None,
+        }
+    }
+
+    pub fn processing(&self) -> bool {
+        self.job
+            .as_ref()
+            .is_some_and(|job| !job.handle.is_finished())
+    }
+
+    /// Fraction of the pixels of all passes rendered so far by the current job.
+    pub fn progress(&mut self) -> Option<f32> {
+        let job = self.job.as_mut()?;
+        job.done += job.progress.try_iter().sum::<u64>();
+        Some(job.done as f32 / job.total as f32)
+    }
+
+    pub fn texture(&self) -> Option<SizedTexture> {
+        *self.out_tex.read()
+    }
+
+    pub fn image(&self) -> Option<Arc<Rgba32FImage>> {
+        self.out_image.read().clone()
+    }
+
+    /// How the last published pass went.
+    pub fn stats(&self) -> Option<RenderStats> {
+        *self.stats.read()
+    }
+
+    /// Bytes held by the full resolution output kept for saving.
+    pub fn image_bytes(&self) -> usize {
+        self.out_image
+            .read()
+            .as_ref()
+            .map_or(0, |img| img.as_raw().len() * std::mem::size_of::<f32>())
+    }
+
+    /// Renders `request` progressively on a background thread, aborting any
+    /// job still running for a previous request.
+    pub fn submit(&mut self, request: RenderRequest, ctx: &Context) {
+        let cancel = CancelToken::next(&self.generation);
+        if request.is_tiled() {
+            // There won't be a full resolution image to save.
+            self.out_image.write().take();
+        }
+        let out_image = Arc::clone(&self.out_image);
+        let out_tex = Arc::clone(&self.out_tex);
+        let stats = Arc::clone(&self.stats);
+        let analysis = Arc::clone(&self.analysis);
+        let ctx = ctx.clone();
+        let passes = request.passes();
+        let total = passes.iter().map(|&(w, h)| w as u64 * h as u64).sum();
+        let (sender, progress) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let last = passes.last().copied();
+            for size in passes {
+                let Ok((out, pass)) = request.render_timed(size, &cancel, Some(&sender)) else {
+                    return;
+                };
+                if Some(size) == last && analysis.update(&ctx, &out, request.size, &cancel).is_err()
+                {
+                    return;
+                }
+                let out_image = (size == request.size).then_some(&*out_image);
+                if publish(&ctx, out, request.size, &out_tex, out_image, &cancel).is_err() {
+                    return;
+                }
+                stats.write().replace(pass);
+            }
+        });
+        self.job = Some(Job {
+            handle,
+            progress,
+            done: 0,
+            total,
+        });
+    }
+
+    /// Stops the current job and frees the texture, for a renderer that
+    /// is done with.
+    pub fn free(&mut self, ctx: &Context) {
+        CancelToken::next(&self.generation);
+        self.job = None;
+        self.out_image.write().take();
+        if let Some(tex) = self.out_tex.write().take() {
+            ctx.tex_manager().write().free(tex.id);
+        }
+        self.analysis.free(ctx);
+    }
+
+    /// Shows an image rendered outside of the background thread.
+    pub fn publish(&mut self, ctx: &Context, out: Rgba32FImage, stats: RenderStats) {
+        let cancel = CancelToken::next(&self.generation);
+        let size = (out.width(), out.height());
+        let out_image = Some(&*self.out_image);
+        if publish(ctx, out, size, &self.out_tex, out_image, &cancel).is_err() {
+            return;
+        }
+        self.stats.write().replace(stats);
+        if let Some(out) = self.image() {
+            let analysis = Arc::clone(&self.analysis);
+            let ctx = ctx.clone();
+            thread::spawn(move || analysis.update(&ctx, &out, size, &cancel));
+        }
+    }
+}
+
+/// The number of textures egui holds, and the bytes they take.
+pub fn texture_memory(ctx: &Context) -> (usize, usize) {
+    let manager = ctx.tex_manager();
+    let manager = manager.read();
+    let bytes = manager.allocated().map(|(_, meta)| meta.bytes_used()).sum();
+    (manager.num_allocated(), bytes)
+}
+
+/// Uploads `out` as the preview texture, displayed at `display_size` so that
+/// coarse passes take the same space as the final image. Full resolution
+/// results are also stored into `out_image` for saving.
+fn publish(
+    ctx: &Context,
+    out: Rgba32FImage,
+    display_size: (u32, u32),
+    out_tex: &RwLock<Option<SizedTexture>>,
+    out_image: Option<&RwLock<Option<Arc<Rgba32FImage>>>>,
+    cancel: &CancelToken,
+) -> Result<(), Canceled> {
+    let size = [out.width() as usize, out.height() as usize];
+    let preview: RgbaImage = out.convert();
+    let image = ColorImage::from_rgba_unmultiplied(size, preview.as_raw());
+    let mut out_tex = out_tex.write();
+    cancel.check()?;
+    // The one texture is overwritten by every pass, instead of allocating a
+    // new one that would never be freed.
+    let manager = ctx.tex_manager();
+    let mut manager = manager.write();
+    let id = match *out_tex {
+        Some(tex) => {
+            manager.set(tex.id, ImageDelta::full(image, Default::default()));
+            tex.id
+        }
+        None => manager.alloc("out".into(), image.into(), Default::default()),
+    };
+    drop(manager);
+    out_tex.replace(SizedTexture::new(
+        id,
+        [display_size.0 as f32, display_size.1 as f32],
+    ));
+    if let Some(out_image) = out_image {
+        out_image.write().replace(Arc::new(out));
+    }
+    ctx.request_repaint();
+    Ok(())
+}
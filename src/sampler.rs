@@ -0,0 +1,262 @@
+use std::f32::consts::PI;
+
+use image::Rgba32FImage;
+
+use crate::icc;
+
+type Vec4f = nalgebra::SVector<f32, 4>;
+
+/// What is sampled outside of the source image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeMode {
+    /// The image is an equirectangular panorama: `x` wraps around and `y`
+    /// is reflected across the poles onto the opposite meridian.
+    Wrap,
+    /// The image is reflected at every edge.
+    Mirror,
+    /// A straight-alpha color.
+    Color([f32; 4]),
+    Transparent,
+}
+
+impl EdgeMode {
+    pub const ALL: [EdgeMode; 4] = [
+        EdgeMode::Wrap,
+        EdgeMode::Mirror,
+        EdgeMode::Color([0.0, 0.0, 0.0, 1.0]),
+        EdgeMode::Transparent,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EdgeMode::Wrap => "Wrap",
+            EdgeMode::Mirror => "Mirror",
+            EdgeMode::Color(_) => "Solid Color",
+            EdgeMode::Transparent => "Transparent",
+        }
+    }
+
+    /// The mode named `name`, with `color` for [`EdgeMode::Color`].
+    pub fn from_name(name: &str, color: [f32; 4]) -> Option<Self> {
+        let mode = EdgeMode::ALL.into_iter().find(|x| x.name() == name)?;
+        Some(match mode {
+            EdgeMode::Color(_) => EdgeMode::Color(color),
+            mode => mode,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sampler {
+    Nearest,
+    Bilinear,
+    Bicubic,
+    Lanczos3,
+}
+
+impl Sampler {
+    pub const ALL: [Sampler; 4] = [
+        Sampler::Nearest,
+        Sampler::Bilinear,
+        Sampler::Bicubic,
+        Sampler::Lanczos3,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Sampler::Nearest => "Nearest",
+            Sampler::Bilinear => "Bilinear",
+            Sampler::Bicubic => "Bicubic",
+            Sampler::Lanczos3 => "Lanczos3",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Sampler::ALL.into_iter().find(|x| x.name() == name)
+    }
+
+    /// Samples `img` at `(x, y)` as a premultiplied RGBA color, filling
+    /// whatever is off the image according to `edge`. Points outside of the
+    /// projection's domain are always transparent.
+    pub fn sample(self, img: &Rgba32FImage, x: f32, y: f32, edge: EdgeMode) -> Vec4f {
+        encode(self.sample_linear(img, x, y, edge, false))
+    }
+
+    /// [`Sampler::sample`] in linear light, for averaging several samples
+    /// before they are encoded with [`encode`]. `linear` images, decoded from
+    /// HDR and OpenEXR files, are blended as they are instead of being
+    /// decoded from sRGB.
+    pub fn sample_linear(
+        self,
+        img: &Rgba32FImage,
+        x: f32,
+        y: f32,
+        edge: EdgeMode,
+        linear: bool,
+    ) -> Vec4f {
+        if let Some(color) = edge_color(img, x, y, edge) {
+            return color;
+        }
+        let src = Source { img, edge, linear };
+        match self {
+            Sampler::Nearest => nearest(src, x, y),
+            Sampler::Bilinear => bilinear_interpolation(src, x, y),
+            Sampler::Bicubic => convolution(src, x, y, 2, catmull_rom),
+            Sampler::Lanczos3 => convolution(src, x, y, 3, lanczos3),
+        }
+    }
+}
+
+/// The color of points that are not sampled from `img`: those outside of the
+/// projection's domain, and those off the image for `edge` modes that fill
+/// it.
+fn edge_color(img: &Rgba32FImage, x: f32, y: f32, edge: EdgeMode) -> Option<Vec4f> {
+    if !(x.is_finite() && y.is_finite()) {
+        return Some(Vec4f::zeros());
+    }
+    let (width, height) = img.dimensions();
+    let outside = x < -0.5 || y < -0.5 || x > width as f32 - 0.5 || y > height as f32 - 0.5;
+    match edge {
+        EdgeMode::Transparent if outside => Some(Vec4f::zeros()),
+        EdgeMode::Color(c) if outside => Some(premultiply(c, false)),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Source<'a> {
+    img: &'a Rgba32FImage,
+    edge: EdgeMode,
+    linear: bool,
+}
+
+fn fetch(src: Source, x: i64, y: i64) -> Vec4f {
+    let (width, height) = src.img.dimensions();
+    let (width, height) = (width as i64, height as i64);
+    let mirror = |i: i64, n: i64| {
+        let i = i.rem_euclid(2 * n);
+        if i < n {
+            i
+        } else {
+            2 * n - 1 - i
+        }
+    };
+    let (x, y) = match src.edge {
+        EdgeMode::Wrap => {
+            let (x, y) = if y < 0 {
+                (x + width / 2, -1 - y)
+            } else if y >= height {
+                (x + width / 2, 2 * height - 1 - y)
+            } else {
+                (x, y)
+            };
+            (x.rem_euclid(width), y.clamp(0, height - 1))
+        }
+        EdgeMode::Mirror => (mirror(x, width), mirror(y, height)),
+        // Filters reaching past the border still see the edge pixels.
+        EdgeMode::Color(_) | EdgeMode::Transparent => {
+            (x.clamp(0, width - 1), y.clamp(0, height - 1))
+        }
+    };
+    premultiply(src.img.get_pixel(x as u32, y as u32).0, src.linear)
+}
+
+/// Decodes a straight-alpha sRGB color into a premultiplied one in linear
+/// light, in which samples are blended so that edges between light and dark
+/// areas don't darken. `linear` colors are only premultiplied.
+fn premultiply(c: [f32; 4], linear: bool) -> Vec4f {
+    let a = c[3];
+    let [r, g, b] = if linear {
+        [c[0], c[1], c[2]]
+    } else {
+        [c[0], c[1], c[2]].map(icc::to_linear)
+    };
+    Vec4f::new(r * a, g * a, b * a, a)
+}
+
+/// Encodes a premultiplied sample in linear light into sRGB, still
+/// premultiplied.
+pub fn encode(q: Vec4f) -> Vec4f {
+    icc::encode_premultiplied(q.into()).into()
+}
+
+/// Converts a premultiplied color back to a straight-alpha pixel.
+pub fn unpremultiply(q: Vec4f) -> image::Rgba<f32> {
+    let a = q.w.clamp(0.0, 1.0);
+    let k = if a > 0.0 { 1.0 / a } else { 0.0 };
+    let q = Vec4f::new(q.x * k, q.y * k, q.z * k, a);
+    image::Rgba(q.map(|c| c.max(0.0)).into())
+}
+
+fn nearest(src: Source, x: f32, y: f32) -> Vec4f {
+    fetch(src, x.round() as i64, y.round() as i64)
+}
+
+fn bilinear_interpolation(src: Source, x: f32, y: f32) -> Vec4f {
+    let (x1, y1) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x1, y - y1);
+    let (x1, y1) = (x1 as i64, y1 as i64);
+    let q11 = fetch(src, x1, y1);
+    let q21 = fetch(src, x1 + 1, y1);
+    let q12 = fetch(src, x1, y1 + 1);
+    let q22 = fetch(src, x1 + 1, y1 + 1);
+
+    let r1 = q11.lerp(&q21, fx);
+    let r2 = q12.lerp(&q22, fx);
+    r1.lerp(&r2, fy)
+}
+
+fn catmull_rom(x: f32) -> f32 {
+    let x = x.abs();
+    if x < 1.0 {
+        1.5 * x.powi(3) - 2.5 * x.powi(2) + 1.0
+    } else if x < 2.0 {
+        -0.5 * x.powi(3) + 2.5 * x.powi(2) - 4.0 * x + 2.0
+    } else {
+        0.0
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn lanczos3(x: f32) -> f32 {
+    if x.abs() < 3.0 {
+        sinc(x) * sinc(x / 3.0)
+    } else {
+        0.0
+    }
+}
+
+/// Largest filter radius supported by [`convolution`].
+const MAX_RADIUS: usize = 3;
+
+/// Separable filter with a support of `radius` pixels on each side.
+fn convolution(src: Source, x: f32, y: f32, radius: usize, kernel: fn(f32) -> f32) -> Vec4f {
+    let radius = radius.min(MAX_RADIUS);
+    let taps = 2 * radius;
+    let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+    let first = 1 - radius as i64;
+    let mut wx = [0.0; 2 * MAX_RADIUS];
+    let mut wy = [0.0; 2 * MAX_RADIUS];
+    for k in 0..taps {
+        wx[k] = kernel(x - (x0 + first + k as i64) as f32);
+        wy[k] = kernel(y - (y0 + first + k as i64) as f32);
+    }
+
+    let mut sum = Vec4f::zeros();
+    let mut total = 0.0;
+    for (j, wy) in wy[..taps].iter().enumerate() {
+        for (i, wx) in wx[..taps].iter().enumerate() {
+            let w = wx * wy;
+            sum += fetch(src, x0 + first + i as i64, y0 + first + j as i64) * w;
+            total += w;
+        }
+    }
+    sum / total
+}
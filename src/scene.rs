@@ -0,0 +1,161 @@
+//! Everything besides the parameters that goes into a render, so that the
+//! preview and every export build the same request.
+
+use std::sync::Arc;
+
+use image::Rgba32FImage;
+
+use crate::{
+    mipmap::{Downscale, MipmapCache},
+    overlay::Overlay,
+    preset::Params,
+    render::RenderRequest,
+    sampler::Sampler,
+    script::CustomProjection,
+    stereo::Stereo,
+    sticker::Layers,
+};
+
+/// The settings and layers of the composition, with the caches of the
+/// images made from the source. Background exports take a clone of it.
+#[derive(Clone)]
+pub struct Scene {
+    /// The source holds linear light rather than sRGB.
+    pub linear: bool,
+    pub sampler: Sampler,
+    /// Samples per pixel of full resolution renders.
+    pub samples: u32,
+    pub downscale: Downscale,
+    pub custom: CustomProjection,
+    pub overlay: Overlay,
+    pub layers: Layers,
+    pub stereo: Stereo,
+    pub mipmaps: MipmapCache,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            linear: false,
+            sampler: Sampler::Bilinear,
+            samples: 1,
+            downscale: Downscale::Auto,
+            custom: CustomProjection::new(""),
+            overlay: Overlay::default(),
+            layers: Layers::default(),
+            stereo: Stereo::default(),
+            mipmaps: MipmapCache::default(),
+        }
+    }
+}
+
+impl Scene {
+    /// Renders `params` from the chosen eye of `image`, with the stickers
+    /// drawn into it and the overlay stamped over the output.
+    pub fn request(&mut self, image: &Arc<Rgba32FImage>, params: &Params) -> RenderRequest {
+        let eye = self.stereo.eye(image);
+        self.eye_request(&eye, params)
+    }
+
+    /// The requests of both eyes of `image`, when both are saved.
+    pub fn pair_requests(
+        &mut self,
+        image: &Arc<Rgba32FImage>,
+        params: &Params,
+    ) -> Option<[RenderRequest; 2]> {
+        if !self.stereo.is_pair() {
+            return None;
+        }
+        let eyes = self.stereo.eyes(image)?;
+        Some(eyes.map(|eye| self.eye_request(&eye, params)))
+    }
+
+    fn eye_request(&mut self, eye: &Arc<Rgba32FImage>, params: &Params) -> RenderRequest {
+        let source = self.layers.apply(eye, self.linear);
+        let mut request = RenderRequest::new(source, params, self.sampler, self.samples);
+        request.linear = self.linear;
+        request.overlay = self.overlay.enabled.then(|| Arc::new(self.overlay.clone()));
+        request.script = self.custom.script();
+        self.mipmaps.apply(&mut request, self.downscale);
+        request
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use std::sync::atomic::AtomicU64;
+
+    use super::*;
+    use crate::{
+        export::{self, ExportOptions},
+        icc,
+        render::{CancelToken, FrameSequence, RenderError},
+        stereo::{Eye, StereoLayout},
+    };
+
+    #[test]
+    fn requests_take_the_eye_overlay_and_script() {
+        let mut img = Rgba32FImage::from_pixel(8, 8, Rgba([1.0, 0.0, 0.0, 1.0]));
+        for y in 4..8 {
+            for x in 0..8 {
+                img.put_pixel(x, y, Rgba([0.0, 0.0, 1.0, 1.0]));
+            }
+        }
+        let img = Arc::new(img);
+        let mut scene = Scene {
+            custom: CustomProjection::new("lon = x\nlat = y"),
+            downscale: Downscale::Full,
+            ..Scene::default()
+        };
+        (scene.custom.enabled, scene.overlay.enabled) = (true, true);
+        (scene.stereo.layout, scene.stereo.eye) = (StereoLayout::OverUnder, Eye::Right);
+
+        let request = scene.request(&img, &Params::default());
+        assert_eq!(request.image.dimensions(), (8, 4));
+        assert_eq!(request.image.get_pixel(0, 0).0, [0.0, 0.0, 1.0, 1.0]);
+        assert!(request.overlay.is_some() && request.script.is_some());
+
+        assert!(scene.pair_requests(&img, &Params::default()).is_none());
+        scene.stereo.pair = true;
+        let [left, right] = scene.pair_requests(&img, &Params::default()).unwrap();
+        assert_eq!(left.image.get_pixel(0, 0).0, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(right.image.get_pixel(0, 0).0, [0.0, 0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn linear_sources_are_decoded_once() {
+        let mut params = Params::default();
+        (params.size, params.vignette.strength) = ((16, 16), 0.0);
+        let cancel = CancelToken::next(&Arc::new(AtomicU64::new(0)));
+        let gray = Arc::new(Rgba32FImage::from_pixel(
+            32,
+            16,
+            Rgba([0.25, 0.25, 0.25, 1.0]),
+        ));
+        let center = |scene: &mut Scene| {
+            let out = scene
+                .request(&gray, &params)
+                .render(params.size, &cancel, None);
+            out.unwrap().get_pixel(8, 8).0[0]
+        };
+        let mut scene = Scene::default();
+        assert!((center(&mut scene) - 0.25).abs() < 1e-3);
+        let empty = scene.request(&gray, &params).render((0, 16), &cancel, None);
+        assert_eq!(empty.err(), Some(RenderError::Empty));
+        scene.linear = true;
+        let encoded = icc::linear_to_srgb(0.25);
+        assert!((center(&mut scene) - encoded).abs() < 1e-3);
+
+        // OpenEXR files hold linear light both ways.
+        let path = std::env::temp_dir().join(format!("shuodedaoli-{}.exr", std::process::id()));
+        let out = Rgba32FImage::from_pixel(2, 2, Rgba([encoded, encoded, encoded, 1.0]));
+        export::save(&out, &path, &ExportOptions::default()).unwrap();
+        let seq = FrameSequence::open(&path);
+        std::fs::remove_file(&path).ok();
+        let seq = seq.unwrap();
+        assert!(seq.linear);
+        assert!((seq.first().get_pixel(0, 0).0[0] - 0.25).abs() < 1e-4);
+    }
+}
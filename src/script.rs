@@ -0,0 +1,523 @@
+//! A small expression language for custom projections, compiled once per
+//! render into a program that is run for every sample.
+//!
+//! A script is a list of assignments, one per line or separated by `;`, with
+//! `#` starting a comment. It reads the output point and assigns either
+//! `lon` and `lat`, the direction in radians with the center of the view at
+//! 0, or `u` and `v`, a point of the source normalized to `[0, 1]`.
+
+use std::{fmt, sync::Arc};
+
+use crate::toml::{Table, Value};
+
+/// Variables set before the script runs: the point on the projection plane
+/// in units of the planet's radius, the output pixel, and the output size.
+pub const INPUTS: [&str; 6] = ["x", "y", "px", "py", "w", "h"];
+
+/// The Mercator projection, written as a script.
+pub const EXAMPLE: &str = "lon = x\nlat = atan(sinh(y))";
+
+/// Most variables of a script, inputs included.
+const MAX_VARS: usize = 32;
+
+/// Most values pending at once while evaluating an expression.
+const MAX_STACK: usize = 32;
+
+const CONSTANTS: [(&str, f32); 3] = [
+    ("pi", std::f32::consts::PI),
+    ("tau", std::f32::consts::TAU),
+    ("e", std::f32::consts::E),
+];
+
+type Function1 = fn(f32) -> f32;
+type Function2 = fn(f32, f32) -> f32;
+
+const FUNCTIONS_1: [(&str, Function1); 19] = [
+    ("sin", f32::sin),
+    ("cos", f32::cos),
+    ("tan", f32::tan),
+    ("asin", f32::asin),
+    ("acos", f32::acos),
+    ("atan", f32::atan),
+    ("sinh", f32::sinh),
+    ("cosh", f32::cosh),
+    ("tanh", f32::tanh),
+    ("asinh", f32::asinh),
+    ("acosh", f32::acosh),
+    ("atanh", f32::atanh),
+    ("sqrt", f32::sqrt),
+    ("abs", f32::abs),
+    ("exp", f32::exp),
+    ("ln", f32::ln),
+    ("floor", f32::floor),
+    ("ceil", f32::ceil),
+    ("sign", f32::signum),
+];
+
+const FUNCTIONS_2: [(&str, Function2); 5] = [
+    ("atan2", f32::atan2),
+    ("pow", f32::powf),
+    ("min", f32::min),
+    ("max", f32::max),
+    ("hypot", f32::hypot),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// What the outputs of a script are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// Longitude and latitude in radians.
+    Direction,
+    /// Normalized source coordinates.
+    Source,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Const(f32),
+    Load(usize),
+    Store(usize),
+    Neg,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Less,
+    Greater,
+    LessEq,
+    GreaterEq,
+    Call1(Function1),
+    Call2(Function2),
+    /// `if(c, a, b)`, with all three evaluated.
+    Select,
+}
+
+/// A compiled script.
+#[derive(Debug, Clone)]
+pub struct Script {
+    source: String,
+    ops: Vec<Op>,
+    target: Target,
+    outputs: [usize; 2],
+}
+
+impl Script {
+    pub fn compile(source: &str) -> Result<Self, ScriptError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+            vars: INPUTS.map(str::to_owned).to_vec(),
+            ops: Vec::new(),
+            depth: 0,
+        };
+        parser.program()?;
+        let slot = |name| parser.vars.iter().position(|v| v == name);
+        let direction = [slot("lon"), slot("lat")];
+        let source_point = [slot("u"), slot("v")];
+        let (target, outputs) = match (direction, source_point) {
+            ([Some(lon), Some(lat)], [None, None]) => (Target::Direction, [lon, lat]),
+            ([None, None], [Some(u), Some(v)]) => (Target::Source, [u, v]),
+            _ => {
+                return Err(ScriptError {
+                    line: parser.line(),
+                    message: "assign either `lon` and `lat` or `u` and `v`".to_owned(),
+                })
+            }
+        };
+        Ok(Self {
+            source: source.to_owned(),
+            ops: parser.ops,
+            target,
+            outputs,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn target(&self) -> Target {
+        self.target
+    }
+
+    /// Runs the script on the values of [`INPUTS`], returning its two
+    /// outputs. Variables left unassigned are `NaN`.
+    pub fn run(&self, inputs: [f32; INPUTS.len()]) -> [f32; 2] {
+        let mut vars = [f32::NAN; MAX_VARS];
+        vars[..INPUTS.len()].copy_from_slice(&inputs);
+        let mut stack = [0.0; MAX_STACK];
+        let mut top = 0;
+        for op in &self.ops {
+            match *op {
+                Op::Const(c) => {
+                    stack[top] = c;
+                    top += 1;
+                }
+                Op::Load(i) => {
+                    stack[top] = vars[i];
+                    top += 1;
+                }
+                Op::Store(i) => {
+                    top -= 1;
+                    vars[i] = stack[top];
+                }
+                Op::Neg => stack[top - 1] = -stack[top - 1],
+                Op::Call1(f) => stack[top - 1] = f(stack[top - 1]),
+                Op::Select => {
+                    top -= 2;
+                    let (c, a, b) = (stack[top - 1], stack[top], stack[top + 1]);
+                    stack[top - 1] = if c != 0.0 { a } else { b };
+                }
+                op => {
+                    top -= 1;
+                    let (a, b) = (stack[top - 1], stack[top]);
+                    stack[top - 1] = match op {
+                        Op::Add => a + b,
+                        Op::Sub => a - b,
+                        Op::Mul => a * b,
+                        Op::Div => a / b,
+                        Op::Rem => a.rem_euclid(b),
+                        Op::Pow => a.powf(b),
+                        Op::Less => (a < b) as u8 as f32,
+                        Op::Greater => (a > b) as u8 as f32,
+                        Op::LessEq => (a <= b) as u8 as f32,
+                        Op::GreaterEq => (a >= b) as u8 as f32,
+                        Op::Call2(f) => f(a, b),
+                        _ => unreachable!(),
+                    };
+                }
+            }
+        }
+        self.outputs.map(|i| vars[i])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token<'a> {
+    Number(f32),
+    Name(&'a str),
+    /// Operators and punctuation, with `<=` and `>=` as one token.
+    Symbol(&'a str),
+    /// A line break or `;`.
+    End,
+}
+
+/// Splits `source` into tokens, each with its line number.
+fn tokenize(source: &str) -> Result<Vec<(Token<'_>, usize)>, ScriptError> {
+    let mut tokens = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let error = |message: String| ScriptError {
+            line: i + 1,
+            message,
+        };
+        let line = line.split('#').next().unwrap_or_default();
+        let mut rest = line.trim_start();
+        while let Some(c) = rest.chars().next() {
+            let len = if c.is_ascii_digit() || c == '.' {
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                    .unwrap_or(rest.len());
+                let number = rest[..len]
+                    .parse()
+                    .map_err(|_| error(format!("invalid number `{}`", &rest[..len])))?;
+                tokens.push((Token::Number(number), i + 1));
+                len
+            } else if c.is_alphabetic() || c == '_' {
+                let len = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                tokens.push((Token::Name(&rest[..len]), i + 1));
+                len
+            } else if c == ';' {
+                tokens.push((Token::End, i + 1));
+                1
+            } else if rest.starts_with("<=") || rest.starts_with(">=") {
+                tokens.push((Token::Symbol(&rest[..2]), i + 1));
+                2
+            } else if "+-*/%^<>=(),".contains(c) {
+                tokens.push((Token::Symbol(&rest[..1]), i + 1));
+                1
+            } else {
+                return Err(error(format!("unexpected `{c}`")));
+            };
+            rest = rest[len..].trim_start();
+        }
+        tokens.push((Token::End, i + 1));
+    }
+    Ok(tokens)
+}
+
+/// Compiles tokens straight into a program for a stack machine.
+struct Parser<'a> {
+    tokens: Vec<(Token<'a>, usize)>,
+    pos: usize,
+    /// Names of the variable slots, in order.
+    vars: Vec<String>,
+    ops: Vec<Op>,
+    /// Values on the stack at this point of the program.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn line(&self) -> usize {
+        let last = self.tokens.last().map_or(1, |t| t.1);
+        self.tokens.get(self.pos).map_or(last, |t| t.1)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, ScriptError> {
+        Err(ScriptError {
+            line: self.line(),
+            message: message.into(),
+        })
+    }
+
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).map(|t| t.0)
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        let found = self.peek() == Some(Token::Symbol(symbol));
+        self.pos += found as usize;
+        found
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), ScriptError> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            self.error(format!("expected `{symbol}`"))
+        }
+    }
+
+    /// Adds `op`, which takes `pops` values off the stack and pushes one.
+    fn emit(&mut self, op: Op, pops: usize) -> Result<(), ScriptError> {
+        self.ops.push(op);
+        self.depth = self.depth + 1 - pops;
+        if self.depth > MAX_STACK {
+            return self.error("expression is too deeply nested");
+        }
+        Ok(())
+    }
+
+    fn program(&mut self) -> Result<(), ScriptError> {
+        while let Some(token) = self.peek() {
+            match token {
+                Token::End => self.pos += 1,
+                Token::Name(name) => {
+                    self.pos += 1;
+                    self.expect("=")?;
+                    if INPUTS.contains(&name) {
+                        return self.error(format!("`{name}` is an input"));
+                    }
+                    self.expression()?;
+                    let slot = match self.vars.iter().position(|v| v == name) {
+                        Some(slot) => slot,
+                        None if self.vars.len() < MAX_VARS => {
+                            self.vars.push(name.to_owned());
+                            self.vars.len() - 1
+                        }
+                        None => return self.error("too many variables"),
+                    };
+                    self.ops.push(Op::Store(slot));
+                    self.depth -= 1;
+                    if self.peek() != Some(Token::End) {
+                        return self.error("expected the end of the line");
+                    }
+                }
+                _ => return self.error("expected `name = expression`"),
+            }
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self) -> Result<(), ScriptError> {
+        self.sum()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol("<")) => Op::Less,
+                Some(Token::Symbol(">")) => Op::Greater,
+                Some(Token::Symbol("<=")) => Op::LessEq,
+                Some(Token::Symbol(">=")) => Op::GreaterEq,
+                _ => return Ok(()),
+            };
+            self.pos += 1;
+            self.sum()?;
+            self.emit(op, 2)?;
+        }
+    }
+
+    fn sum(&mut self) -> Result<(), ScriptError> {
+        self.product()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol("+")) => Op::Add,
+                Some(Token::Symbol("-")) => Op::Sub,
+                _ => return Ok(()),
+            };
+            self.pos += 1;
+            self.product()?;
+            self.emit(op, 2)?;
+        }
+    }
+
+    fn product(&mut self) -> Result<(), ScriptError> {
+        self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol("*")) => Op::Mul,
+                Some(Token::Symbol("/")) => Op::Div,
+                Some(Token::Symbol("%")) => Op::Rem,
+                _ => return Ok(()),
+            };
+            self.pos += 1;
+            self.unary()?;
+            self.emit(op, 2)?;
+        }
+    }
+
+    /// Negation binds looser than powers, so `-x^2` is `-(x^2)`.
+    fn unary(&mut self) -> Result<(), ScriptError> {
+        if self.eat("-") {
+            self.unary()?;
+            return self.emit(Op::Neg, 1);
+        }
+        self.atom()?;
+        if self.eat("^") {
+            self.unary()?;
+            self.emit(Op::Pow, 2)?;
+        }
+        Ok(())
+    }
+
+    fn atom(&mut self) -> Result<(), ScriptError> {
+        let token = self.peek();
+        self.pos += 1;
+        match token {
+            Some(Token::Number(n)) => self.emit(Op::Const(n), 0),
+            Some(Token::Symbol("(")) => {
+                self.expression()?;
+                self.expect(")")
+            }
+            Some(Token::Name(name)) if self.eat("(") => self.call(name),
+            Some(Token::Name(name)) => {
+                if let Some(slot) = self.vars.iter().position(|v| v == name) {
+                    self.emit(Op::Load(slot), 0)
+                } else if let Some(&(_, c)) = CONSTANTS.iter().find(|c| c.0 == name) {
+                    self.emit(Op::Const(c), 0)
+                } else {
+                    self.pos -= 1;
+                    self.error(format!("unknown variable `{name}`"))
+                }
+            }
+            _ => {
+                self.pos -= 1;
+                self.error("expected a value")
+            }
+        }
+    }
+
+    /// Arguments of a call to `name`, after its opening parenthesis.
+    fn call(&mut self, name: &str) -> Result<(), ScriptError> {
+        let mut args = 0;
+        if !self.eat(")") {
+            loop {
+                self.expression()?;
+                args += 1;
+                if self.eat(")") {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+        let (op, arity) = if let Some(&(_, f)) = FUNCTIONS_1.iter().find(|f| f.0 == name) {
+            (Op::Call1(f), 1)
+        } else if let Some(&(_, f)) = FUNCTIONS_2.iter().find(|f| f.0 == name) {
+            (Op::Call2(f), 2)
+        } else if name == "if" {
+            (Op::Select, 3)
+        } else {
+            return self.error(format!("unknown function `{name}`"));
+        };
+        if args != arity {
+            let plural = if arity == 1 { "" } else { "s" };
+            return self.error(format!("`{name}` takes {arity} argument{plural}"));
+        }
+        self.emit(op, arity)
+    }
+}
+
+/// The custom projection being edited, kept compiled for the renders.
+#[derive(Clone)]
+pub struct CustomProjection {
+    pub enabled: bool,
+    source: String,
+    compiled: Result<Arc<Script>, ScriptError>,
+}
+
+impl CustomProjection {
+    pub fn new(source: &str) -> Self {
+        let source = if source.trim().is_empty() {
+            EXAMPLE
+        } else {
+            source
+        };
+        Self {
+            enabled: false,
+            source: source.to_owned(),
+            compiled: Script::compile(source).map(Arc::new),
+        }
+    }
+
+    pub fn write(&self, table: &mut Table, prefix: &str) {
+        table.insert(format!("{prefix}enabled"), self.enabled);
+        table.insert(format!("{prefix}source"), self.source.as_str());
+    }
+
+    /// Reads the projection written by [`CustomProjection::write`], starting
+    /// from the example when there is no source.
+    pub fn read(table: &Table, prefix: &str) -> Self {
+        let get = |key: &str| table.get(&format!("{prefix}{key}"));
+        let mut custom = Self::new(get("source").and_then(Value::as_str).unwrap_or_default());
+        custom.enabled = get("enabled").and_then(Value::as_bool).unwrap_or(false);
+        custom
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Gives the source to edit in place, to be recompiled with
+    /// [`CustomProjection::compile`] when it has changed.
+    pub fn source_mut(&mut self) -> &mut String {
+        &mut self.source
+    }
+
+    pub fn compile(&mut self) {
+        self.compiled = Script::compile(&self.source).map(Arc::new);
+    }
+
+    pub fn error(&self) -> Option<&ScriptError> {
+        self.compiled.as_ref().err()
+    }
+
+    /// The script to render with, when it is enabled and compiles.
+    pub fn script(&self) -> Option<Arc<Script>> {
+        let script = self.compiled.as_ref().ok().filter(|_| self.enabled);
+        script.map(Arc::clone)
+    }
+}
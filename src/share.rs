@@ -0,0 +1,377 @@
+//! Parameters as one line of text, to be pasted into a chat and opened again
+//! with the same look.
+//!
+//! The text is the base64 of a fixed sequence of fields, each stored in as
+//! few bytes as its range needs. Angles, offsets and the like are rounded to
+//! steps finer than the sliders that edit them, so a pasted string looks the
+//! same but may differ in the last digits.
+
+use crate::{
+    base64,
+    color::{ColorAdjust, Stage},
+    effect::{Graticule, Pole, PoleCap, PoleMode, Vignette, VignetteMode},
+    error::AppError,
+    i18n::tr,
+    preset::Params,
+    projection::ProjectionKind,
+    sampler::{EdgeMode, Sampler},
+    scene::Scene,
+    script::CustomProjection,
+};
+
+/// Marks the text as parameters.
+const PREFIX: &str = "planet:";
+
+/// The first byte of the data, bumped whenever the fields change.
+const VERSION: u8 = 1;
+
+/// Everything a settings string carries: the parameters, how the source is
+/// sampled and the custom projection, when it is enabled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shared {
+    pub params: Params,
+    pub sampler: Sampler,
+    pub samples: u32,
+    pub script: Option<String>,
+}
+
+impl Shared {
+    pub fn new(params: Params, scene: &Scene) -> Self {
+        let custom = &scene.custom;
+        Self {
+            params,
+            sampler: scene.sampler,
+            samples: scene.samples,
+            script: custom.enabled.then(|| custom.source().to_owned()),
+        }
+    }
+
+    /// Sets the sampler and custom projection of `scene`, returning the
+    /// parameters. The script being edited is kept, disabled, when there is
+    /// none in the string.
+    pub fn apply(self, scene: &mut Scene) -> Params {
+        (scene.sampler, scene.samples) = (self.sampler, self.samples);
+        match self.script {
+            Some(source) => {
+                scene.custom = CustomProjection::new(&source);
+                scene.custom.enabled = true;
+            }
+            None => scene.custom.enabled = false,
+        }
+        self.params
+    }
+}
+
+pub fn encode(shared: &Shared) -> String {
+    let p = &shared.params;
+    let mut w = Writer(vec![VERSION]);
+    w.index(&ProjectionKind::ALL, p.kind);
+    let flags = [
+        p.inverse,
+        p.tunnel,
+        p.graticule.enabled,
+        p.graticule.preview_only,
+        p.pole.enabled,
+        shared.script.is_some(),
+    ];
+    w.u8(flags.iter().rev().fold(0, |bits, &f| bits << 1 | f as u8));
+    w.fixed(p.offset.0, OFFSET);
+    w.fixed(p.offset.1, OFFSET);
+    let (x, y, z) = p.rotation;
+    for angle in [x, y, z] {
+        w.fixed(angle, ANGLE);
+    }
+    w.unsigned(p.scale, SCALE);
+    w.u16(p.size.0.min(u16::MAX as u32) as u16);
+    w.u16(p.size.1.min(u16::MAX as u32) as u16);
+    match p.edge {
+        EdgeMode::Color(c) => {
+            w.u8(2);
+            w.color(c);
+        }
+        edge => w.u8(EdgeMode::ALL.iter().position(|e| *e == edge).unwrap_or(0) as u8),
+    }
+    w.unsigned(p.fov.0, ANGLE);
+    w.unsigned(p.fov.1, ANGLE);
+
+    let c = &p.color;
+    w.fixed(c.exposure, EXPOSURE);
+    for value in [c.contrast, c.saturation, c.temperature, c.tint] {
+        w.fixed(value, UNIT);
+    }
+    w.index(&Stage::ALL, c.stage);
+    w.index(&VignetteMode::ALL, p.vignette.mode);
+    w.unsigned(p.vignette.strength, UNIT);
+    w.unsigned(p.vignette.radius, UNIT);
+    w.unsigned(p.graticule.spacing, ANGLE);
+    w.color(p.graticule.color);
+    w.index(&Pole::ALL, p.pole.pole);
+    w.index(&PoleMode::ALL, p.pole.mode);
+    w.unsigned(p.pole.radius, ANGLE);
+
+    w.index(&Sampler::ALL, shared.sampler);
+    w.u8(shared.samples.min(u8::MAX as u32) as u8);
+    if let Some(script) = &shared.script {
+        let bytes = &script.as_bytes()[..script.len().min(u16::MAX as usize)];
+        w.u16(bytes.len() as u16);
+        w.0.extend_from_slice(bytes);
+    }
+    format!("{PREFIX}{}", base64::encode(&w.0))
+}
+
+/// Reads the text of [`encode`], ignoring the whitespace around it.
+pub fn decode(text: &str) -> Result<Shared, AppError> {
+    let invalid = || AppError::Invalid(tr("not a settings string").to_owned());
+    let data = text.trim().strip_prefix(PREFIX).ok_or_else(invalid)?;
+    let bytes = base64::decode(data).ok_or_else(invalid)?;
+    read(&bytes).ok_or_else(invalid)
+}
+
+fn read(bytes: &[u8]) -> Option<Shared> {
+    let mut r = Reader(bytes);
+    if r.u8()? != VERSION {
+        return None;
+    }
+    let kind = r.index(&ProjectionKind::ALL)?;
+    let flags = r.u8()?;
+    let flag = |i: u32| flags >> i & 1 == 1;
+    let offset = (r.fixed(OFFSET)?, r.fixed(OFFSET)?);
+    let rotation = (r.fixed(ANGLE)?, r.fixed(ANGLE)?, r.fixed(ANGLE)?);
+    // Clamped like the parameters of presets, so that crafted strings still
+    // render something.
+    let scale = r.unsigned(SCALE)?.max(SCALE);
+    let size = ((r.u16()? as u32).max(1), (r.u16()? as u32).max(1));
+    let edge = match *EdgeMode::ALL.get(r.u8()? as usize)? {
+        EdgeMode::Color(_) => EdgeMode::Color(r.color()?),
+        edge => edge,
+    };
+    let fov = (
+        r.unsigned(ANGLE)?.clamp(1.0, 360.0),
+        r.unsigned(ANGLE)?.clamp(1.0, 180.0),
+    );
+    let color = ColorAdjust {
+        exposure: r.fixed(EXPOSURE)?,
+        contrast: r.fixed(UNIT)?,
+        saturation: r.fixed(UNIT)?,
+        temperature: r.fixed(UNIT)?,
+        tint: r.fixed(UNIT)?,
+        stage: r.index(&Stage::ALL)?,
+    };
+    let vignette = Vignette {
+        mode: r.index(&VignetteMode::ALL)?,
+        strength: r.unsigned(UNIT)?,
+        radius: r.unsigned(UNIT)?,
+    };
+    let graticule = Graticule {
+        enabled: flag(2),
+        spacing: r.unsigned(ANGLE)?,
+        color: r.color()?,
+        preview_only: flag(3),
+    };
+    let pole = PoleCap {
+        enabled: flag(4),
+        pole: r.index(&Pole::ALL)?,
+        mode: r.index(&PoleMode::ALL)?,
+        radius: r.unsigned(ANGLE)?,
+    };
+    let params = Params {
+        kind,
+        inverse: flag(0),
+        offset,
+        rotation,
+        scale,
+        tunnel: flag(1),
+        size,
+        edge,
+        fov,
+        color,
+        vignette,
+        graticule,
+        pole,
+    };
+
+    let sampler = r.index(&Sampler::ALL)?;
+    let samples = (r.u8()? as u32).max(1);
+    let script = if flag(5) {
+        let len = r.u16()? as usize;
+        Some(String::from_utf8(r.take(len)?.to_vec()).ok()?)
+    } else {
+        None
+    };
+    r.0.is_empty().then_some(Shared {
+        params,
+        sampler,
+        samples,
+        script,
+    })
+}
+
+/// Steps of the fixed point fields.
+const OFFSET: f32 = 1e-4;
+const ANGLE: f32 = 1e-2;
+const SCALE: f32 = 1e-3;
+const EXPOSURE: f32 = 1e-3;
+const UNIT: f32 = 1e-4;
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    fn u16(&mut self, v: u16) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn index<T: PartialEq>(&mut self, all: &[T], v: T) {
+        self.u8(all.iter().position(|x| *x == v).unwrap_or(0) as u8);
+    }
+
+    /// `v` as a signed multiple of `step`, saturating at the range of `i16`.
+    fn fixed(&mut self, v: f32, step: f32) {
+        self.0
+            .extend_from_slice(&((v / step).round() as i16).to_le_bytes());
+    }
+
+    /// `v` as an unsigned multiple of `step`, saturating at the range of
+    /// `u16`.
+    fn unsigned(&mut self, v: f32, step: f32) {
+        self.u16((v / step).round() as u16);
+    }
+
+    fn color(&mut self, c: [f32; 4]) {
+        for v in c {
+            self.u8((v.clamp(0.0, 1.0) * 255.0).round() as u8);
+        }
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Option<&[u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let b = self.take(2)?;
+        Some(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn index<T: Copy>(&mut self, all: &[T]) -> Option<T> {
+        all.get(self.u8()? as usize).copied()
+    }
+
+    fn fixed(&mut self, step: f32) -> Option<f32> {
+        let b = self.take(2)?;
+        Some(i16::from_le_bytes([b[0], b[1]]) as f32 * step)
+    }
+
+    fn unsigned(&mut self, step: f32) -> Option<f32> {
+        Some(self.u16()? as f32 * step)
+    }
+
+    fn color(&mut self) -> Option<[f32; 4]> {
+        let b = self.take(4)?;
+        Some([b[0], b[1], b[2], b[3]].map(|v| v as f32 / 255.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preset::tests::edited;
+
+    fn shared(params: Params, script: Option<&str>) -> Shared {
+        Shared {
+            params,
+            sampler: Sampler::Lanczos3,
+            samples: 4,
+            script: script.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let all = [
+            shared(Params::default(), None),
+            shared(edited(), Some("lon = x * 2\nlat = y # 说的")),
+        ];
+        for shared in all {
+            let text = encode(&shared);
+            assert!(text.starts_with(PREFIX));
+            let decoded = decode(&format!("  {text}\n")).unwrap();
+            // Rounded once, the fields stay the same.
+            assert_eq!(encode(&decoded), text);
+            assert_eq!(decode(&text).unwrap(), decoded);
+
+            let (a, b) = (&shared.params, &decoded.params);
+            let exact = |p: &Params| {
+                (
+                    p.kind,
+                    p.inverse,
+                    p.tunnel,
+                    p.size,
+                    p.pole.pole,
+                    p.vignette.mode,
+                )
+            };
+            assert_eq!(exact(a), exact(b));
+            assert_eq!(a.graticule.enabled, b.graticule.enabled);
+            let (r, s) = (a.rotation, b.rotation);
+            for (x, y, step) in [
+                (r.0, s.0, ANGLE),
+                (r.1, s.1, ANGLE),
+                (r.2, s.2, ANGLE),
+                (a.scale, b.scale, SCALE),
+                (a.offset.1, b.offset.1, OFFSET),
+                (a.color.exposure, b.color.exposure, EXPOSURE),
+            ] {
+                assert!((x - y).abs() <= step / 2.0 + 1e-6, "{x} {y}");
+            }
+            assert_eq!(a.edge.name(), b.edge.name());
+            assert_eq!(
+                (decoded.sampler, decoded.samples, &decoded.script),
+                (shared.sampler, shared.samples, &shared.script)
+            );
+        }
+    }
+
+    #[test]
+    fn is_short() {
+        // About a fifth of the TOML it replaced.
+        assert!(encode(&shared(edited(), None)).len() < 100);
+    }
+
+    #[test]
+    fn clamps_crafted_values() {
+        let mut params = Params::default();
+        (params.size, params.scale, params.fov) = ((0, 0), 0.0, (0.0, 400.0));
+        let decoded = decode(&encode(&shared(params, None))).unwrap().params;
+        assert_eq!(decoded.size, (1, 1));
+        assert!(decoded.scale > 0.0);
+        assert_eq!(decoded.fov, (1.0, 180.0));
+    }
+
+    #[test]
+    fn rejects_other_text() {
+        let text = encode(&shared(Params::default(), None));
+        let bytes = base64::decode(&text[PREFIX.len()..]).unwrap();
+        let with = |bytes: &[u8]| format!("{PREFIX}{}", base64::encode(bytes));
+        assert!(decode("hello").is_err());
+        assert!(decode("planet:not base64!").is_err());
+        assert!(decode(&with(&bytes[..bytes.len() - 1])).is_err());
+        assert!(decode(&with(&[&bytes[..], &[0]].concat())).is_err());
+        assert!(decode(&with(&[&[VERSION + 1], &bytes[1..]].concat())).is_err());
+    }
+}
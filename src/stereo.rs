@@ -0,0 +1,189 @@
+//! Stereo panoramas, with the image of each eye stacked over-under or side
+//! by side in one file.
+
+use std::sync::Arc;
+
+use image::{imageops, Rgba32FImage};
+
+use crate::toml::{Table, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoLayout {
+    Mono,
+    /// The left eye on top.
+    OverUnder,
+    /// The left eye on the left.
+    SideBySide,
+}
+
+impl StereoLayout {
+    pub const ALL: [StereoLayout; 3] = [
+        StereoLayout::Mono,
+        StereoLayout::OverUnder,
+        StereoLayout::SideBySide,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StereoLayout::Mono => "Mono",
+            StereoLayout::OverUnder => "Over-Under",
+            StereoLayout::SideBySide => "Side by Side",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        StereoLayout::ALL.into_iter().find(|l| l.name() == name)
+    }
+
+    /// Guesses the layout from the shape of the image. Two 360° eyes over
+    /// each other are square and side by side are four times as wide as high,
+    /// and two VR180 eyes over each other are twice as high as wide. VR180
+    /// side by side looks like a mono panorama and has to be chosen.
+    pub fn detect(width: u32, height: u32) -> Self {
+        let aspect = width as f32 / height as f32;
+        let near = |target: f32| (aspect / target - 1.0).abs() < 0.01;
+        if near(1.0) || near(0.5) {
+            StereoLayout::OverUnder
+        } else if near(4.0) {
+            StereoLayout::SideBySide
+        } else {
+            StereoLayout::Mono
+        }
+    }
+
+    /// Cuts `img` into the images of the left and right eye.
+    pub fn split(self, img: &Rgba32FImage) -> Option<[Rgba32FImage; 2]> {
+        let (width, height) = img.dimensions();
+        let eye = |x, y, w, h| imageops::crop_imm(img, x, y, w, h).to_image();
+        match self {
+            StereoLayout::Mono => None,
+            StereoLayout::OverUnder => {
+                let h = height / 2;
+                Some([eye(0, 0, width, h), eye(0, h, width, h)])
+            }
+            StereoLayout::SideBySide => {
+                let w = width / 2;
+                Some([eye(0, 0, w, height), eye(w, 0, w, height)])
+            }
+        }
+    }
+
+    /// Size of the image [`StereoLayout::combine`] makes from eyes of `size`.
+    pub fn size(self, (width, height): (u32, u32)) -> (u32, u32) {
+        match self {
+            StereoLayout::Mono => (width, height),
+            StereoLayout::OverUnder => (width, height * 2),
+            StereoLayout::SideBySide => (width * 2, height),
+        }
+    }
+
+    /// Puts the images of both eyes back into one, the inverse of
+    /// [`StereoLayout::split`]. Mono keeps the left eye.
+    pub fn combine(self, [left, right]: [&Rgba32FImage; 2]) -> Rgba32FImage {
+        let (width, height) = left.dimensions();
+        let (mut out, x, y) = match self {
+            StereoLayout::Mono => return left.clone(),
+            StereoLayout::OverUnder => (Rgba32FImage::new(width, height * 2), 0, height),
+            StereoLayout::SideBySide => (Rgba32FImage::new(width * 2, height), width, 0),
+        };
+        imageops::replace(&mut out, left, 0, 0);
+        imageops::replace(&mut out, right, x as i64, y as i64);
+        out
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+impl Eye {
+    pub const ALL: [Eye; 2] = [Eye::Left, Eye::Right];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Eye::Left => "Left",
+            Eye::Right => "Right",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Eye::ALL.into_iter().find(|e| e.name() == name)
+    }
+}
+
+/// How a stereo source is shown, with the eyes of the last one kept.
+#[derive(Clone)]
+pub struct Stereo {
+    pub layout: StereoLayout,
+    /// The eye shown, and saved unless both are.
+    pub eye: Eye,
+    /// Saves both eyes, in the layout of the source.
+    pub pair: bool,
+    cache: Option<(Arc<Rgba32FImage>, StereoLayout, [Arc<Rgba32FImage>; 2])>,
+}
+
+impl Default for Stereo {
+    fn default() -> Self {
+        Self {
+            layout: StereoLayout::Mono,
+            eye: Eye::Left,
+            pair: false,
+            cache: None,
+        }
+    }
+}
+
+impl Stereo {
+    pub fn write(&self, table: &mut Table, prefix: &str) {
+        table.insert(format!("{prefix}layout"), self.layout.name());
+        table.insert(format!("{prefix}eye"), self.eye.name());
+        table.insert(format!("{prefix}pair"), self.pair);
+    }
+
+    /// Reads the settings written by [`Stereo::write`], keeping the defaults
+    /// for anything that is missing or malformed.
+    pub fn read(table: &Table, prefix: &str) -> Self {
+        let get = |key: &str| table.get(&format!("{prefix}{key}"));
+        let mut stereo = Stereo::default();
+        if let Some(layout) = get("layout").and_then(Value::as_str) {
+            stereo.layout = StereoLayout::from_name(layout).unwrap_or(stereo.layout);
+        }
+        if let Some(eye) = get("eye").and_then(Value::as_str) {
+            stereo.eye = Eye::from_name(eye).unwrap_or(stereo.eye);
+        }
+        if let Some(pair) = get("pair").and_then(Value::as_bool) {
+            stereo.pair = pair;
+        }
+        stereo
+    }
+
+    /// The images of both eyes of `img`, or `None` for mono sources.
+    pub fn eyes(&mut self, img: &Arc<Rgba32FImage>) -> Option<[Arc<Rgba32FImage>; 2]> {
+        if let Some((source, layout, eyes)) = &self.cache {
+            if Arc::ptr_eq(source, img) && *layout == self.layout {
+                return Some(eyes.clone());
+            }
+        }
+        let eyes = self.layout.split(img)?.map(Arc::new);
+        self.cache = Some((Arc::clone(img), self.layout, eyes.clone()));
+        Some(eyes)
+    }
+
+    /// The image of the chosen eye, or `img` itself for mono sources.
+    pub fn eye(&mut self, img: &Arc<Rgba32FImage>) -> Arc<Rgba32FImage> {
+        match self.eyes(img) {
+            Some([left, right]) => match self.eye {
+                Eye::Left => left,
+                Eye::Right => right,
+            },
+            None => Arc::clone(img),
+        }
+    }
+
+    /// Whether "Save Image" writes both eyes.
+    pub fn is_pair(&self) -> bool {
+        self.pair && self.layout != StereoLayout::Mono
+    }
+}
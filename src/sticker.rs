@@ -0,0 +1,156 @@
+//! Small images pinned to the sphere, drawn into the panorama so that they
+//! are warped along with it.
+
+use std::{io, sync::Arc};
+
+use image::Rgba32FImage;
+use nalgebra::vector;
+use rayon::prelude::*;
+
+use crate::{
+    icc, overlay, project, projection,
+    sampler::{self, EdgeMode, Sampler},
+    toml::{Table, Value},
+};
+
+#[derive(Clone)]
+pub struct Sticker {
+    pub name: String,
+    pub image: Arc<Rgba32FImage>,
+    pub visible: bool,
+    /// Degrees to the right of the center of the panorama.
+    pub longitude: f32,
+    /// Degrees above the horizon.
+    pub latitude: f32,
+    /// Angle the height of the sticker spans, in degrees.
+    pub size: f32,
+}
+
+impl Sticker {
+    pub fn new(name: String, image: Arc<Rgba32FImage>) -> Self {
+        Self {
+            name,
+            image,
+            visible: true,
+            longitude: 0.0,
+            // Just below the horizon, standing on the ground.
+            latitude: -20.0,
+            size: 20.0,
+        }
+    }
+
+    /// Writes the sticker into `table`, with its image embedded.
+    pub fn write(&self, table: &mut Table, prefix: &str) -> io::Result<()> {
+        table.insert(format!("{prefix}name"), self.name.as_str());
+        project::write_image(table, &format!("{prefix}image"), &self.image)?;
+        table.insert(format!("{prefix}visible"), self.visible);
+        table.insert(format!("{prefix}longitude"), self.longitude);
+        table.insert(format!("{prefix}latitude"), self.latitude);
+        table.insert(format!("{prefix}size"), self.size);
+        Ok(())
+    }
+
+    /// Reads the sticker written by [`Sticker::write`], or `None` when there
+    /// is no image to make it from.
+    pub fn read(table: &Table, prefix: &str) -> Option<Self> {
+        let get = |key: &str| table.get(&format!("{prefix}{key}"));
+        let name = get("name").and_then(Value::as_str).unwrap_or_default();
+        let image = project::read_image(table, &format!("{prefix}image"))?;
+        let mut sticker = Sticker::new(name.to_owned(), image);
+        if let Some(visible) = get("visible").and_then(Value::as_bool) {
+            sticker.visible = visible;
+        }
+        if let Some(longitude) = get("longitude").and_then(Value::as_f32) {
+            sticker.longitude = longitude;
+        }
+        if let Some(latitude) = get("latitude").and_then(Value::as_f32) {
+            sticker.latitude = latitude;
+        }
+        if let Some(size) = get("size").and_then(Value::as_f32) {
+            sticker.size = size;
+        }
+        Some(sticker)
+    }
+
+    /// Draws the sticker onto an equirectangular panorama, as seen on a plane
+    /// touching the sphere at its center, upright towards the zenith. The
+    /// sticker is decoded into linear light for `linear` panoramas.
+    fn draw(&self, panorama: &mut Rgba32FImage, linear: bool) {
+        let (lon, lat) = (self.longitude.to_radians(), self.latitude.to_radians());
+        let center = vector![lon.sin() * lat.cos(), lon.cos() * lat.cos(), lat.sin()];
+        let east = vector![lon.cos(), -lon.sin(), 0.0];
+        let north = east.cross(&center);
+        let aspect = self.image.width() as f32 / self.image.height() as f32;
+        let half = (self.size.to_radians() / 2.0).tan();
+        let (sw, sh) = (self.image.width() as f32, self.image.height() as f32);
+
+        // Only rows within the sticker's angular radius can be covered.
+        let (w, h) = panorama.dimensions();
+        let radius = (half * aspect.hypot(1.0)).atan().to_degrees();
+        let row = |lat: f32| ((0.5 - lat / 180.0) * h as f32).clamp(0.0, h as f32) as usize;
+        let rows = row(self.latitude + radius)..(row(self.latitude - radius) + 1).min(h as usize);
+        let width = w as usize * 4;
+        panorama.as_mut()[rows.start * width..rows.end * width]
+            .par_chunks_exact_mut(width)
+            .enumerate()
+            .for_each(|(i, line)| {
+                let y = rows.start + i;
+                for (x, pixel) in line.chunks_exact_mut(4).enumerate() {
+                    let uv = vector![(x as f32 + 0.5) / w as f32, (y as f32 + 0.5) / h as f32];
+                    let d = projection::equirect_to_sphere(uv);
+                    let depth = d.dot(&center);
+                    if depth <= 0.0 {
+                        continue;
+                    }
+                    let (pe, pn) = (d.dot(&east) / depth, d.dot(&north) / depth);
+                    let sx = (pe / (half * aspect) + 1.0) / 2.0 * sw - 0.5;
+                    let sy = (1.0 - pn / half) / 2.0 * sh - 0.5;
+                    let q = Sampler::Bilinear.sample(&self.image, sx, sy, EdgeMode::Transparent);
+                    if q.w > 0.0 {
+                        let mut above = sampler::unpremultiply(q).0;
+                        if linear {
+                            for c in &mut above[..3] {
+                                *c = icc::srgb_to_linear(*c);
+                            }
+                        }
+                        let below: [f32; 4] = (&*pixel).try_into().unwrap();
+                        pixel.copy_from_slice(&overlay::over(below, above));
+                    }
+                }
+            });
+    }
+}
+
+/// The stickers of the scene, with the panorama they were last drawn onto.
+#[derive(Clone, Default)]
+pub struct Layers {
+    pub stickers: Vec<Sticker>,
+    cache: Option<(Arc<Rgba32FImage>, Arc<Rgba32FImage>)>,
+}
+
+impl Layers {
+    /// Must be called after the stickers are edited.
+    pub fn changed(&mut self) {
+        self.cache = None;
+    }
+
+    /// The panorama with the visible stickers drawn onto it, which is only
+    /// redrawn when the panorama or the stickers change.
+    pub fn apply(&mut self, panorama: &Arc<Rgba32FImage>, linear: bool) -> Arc<Rgba32FImage> {
+        if !self.stickers.iter().any(|s| s.visible) {
+            return Arc::clone(panorama);
+        }
+        if let Some((source, out)) = &self.cache {
+            if Arc::ptr_eq(source, panorama) {
+                return Arc::clone(out);
+            }
+        }
+        let mut out = (**panorama).clone();
+        for sticker in self.stickers.iter().filter(|s| s.visible) {
+            sticker.draw(&mut out, linear);
+        }
+        let out = Arc::new(out);
+        self.cache = Some((Arc::clone(panorama), Arc::clone(&out)));
+        out
+    }
+}
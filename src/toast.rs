@@ -0,0 +1,96 @@
+//! Error notifications in a corner of the window, which unlike a dialog
+//! neither block the interface nor the thread that failed.
+
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    time::{Duration, Instant},
+};
+
+use egui::{vec2, Align2, Color32, Context, Frame, Id, Order, RichText};
+
+use crate::{error::AppError, i18n::tr};
+
+/// How long a toast stays unless it is dismissed earlier.
+const LIFETIME: Duration = Duration::from_secs(8);
+
+/// Sends toasts to [`Toasts`], from any thread.
+#[derive(Clone)]
+pub struct Notifier {
+    sender: Sender<String>,
+    ctx: Context,
+}
+
+impl Notifier {
+    /// Tells the user that `action` failed.
+    pub fn error(&self, action: &'static str, e: impl Into<AppError>) {
+        let text = format!("{}: {}", tr(action), e.into());
+        if self.sender.send(text).is_ok() {
+            self.ctx.request_repaint();
+        }
+    }
+}
+
+pub struct Toasts {
+    sender: Sender<String>,
+    receiver: Receiver<String>,
+    shown: Vec<(String, Instant)>,
+}
+
+impl Default for Toasts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Toasts {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver,
+            shown: vec![],
+        }
+    }
+
+    pub fn notifier(&self, ctx: &Context) -> Notifier {
+        Notifier {
+            sender: self.sender.clone(),
+            ctx: ctx.clone(),
+        }
+    }
+
+    /// Shows the toasts received so far over the bottom right corner, the
+    /// latest at the bottom.
+    pub fn show(&mut self, ctx: &Context) {
+        let now = Instant::now();
+        self.shown
+            .extend(self.receiver.try_iter().map(|text| (text, now)));
+        self.shown.retain(|(_, since)| now - *since < LIFETIME);
+        let Some(oldest) = self.shown.first().map(|(_, since)| *since) else {
+            return;
+        };
+        ctx.request_repaint_after(LIFETIME - (now - oldest));
+
+        let mut dismissed = None;
+        egui::Area::new(Id::new("toasts"))
+            .anchor(Align2::RIGHT_BOTTOM, vec2(-12.0, -12.0))
+            .order(Order::Foreground)
+            .show(ctx, |ui| {
+                for (i, (text, _)) in self.shown.iter().enumerate() {
+                    Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.set_max_width(360.0);
+                        ui.horizontal(|ui| {
+                            ui.label(RichText::new("⚠").color(Color32::LIGHT_RED));
+                            ui.label(text);
+                            if ui.small_button("×").clicked() {
+                                dismissed = Some(i);
+                            }
+                        });
+                    });
+                }
+            });
+        if let Some(i) = dismissed {
+            self.shown.remove(i);
+        }
+    }
+}
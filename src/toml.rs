@@ -0,0 +1,322 @@
+//! A small subset of TOML: `key = value` pairs under optional `[section]`
+//! headers, where values are booleans, numbers, strings or single-line arrays
+//! of those.
+
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            Value::Number(n) => Some(*n as f32),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 && *n <= u32::MAX as f64 => {
+                Some(*n as u32)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(n: f32) -> Self {
+        // Go through the shortest decimal form so that 0.4 isn't written as
+        // 0.4000000059604645.
+        Value::Number(f64::from_str(&n.to_string()).unwrap_or(n as f64))
+    }
+}
+
+impl From<u32> for Value {
+    fn from(n: u32) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl<T: Into<Value>, const N: usize> From<[T; N]> for Value {
+    fn from(a: [T; N]) -> Self {
+        Value::Array(a.into_iter().map(Into::into).collect())
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Number(n) => write!(f, "{n}"),
+            Value::String(s) => {
+                f.write_str("\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\r' => f.write_str("\\r")?,
+                        '\t' => f.write_str("\\t")?,
+                        c => write!(f, "{c}")?,
+                    }
+                }
+                f.write_str("\"")
+            }
+            Value::Array(a) => {
+                f.write_str("[")?;
+                for (i, v) in a.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{v}")?;
+                }
+                f.write_str("]")
+            }
+        }
+    }
+}
+
+/// Key-value pairs, where keys inside a section are stored as
+/// `section.key`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Table(BTreeMap<String, Value>);
+
+impl Table {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<Value>) {
+        self.0.insert(key.into(), value.into());
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.0.get(key)
+    }
+
+    pub fn retain(&mut self, f: impl FnMut(&String, &mut Value) -> bool) {
+        self.0.retain(f);
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (top, nested): (Vec<_>, Vec<_>) =
+            self.0.iter().partition(|(key, _)| !key.contains('.'));
+        for (key, value) in top {
+            writeln!(f, "{key} = {value}")?;
+        }
+        let mut section = None;
+        for (key, value) in nested {
+            let (name, key) = key.split_once('.').unwrap();
+            if section != Some(name) {
+                writeln!(f, "\n[{name}]")?;
+                section = Some(name);
+            }
+            writeln!(f, "{key} = {value}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl FromStr for Table {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut table = Table::new();
+        let mut section = String::new();
+        for (i, line) in s.lines().enumerate() {
+            let error = |message: &str| ParseError {
+                line: i + 1,
+                message: message.to_owned(),
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                let name = name
+                    .strip_suffix(']')
+                    .ok_or_else(|| error("unclosed section"))?;
+                section = format!("{}.", name.trim());
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected `key = value`"))?;
+            let (value, rest) = parse_value(value.trim_start()).map_err(error)?;
+            let rest = rest.trim_start();
+            if !(rest.is_empty() || rest.starts_with('#')) {
+                return Err(error("unexpected characters after value"));
+            }
+            table.insert(format!("{section}{}", key.trim()), value);
+        }
+        Ok(table)
+    }
+}
+
+/// Parses one value off the front of `s`, returning it with the remainder.
+fn parse_value(s: &str) -> Result<(Value, &str), &'static str> {
+    if let Some(mut rest) = s.strip_prefix('[') {
+        let mut items = vec![];
+        loop {
+            rest = rest.trim_start();
+            if let Some(rest) = rest.strip_prefix(']') {
+                return Ok((Value::Array(items), rest));
+            }
+            let (item, next) = parse_value(rest)?;
+            items.push(item);
+            let next = next.trim_start();
+            rest = match next.strip_prefix(',') {
+                Some(next) => next,
+                None if next.starts_with(']') => next,
+                None => return Err("expected `,` or `]` in array"),
+            };
+        }
+    }
+    if let Some(rest) = s.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((Value::String(out), &rest[i + 1..])),
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('"') => out.push('"'),
+                    Some('\\') => out.push('\\'),
+                    Some('n') => out.push('\n'),
+                    Some('r') => out.push('\r'),
+                    Some('t') => out.push('\t'),
+                    _ => return Err("invalid escape in string"),
+                },
+                c => out.push(c),
+            }
+        }
+        return Err("unclosed string");
+    }
+
+    let end = s
+        .find(|c: char| c == ',' || c == ']' || c == '#' || c.is_whitespace())
+        .unwrap_or(s.len());
+    let (token, rest) = s.split_at(end);
+    let value = match token {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => Value::Number(token.parse().map_err(|_| "invalid value")?),
+    };
+    Ok((value, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut table = Table::new();
+        table.insert("enabled", true);
+        table.insert("scale", 0.4f32);
+        table.insert("size", [600u32, 400]);
+        table.insert("name", "tunnel \"view\"\\\n\t说的道理");
+        table.insert("export.format", "PNG");
+        table.insert("export.quality", 90u32);
+        let text = table.to_string();
+        assert!(text.contains("scale = 0.4\n"), "{text}");
+        assert!(text.contains("\n[export]\n"), "{text}");
+        assert_eq!(text.parse::<Table>(), Ok(table));
+    }
+
+    #[test]
+    fn parses_comments_and_nested_arrays() {
+        let table: Table =
+            "# a preset\nrotation = [1, -2.5, 3e2] # degrees\n\n[a]\nb = [[true], []]\n"
+                .parse()
+                .unwrap();
+        let rotation = table.get("rotation").and_then(Value::as_array).unwrap();
+        let rotation: Vec<_> = rotation.iter().filter_map(Value::as_f32).collect();
+        assert_eq!(rotation, [1.0, -2.5, 300.0]);
+        let nested = Value::Array(vec![Value::Array(vec![true.into()]), Value::Array(vec![])]);
+        assert_eq!(table.get("a.b"), Some(&nested));
+    }
+
+    #[test]
+    fn reports_the_line_of_errors() {
+        let error = |text: &str| text.parse::<Table>().unwrap_err();
+        assert_eq!(error("a = 1\nb = \"open").line, 2);
+        assert_eq!(
+            error("a = 1 2").message,
+            "unexpected characters after value"
+        );
+        assert_eq!(error("\n\n[section").message, "unclosed section");
+        assert_eq!(error("a = [1 2]").message, "expected `,` or `]` in array");
+        assert_eq!(error("a = \"\\q\"").message, "invalid escape in string");
+        assert_eq!(error("just text").message, "expected `key = value`");
+    }
+
+    #[test]
+    fn converts_values() {
+        assert_eq!(Value::from(3u32).as_u32(), Some(3));
+        assert_eq!(Value::Number(-1.0).as_u32(), None);
+        assert_eq!(Value::Number(1.5).as_u32(), None);
+        assert_eq!(Value::from("x").as_bool(), None);
+    }
+}
@@ -0,0 +1,71 @@
+use egui::Vec2;
+use nalgebra::{vector, Matrix3, Rotation3};
+
+use crate::{
+    preset::{self, Params},
+    projection::ProjectionKind,
+};
+
+/// A rectilinear camera looking around from the inside of the sphere.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewer {
+    /// Degrees to the right of the center of the panorama.
+    pub yaw: f32,
+    /// Degrees above the horizon.
+    pub pitch: f32,
+    /// Field of view across the shorter side of the output, in degrees.
+    pub fov: f32,
+}
+
+impl Default for Viewer {
+    fn default() -> Self {
+        Self {
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 90.0,
+        }
+    }
+}
+
+impl Viewer {
+    /// Rotation taking the projection's axis to the viewing direction, with
+    /// the horizon level.
+    pub fn rotation(&self) -> Rotation3<f32> {
+        let (yaw, pitch) = (self.yaw.to_radians(), self.pitch.to_radians());
+        let forward = vector![
+            yaw.sin() * pitch.cos(),
+            yaw.cos() * pitch.cos(),
+            pitch.sin()
+        ];
+        let right = vector![yaw.cos(), -yaw.sin(), 0.0];
+        let down = forward.cross(&right);
+        Rotation3::from_matrix_unchecked(Matrix3::from_columns(&[right, down, forward]))
+    }
+
+    /// Parameters rendering the view, with the output size, edges and colors
+    /// of `base`.
+    pub fn params(&self, base: &Params) -> Params {
+        Params {
+            kind: ProjectionKind::Rectilinear,
+            inverse: false,
+            offset: (0.0, 0.0),
+            rotation: preset::rotation_to_degrees(self.rotation()),
+            // The projection's radius is a tenth of the shorter side at scale 1.
+            scale: 5.0 / (self.fov.to_radians() / 2.0).tan(),
+            tunnel: false,
+            ..*base
+        }
+    }
+
+    /// Turns the camera so that the scene follows a drag of `delta` on an
+    /// area whose shorter side is `extent`.
+    pub fn look(&mut self, delta: Vec2, extent: f32) {
+        let degrees = self.fov / extent;
+        self.yaw = (self.yaw - delta.x * degrees + 540.0).rem_euclid(360.0) - 180.0;
+        self.pitch = (self.pitch + delta.y * degrees).clamp(-90.0, 90.0);
+    }
+
+    pub fn zoom(&mut self, scroll: f32) {
+        self.fov = (self.fov * (-scroll * 0.002).exp()).clamp(10.0, 150.0);
+    }
+}
@@ -0,0 +1,143 @@
+//! More outputs of the same source next to the main one, each with its own
+//! projection and parameters, rendered on threads of their own and shown in
+//! windows.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use egui::{ComboBox, Context, Image, PointerButton, Sense, Slider, Vec2};
+
+use crate::{
+    gizmo,
+    i18n::{tr, trf},
+    params::AppParams,
+    preset::Params,
+    projection::ProjectionKind,
+    render::Renderer,
+};
+
+/// Widest a view is shown in its window, in points.
+const MAX_WIDTH: f32 = 360.0;
+
+/// An output of its own, with the state of its renders.
+pub struct OutputView {
+    /// Shown in the title, counting the main output as view 1.
+    pub number: usize,
+    pub params: AppParams,
+    pub renderer: Renderer,
+    open: bool,
+}
+
+#[derive(Default)]
+pub struct Views {
+    views: Vec<OutputView>,
+    next: usize,
+}
+
+impl Views {
+    /// Adds a view starting from `params`, rendered on the next frame.
+    pub fn add(&mut self, params: Params) {
+        let mut params = AppParams::new(params);
+        params.refresh();
+        self.next += 1;
+        self.views.push(OutputView {
+            number: self.next + 1,
+            params,
+            renderer: Renderer::new(),
+            open: true,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.views.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &OutputView> {
+        self.views.iter()
+    }
+
+    /// The views to render again, given whether state they share with the
+    /// main output, like the source or the sampler, changed.
+    pub fn due(&mut self, now: Instant, shared: bool) -> Vec<&mut OutputView> {
+        self.views
+            .iter_mut()
+            .filter_map(|view| {
+                view.params += shared;
+                view.params.update(now);
+                view.params.due(now).map(|_| view)
+            })
+            .collect()
+    }
+
+    /// How long until the next view is due.
+    pub fn wait(&self, now: Instant) -> Option<Duration> {
+        self.views.iter().filter_map(|v| v.params.wait(now)).min()
+    }
+
+    /// Shows a window for each view. "Swap with Main" trades the parameters
+    /// of a view with `main`, to edit them with all the controls.
+    pub fn show(&mut self, ctx: &Context, main: &mut Params) {
+        for view in &mut self.views {
+            let title = trf("View {}", &[&view.number]);
+            egui::Window::new(title)
+                .id(egui::Id::new(("view", view.number)))
+                .open(&mut view.open)
+                .default_width(MAX_WIDTH)
+                .show(ctx, |ui| {
+                    let params = &mut view.params;
+                    ui.horizontal(|ui| {
+                        ui.add_enabled_ui(!params.inverse, |ui| {
+                            ComboBox::from_id_source(("view kind", view.number))
+                                .selected_text(tr(params.kind.name()))
+                                .show_ui(ui, |ui| {
+                                    for k in ProjectionKind::ALL {
+                                        let name = tr(k.name());
+                                        ui.selectable_value(&mut params.kind, k, name);
+                                    }
+                                });
+                        });
+                        ui.checkbox(&mut params.tunnel, tr("Tunnel"));
+                        if view.renderer.processing() {
+                            ui.spinner();
+                        }
+                    });
+                    ui.add(Slider::new(&mut params.scale, 0.1..=5.0).text(tr("Scale")));
+                    if ui.button(tr("Swap with Main")).clicked() {
+                        std::mem::swap(main, &mut **params);
+                    }
+                    let Some(tex) = view.renderer.texture() else {
+                        return;
+                    };
+                    let width = ui.available_width().min(MAX_WIDTH);
+                    let image = Image::from_texture(tex)
+                        .max_width(width)
+                        .sense(Sense::drag());
+                    let response = ui.add(image);
+                    let delta = response.drag_delta();
+                    if response.dragged_by(PointerButton::Primary) && delta != Vec2::ZERO {
+                        let extent = response.rect.size().min_elem();
+                        let r = params.view_rotation() * gizmo::drag_rotation(delta, extent);
+                        params.set_view_rotation(r);
+                        params.refresh();
+                    }
+                });
+        }
+        for view in self.views.iter_mut().filter(|view| !view.open) {
+            view.renderer.free(ctx);
+        }
+        self.views.retain(|view| view.open);
+    }
+}
+
+/// Where view `number` is saved when the main output goes to `path`, as
+/// `name-2.png` next to `name.png`.
+pub fn numbered(path: &Path, number: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}-{number}.{}", ext.to_string_lossy()),
+        None => format!("{stem}-{number}"),
+    };
+    path.with_file_name(name)
+}
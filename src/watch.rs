@@ -0,0 +1,57 @@
+//! Notices when the loaded image is written again, by polling its
+//! modification time.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How often the file is looked at.
+pub const INTERVAL: Duration = Duration::from_millis(500);
+
+/// Modification time and length, which together change on every write.
+type Stamp = (SystemTime, u64);
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[derive(Default)]
+pub struct Watcher {
+    path: Option<PathBuf>,
+    /// The file as it was last loaded.
+    loaded: Option<Stamp>,
+    /// The file as it was at the previous poll.
+    last: Option<Stamp>,
+    checked: Option<Instant>,
+}
+
+impl Watcher {
+    /// Whether `path` has changed since it was last reported. A change is
+    /// only reported once the file has stayed the same for a poll, so that
+    /// a file still being written is not read half way.
+    pub fn poll(&mut self, path: &Path) -> bool {
+        if self.path.as_deref() != Some(path) {
+            self.path = Some(path.to_owned());
+            self.loaded = stamp(path);
+            self.last = self.loaded;
+            self.checked = Some(Instant::now());
+            return false;
+        }
+        if self.checked.is_some_and(|t| t.elapsed() < INTERVAL) {
+            return false;
+        }
+        self.checked = Some(Instant::now());
+        let current = stamp(path);
+        let settled = current.is_some() && current == self.last;
+        self.last = current;
+        if settled && current != self.loaded {
+            self.loaded = current;
+            true
+        } else {
+            false
+        }
+    }
+}
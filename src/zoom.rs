@@ -0,0 +1,87 @@
+use egui::{PointerButton, Response, ScrollArea, Ui, Vec2};
+
+/// The magnification of the preview, which fits the available space until
+/// it is zoomed in.
+pub struct Zoom {
+    /// Points per output pixel, or `None` to fit.
+    scale: Option<f32>,
+    offset: Vec2,
+}
+
+impl Default for Zoom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Zoom {
+    pub fn new() -> Self {
+        Self {
+            scale: None,
+            offset: Vec2::ZERO,
+        }
+    }
+
+    pub fn fit(&mut self) {
+        self.scale = None;
+    }
+
+    /// Shows one output pixel per screen pixel.
+    pub fn actual_size(&mut self, ui: &Ui) {
+        self.scale = Some(1.0 / ui.ctx().pixels_per_point());
+    }
+
+    /// Magnification as a fraction of the actual size, once it has been shown.
+    pub fn percent(&self, ui: &Ui) -> Option<f32> {
+        Some(self.scale? * ui.ctx().pixels_per_point() * 100.0)
+    }
+
+    /// Shows the preview added by `add`, given the size to draw each of its
+    /// `columns` images of `image_size` pixels at. Ctrl+scroll zooms around
+    /// the pointer and a right drag pans.
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        image_size: Vec2,
+        columns: f32,
+        add: impl FnOnce(&mut Ui, Vec2) -> Response,
+    ) -> Response {
+        let available = ui.available_size();
+        let content = image_size * Vec2::new(columns, 1.0);
+        let fit = (available / content).min_elem().min(1.0);
+        let Some(scale) = self.scale.filter(|&s| s > fit) else {
+            self.scale = None;
+            let response = add(ui, image_size * fit);
+            self.zoom(ui, &response, fit, fit, response.rect.min);
+            return response;
+        };
+        let output = ScrollArea::both()
+            .id_source("preview")
+            .enable_scrolling(false)
+            .scroll_offset(self.offset)
+            .max_width(available.x)
+            .max_height(available.y)
+            .show(ui, |ui| add(ui, image_size * scale));
+        let response = output.inner;
+        self.offset = output.state.offset;
+        if response.dragged_by(PointerButton::Secondary) {
+            self.offset -= response.drag_delta();
+        }
+        self.zoom(ui, &response, scale, fit, output.inner_rect.min);
+        response
+    }
+
+    /// Zooms with ctrl+scroll over `response`, keeping the point under the
+    /// pointer still. `origin` is the top left corner of the visible area.
+    fn zoom(&mut self, ui: &Ui, response: &Response, scale: f32, fit: f32, origin: egui::Pos2) {
+        let factor = ui.input(|i| i.zoom_delta());
+        let Some(pointer) = response.hover_pos().filter(|_| factor != 1.0) else {
+            return;
+        };
+        let max = 16.0 / ui.ctx().pixels_per_point();
+        let new = (scale * factor).clamp(fit, max.max(fit));
+        let pointer = pointer - origin;
+        self.offset = (self.offset + pointer) * (new / scale) - pointer;
+        self.scale = Some(new);
+    }
+}